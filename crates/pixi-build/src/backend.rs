@@ -0,0 +1,1146 @@
+//! The plumbing that is shared by all build backends.
+//!
+//! A backend only describes what is specific to its build tool, e.g. how the
+//! build script is generated and which build tools are required, by
+//! implementing [`BuildBackend`]. Reading the manifest and the configuration,
+//! resolving the channels and platforms, solving the environments, building
+//! the package and post-processing it is implemented once by
+//! [`GenericBackend`], which implements [`Protocol`] for any
+//! [`BuildBackend`].
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::Utc;
+use itertools::Itertools;
+use miette::{Context, IntoDiagnostic};
+use pixi_build_types::{
+    procedures::{
+        conda_build::{CondaBuildParams, CondaBuildResult, CondaBuiltPackage},
+        conda_metadata::CondaMetadataResult,
+        initialize::{InitializeParams, InitializeResult},
+    },
+    BackendCapabilities, CondaPackageMetadata, FrontendCapabilities, PlatformAndVirtualPackages,
+};
+use pixi_manifest::{CondaDependencies, Dependencies, Manifest, SpecType};
+use rattler_build::{
+    build::run_build,
+    console_utils::LoggingOutputHandler,
+    metadata::{BuildConfiguration, Directories, Output, PackagingSettings},
+    recipe::{
+        parser::{About, Build, Dependency, Package, Requirements, Script, Source},
+        Recipe,
+    },
+    render::resolved_dependencies::DependencyInfo,
+    tool_configuration::Configuration,
+};
+use rattler_conda_types::{
+    package::ArchiveType, ChannelConfig, MatchSpec, NoArchType, Platform, Version,
+};
+use rattler_package_streaming::write::CompressionLevel;
+use reqwest::Url;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+use crate::{
+    build_log::BuildLogCapture,
+    build_tools::{explain_missing_build_tools, unconstrained_build_tools},
+    channel_diagnostics::diagnose_channels,
+    conda_build::{CondaBuildRequest, CondaBuildResponse},
+    conda_build_config::load_conda_build_config,
+    conda_metadata::{CondaMetadataRequest, CondaMetadataResponse},
+    config::{BackendConfig, ConfigOverrides, NoArch},
+    dependencies::{
+        add_spec_channels, check_conflicting_dependencies, check_pin_compatible, sort_requirements,
+        BuiltSourcePackage, MatchspecExtractor,
+    },
+    describe::{DescribeParams, DescribeResult},
+    dynamic_version::package_version,
+    effective_config::EffectiveConfig,
+    environment_file::EnvironmentFile,
+    environment_snapshot::write_environment_snapshots,
+    error::BuildBackendError,
+    hooks::run_post_build_hook,
+    host_prefix::use_host_prefix,
+    info_files::add_info_files,
+    input_globs::{InputGlobsParams, InputGlobsResult},
+    local_channel::{
+        add_to_local_channel, apply_repodata_source, local_channel_url, prepare_local_channel,
+    },
+    lock::{apply_lock_file, update_lock_file},
+    manifest_ext::{load_manifest, ManifestExt},
+    metadata_cache::{MetadataCache, MetadataCacheKey},
+    metapackage::into_metapackage,
+    package_contents::ensure_recipe_stored,
+    platform::{resolve_build_and_host_platform, target_platform},
+    platform_output::split_platform_output,
+    prefix_padding::pad_host_prefix,
+    protocol::{Protocol, ProtocolFactory},
+    recipe_hash::{recipe_hash, RecipeHashResult},
+    relocatability::check_relocatability,
+    sbom::write_sbom,
+    submodules::{git_source, warn_uncommitted_changes, SubmoduleSource},
+    utils::{write_build_script, TemporaryRenderedRecipe},
+    variants,
+};
+
+/// The parts of a build backend that are specific to its build tool.
+pub trait BuildBackend: Default + Send + Sync + 'static {
+    /// The keys of the `[tool.pixi-build]` table that are supported by this
+    /// backend.
+    const CONFIG_KEYS: &'static [&'static str];
+
+    /// The build tools that the backend adds to the requirements if they are
+    /// not declared in the manifest.
+    const BUILD_TOOLS: &'static [&'static str];
+
+    /// The `noarch` type of a package that does not configure one.
+    const DEFAULT_NOARCH: NoArch;
+
+    /// Whether the backend supports `reuse-build-dir`.
+    const REUSES_BUILD_DIR: bool;
+
+    /// Whether the build directory is kept after the package is built, unless
+    /// `tool-options.keep-build` says otherwise.
+    const KEEP_BUILD: bool;
+
+    /// Whether the variant of the package, and therefore its build string, is
+    /// only final once the dependencies are resolved, see
+    /// [`BuildBackend::finalize_output`]. The dependencies are then also
+    /// resolved before the package is built.
+    const SOLVE_BEFORE_BUILD: bool = false;
+
+    /// Constructs the [`Recipe`] of the package, see
+    /// [`BackendContext::recipe`] for the parts that are shared between the
+    /// backends.
+    fn recipe(
+        &self,
+        context: &BackendContext,
+        host_platform: Platform,
+        channel_config: &ChannelConfig,
+    ) -> miette::Result<Recipe>;
+
+    /// Returns the globs of the files that a build of the package depends on.
+    fn input_globs(&self) -> Vec<String>;
+
+    /// Returns the name of the package that installs the package, if the
+    /// backend uses one.
+    fn installer(
+        &self,
+        _context: &BackendContext,
+        _host_platform: Platform,
+        _channel_config: &ChannelConfig,
+    ) -> miette::Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Called after the dependencies of `output` are resolved, before its
+    /// build string is determined.
+    fn finalize_output(&self, _output: &mut Output) {}
+
+    /// Called after the package of `output` is built.
+    fn after_build(&self, _context: &BackendContext, _output: &Output) {}
+}
+
+/// The dependencies of each section of the manifest for a specific
+/// platform, see [`BackendContext::dependencies`].
+pub struct ManifestDependencies {
+    pub build: CondaDependencies,
+    pub host: CondaDependencies,
+    pub run: CondaDependencies,
+}
+
+/// The manifest and the configuration of the package that is built.
+#[derive(Clone)]
+pub struct BackendContext {
+    pub manifest: Manifest,
+    pub config: BackendConfig,
+    pub variant: BTreeMap<String, String>,
+    pub version: Version,
+    pub environment_file: Option<EnvironmentFile>,
+    /// The source dependencies that the frontend has already built for the
+    /// current request, see [`BuiltSourcePackage`].
+    pub built_sources: Vec<BuiltSourcePackage>,
+}
+
+impl BackendContext {
+    /// Reads the manifest at the given path and the configuration of the
+    /// backend `B` from it.
+    pub fn new<B: BuildBackend>(
+        manifest_path: &Path,
+        overrides: &ConfigOverrides,
+    ) -> miette::Result<Self> {
+        let manifest = load_manifest(manifest_path)?;
+        let config =
+            BackendConfig::from_manifest(&manifest, B::CONFIG_KEYS)?.with_overrides(overrides);
+
+        let variant = load_conda_build_config(manifest.manifest_root())?;
+        let version = package_version(&manifest, config.version_source)?;
+        let environment_file = config.environment_file(manifest.manifest_root())?;
+        config.report_warnings(&manifest, B::DEFAULT_NOARCH)?;
+
+        Ok(Self {
+            manifest,
+            config,
+            variant,
+            version,
+            environment_file,
+            built_sources: Vec::new(),
+        })
+    }
+
+    /// Returns the directory that contains the manifest.
+    pub fn manifest_root(&self) -> &Path {
+        self.manifest.manifest_root()
+    }
+
+    /// Returns the dependencies that are declared in the manifest for
+    /// `host_platform`. Returns an error if a package is declared in
+    /// conflicting ways.
+    pub fn dependencies(&self, host_platform: Platform) -> miette::Result<ManifestDependencies> {
+        let features = self
+            .manifest
+            .environment_features(self.config.environment.as_deref())?;
+
+        // Get all different feature types
+        let dependencies = |spec_type| {
+            Dependencies::from(
+                features
+                    .iter()
+                    .filter_map(|f| f.dependencies(spec_type, Some(host_platform))),
+            )
+        };
+        let dependencies = ManifestDependencies {
+            build: dependencies(SpecType::Build),
+            host: dependencies(SpecType::Host),
+            run: dependencies(SpecType::Run),
+        };
+
+        // Reject conflicting declarations before the backend injects any packages.
+        check_conflicting_dependencies(&dependencies.build, &dependencies.host, &dependencies.run)?;
+
+        Ok(dependencies)
+    }
+
+    /// Returns the requirements of the recipe for `dependencies`, followed by
+    /// the `extra_build` requirements that the backend adds (e.g. compilers).
+    pub fn requirements(
+        &self,
+        dependencies: ManifestDependencies,
+        channel_config: &ChannelConfig,
+        extra_build: Vec<MatchSpec>,
+    ) -> miette::Result<Requirements> {
+        let extractor = self.built_sources.iter().cloned().fold(
+            MatchspecExtractor::new(channel_config.clone())
+                .with_ignore_self(!self.config.keep_self_references),
+            MatchspecExtractor::with_built_source,
+        );
+        let extract = |dependencies: CondaDependencies| -> miette::Result<Vec<Dependency>> {
+            Ok(extractor
+                .extract(dependencies)?
+                .into_iter()
+                .map(Dependency::Spec)
+                .collect())
+        };
+
+        let mut requirements = Requirements {
+            build: extract(dependencies.build)?,
+            host: extract(dependencies.host)?,
+            run: extract(dependencies.run)?,
+            ..Requirements::default()
+        };
+        if let Some(environment_file) = &self.environment_file {
+            environment_file.add_run_dependencies(&mut requirements.run);
+        }
+        requirements.run_exports = self.config.run_exports.run_exports()?;
+        requirements.run_constraints = self.config.pin_compatible()?;
+        requirements
+            .build
+            .extend(extra_build.into_iter().map(Dependency::Spec));
+
+        variants::apply_variant(&mut requirements, &self.variant)?;
+        check_pin_compatible(&requirements)?;
+        sort_requirements(&mut requirements);
+
+        Ok(requirements)
+    }
+
+    /// Returns the absolute paths of the configured activation scripts.
+    pub fn activation_scripts(&self) -> miette::Result<Vec<String>> {
+        Ok(self
+            .config
+            .activation_scripts(self.manifest_root())?
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect())
+    }
+
+    /// Returns the git source that clones the sources with their submodules if
+    /// `recurse-submodules` is enabled, see [`crate::submodules`].
+    pub fn submodule_source(&self) -> miette::Result<Option<SubmoduleSource>> {
+        if !self.config.recurse_submodules {
+            return Ok(None);
+        }
+        git_source(&self.config.source_dir(self.manifest_root())?).map(Some)
+    }
+
+    /// Constructs a [`Recipe`] from the manifest and the configuration, with
+    /// the `source` and build `script` of the backend.
+    pub fn recipe(
+        &self,
+        noarch: NoArchType,
+        requirements: Requirements,
+        source: Vec<Source>,
+        script: Script,
+    ) -> miette::Result<Recipe> {
+        Ok(Recipe {
+            schema_version: self.config.schema_version()?,
+            context: Default::default(),
+            package: Package {
+                version: self.version.clone().into(),
+                name: self.manifest.package_name()?,
+            },
+            cache: None,
+            source,
+            build: Build {
+                number: self.config.build_number.unwrap_or(0),
+                string: Default::default(),
+                script,
+                noarch,
+                post_process: self.config.post_process()?,
+                always_include_files: self.config.files.always_include_files()?,
+                files: self.config.files.files()?,
+                prefix_detection: self.config.prefix_detection.prefix_detection()?,
+                dynamic_linking: self.config.dynamic_linking.dynamic_linking()?,
+                variant: self.config.variant.key_usage(),
+                ..Build::default()
+            },
+            requirements,
+            tests: self.config.test.tests()?,
+            about: About {
+                license_url: self.config.license_url.clone(),
+                ..self.manifest.about()?
+            },
+            extra: Default::default(),
+        })
+    }
+
+    /// Returns the channels to resolve the dependencies of `recipe` from, in
+    /// order of priority. The `base_urls` that are requested by the frontend
+    /// take precedence over the channels of the manifest. If `prepare` is
+    /// `true` the output channel is created if it does not exist yet.
+    fn channels(
+        &self,
+        base_urls: Option<Vec<Url>>,
+        channel_config: &ChannelConfig,
+        recipe: &Recipe,
+        prepare: bool,
+    ) -> miette::Result<Vec<Url>> {
+        let mut channels = match base_urls {
+            Some(channels) => channels,
+            None => self
+                .manifest
+                .resolved_project_channels(channel_config)
+                .into_diagnostic()
+                .context("failed to determine channels from the manifest")?,
+        };
+        if let Some(output_channel) = self.config.output_channel(self.manifest_root()) {
+            let url = if prepare {
+                prepare_local_channel(&output_channel)?
+            } else {
+                local_channel_url(&output_channel)?
+            };
+            channels.insert(0, url);
+        }
+        add_spec_channels(&mut channels, &recipe.requirements);
+        if let Some(environment_file) = &self.environment_file {
+            environment_file.add_channels(&mut channels, channel_config)?;
+        }
+        apply_repodata_source(&mut channels, self.config.repodata_source.as_deref())?;
+        Ok(channels)
+    }
+
+    /// Returns the channel configuration that resolves channel names against
+    /// `channel_alias`.
+    fn channel_config(&self, channel_alias: Url) -> ChannelConfig {
+        ChannelConfig {
+            channel_alias,
+            root_dir: self.manifest_root().to_path_buf(),
+        }
+    }
+
+    /// Returns an error if the manifest does not support `host_platform`.
+    fn check_platform(&self, host_platform: Platform) -> miette::Result<()> {
+        if !self.manifest.supports_target_platform(host_platform) {
+            return Err(BuildBackendError::UnsupportedPlatform(host_platform).into());
+        }
+        Ok(())
+    }
+}
+
+/// Returns the build and host platform that a package is built with for
+/// `params`. The package is built on this machine, so the build platform is
+/// the current platform with the virtual packages the frontend detected.
+fn build_and_host_platform(
+    params: &CondaBuildParams,
+) -> (
+    Option<PlatformAndVirtualPackages>,
+    Option<PlatformAndVirtualPackages>,
+) {
+    (
+        Some(PlatformAndVirtualPackages {
+            platform: Platform::current(),
+            virtual_packages: params.build_platform_virtual_packages.clone(),
+        }),
+        params.host_platform.clone(),
+    )
+}
+
+/// Implements [`Protocol`] for the [`BuildBackend`] `B`.
+pub struct GenericBackend<B> {
+    backend: B,
+    context: BackendContext,
+    logging_output_handler: LoggingOutputHandler,
+    cache_dir: Option<PathBuf>,
+    metadata_cache: MetadataCache,
+}
+
+impl<B: BuildBackend> GenericBackend<B> {
+    /// Returns a new instance of [`GenericBackendFactory`].
+    ///
+    /// This type implements [`ProtocolFactory`] and can be used to initialize a
+    /// new [`GenericBackend`].
+    pub fn factory(
+        logging_output_handler: LoggingOutputHandler,
+        overrides: ConfigOverrides,
+    ) -> GenericBackendFactory<B> {
+        GenericBackendFactory {
+            logging_output_handler,
+            overrides,
+            backend: PhantomData,
+        }
+    }
+
+    /// Returns a new instance of [`GenericBackend`] by reading the manifest
+    /// at the given path.
+    ///
+    /// A relative `cache_dir` is resolved against the directory of the
+    /// manifest.
+    pub fn new(
+        manifest_path: &Path,
+        logging_output_handler: LoggingOutputHandler,
+        cache_dir: Option<PathBuf>,
+        overrides: &ConfigOverrides,
+    ) -> miette::Result<Self> {
+        let context = BackendContext::new::<B>(manifest_path, overrides)?;
+        let cache_dir = cache_dir.map(|dir| context.manifest_root().join(dir));
+
+        Ok(Self {
+            backend: B::default(),
+            context,
+            logging_output_handler,
+            cache_dir,
+            metadata_cache: MetadataCache::default(),
+        })
+    }
+
+    /// Returns the capabilities of this backend that do not depend on a
+    /// manifest.
+    pub fn static_capabilities() -> BackendCapabilities {
+        BackendCapabilities {
+            provides_conda_metadata: Some(true),
+            provides_conda_build: Some(true),
+        }
+    }
+
+    /// Returns the capabilities of this backend based on the capabilities of
+    /// the frontend.
+    pub fn capabilites(
+        &self,
+        _frontend_capabilities: &FrontendCapabilities,
+    ) -> BackendCapabilities {
+        Self::static_capabilities()
+    }
+
+    /// Constructs the [`Recipe`] of the package. The `build_number` of a
+    /// request takes precedence over the build number of the configuration,
+    /// and dependencies on the `built_sources` of a request are pinned to the
+    /// built packages.
+    fn recipe(
+        &self,
+        host_platform: Platform,
+        channel_config: &ChannelConfig,
+        build_number: Option<u64>,
+        built_sources: &[BuiltSourcePackage],
+    ) -> miette::Result<Recipe> {
+        let mut recipe = if built_sources.is_empty() {
+            self.backend
+                .recipe(&self.context, host_platform, channel_config)?
+        } else {
+            let context = BackendContext {
+                built_sources: built_sources.to_vec(),
+                ..self.context.clone()
+            };
+            self.backend
+                .recipe(&context, host_platform, channel_config)?
+        };
+        if let Some(build_number) = build_number {
+            recipe.build.number = build_number;
+        }
+        if self.context.config.metapackage {
+            into_metapackage(&mut recipe, self.context.config.noarch);
+        }
+        Ok(recipe)
+    }
+
+    /// Returns the build configuration for a recipe
+    pub async fn build_configuration(
+        &self,
+        recipe: &Recipe,
+        channels: Vec<Url>,
+        build_platform: Option<PlatformAndVirtualPackages>,
+        host_platform: Option<PlatformAndVirtualPackages>,
+        work_directory: &Path,
+    ) -> miette::Result<BuildConfiguration> {
+        let config = &self.context.config;
+        let name = self.context.manifest.package_name()?;
+
+        std::fs::create_dir_all(work_directory)
+            .into_diagnostic()
+            .context("failed to create output directory")?;
+
+        let (build_platform, host_platform) =
+            resolve_build_and_host_platform(build_platform, host_platform)?;
+
+        // When the build directory is reused, the output directory must be stable
+        // across invocations. Together with omitting the build id this results in
+        // the same build and host prefixes for every build.
+        let output_directory = B::REUSES_BUILD_DIR
+            .then(|| {
+                config.reusable_build_dir(
+                    self.context.manifest_root(),
+                    &name,
+                    host_platform.platform,
+                )
+            })
+            .flatten()
+            .unwrap_or_else(|| work_directory.to_path_buf());
+        // The outputs of a package with a `platform-output` share the reusable build
+        // directory, but each of them has its own prefixes.
+        let mut directories = Directories::setup(
+            recipe.package.name.as_normalized(),
+            self.context.manifest.path.as_path(),
+            &output_directory,
+            true,
+            &Utc::now(),
+        )
+        .into_diagnostic()
+        .context("failed to setup build directories")?;
+        pad_host_prefix(&mut directories, config.host_prefix_length)?;
+
+        let variant = variants::used_variant(
+            &self.context.variant,
+            &recipe.requirements,
+            &recipe.build.variant,
+        );
+
+        Ok(BuildConfiguration {
+            target_platform: target_platform(
+                host_platform.platform,
+                &recipe.build.noarch,
+                config.target_platform,
+            )?,
+            host_platform,
+            build_platform,
+            hash: variants::hash_info(&variant, &recipe.build.noarch),
+            variant,
+            directories,
+            channels,
+            channel_priority: Default::default(),
+            solve_strategy: Default::default(),
+            timestamp: chrono::Utc::now(),
+            subpackages: Default::default(), // TODO: ???
+            packaging_settings: PackagingSettings::from_args(
+                ArchiveType::Conda,
+                CompressionLevel::default(),
+            ),
+            store_recipe: config.store_recipe,
+            force_colors: config.color.force_colors(),
+        })
+    }
+
+    /// Returns the recipes of the outputs of the package, in the order in
+    /// which they are built. That is only `recipe` itself, unless a
+    /// `platform-output` is configured, see [`crate::platform_output`].
+    fn outputs(&self, recipe: Recipe) -> miette::Result<Vec<Recipe>> {
+        let config = &self.context.config;
+        match &config.platform_output {
+            Some(platform_output) => {
+                Ok(split_platform_output(recipe, platform_output, &config.files)?.into())
+            }
+            None => Ok(vec![recipe]),
+        }
+    }
+
+    /// Resolves the dependencies of `output` and returns its metadata.
+    async fn resolved_metadata(
+        &self,
+        output: Output,
+        tool_config: &Configuration,
+        cancellation: &CancellationToken,
+    ) -> miette::Result<CondaPackageMetadata> {
+        let context = &self.context;
+        let config = &context.config;
+        if config.diagnose_channels {
+            diagnose_channels(&output, tool_config).await;
+        }
+
+        let output = apply_lock_file(config.lock_mode, context.manifest_root(), output)?;
+
+        let build_tools = unconstrained_build_tools(&output.recipe.requirements, B::BUILD_TOOLS);
+        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
+        let resolve = temp_recipe.within_context_async(move || async move {
+            output
+                .resolve_dependencies(tool_config)
+                .await
+                .map_err(|err| BuildBackendError::DependencyResolution(err.into()).into())
+        });
+        let mut output = until_cancelled(cancellation, resolve)
+            .await
+            .map_err(|err| explain_missing_build_tools(err, &build_tools))?;
+        if B::SOLVE_BEFORE_BUILD {
+            self.backend.finalize_output(&mut output);
+            variants::apply_build_string(&mut output, config.build_string.as_deref())?;
+        }
+        update_lock_file(config.lock_mode, context.manifest_root(), &output)?;
+
+        let finalized_deps = &output
+            .finalized_dependencies
+            .as_ref()
+            .expect("dependencies should be resolved at this point")
+            .run;
+
+        Ok(CondaPackageMetadata {
+            name: output.name().clone(),
+            version: output.version().clone().into(),
+            build: output.build_string().into_owned(),
+            build_number: output.recipe.build.number,
+            subdir: output.build_configuration.target_platform,
+            depends: unique_sorted_specs(finalized_deps.depends.iter().map(DependencyInfo::spec)),
+            constraints: unique_sorted_specs(
+                finalized_deps.constraints.iter().map(DependencyInfo::spec),
+            ),
+            license: output.recipe.about.license.map(|l| l.to_string()),
+            license_family: output.recipe.about.license_family,
+            noarch: output.recipe.build.noarch,
+        })
+    }
+
+    /// Builds the package for a `conda/build` request, see
+    /// [`Protocol::build_conda`].
+    async fn build(
+        &self,
+        request: CondaBuildRequest,
+        cancellation: CancellationToken,
+    ) -> miette::Result<CondaBuildResponse> {
+        let CondaBuildRequest {
+            params,
+            build_number,
+            built_sources,
+        } = request;
+        let context = &self.context;
+        let config = &context.config;
+        let manifest_root = context.manifest_root();
+        let channel_config = context.channel_config(params.channel_configuration.base_url.clone());
+        let host_platform = params
+            .host_platform
+            .as_ref()
+            .map(|p| p.platform)
+            .unwrap_or_else(Platform::current);
+        context.check_platform(host_platform)?;
+        if config.recurse_submodules {
+            warn_uncommitted_changes(&config.source_dir(manifest_root)?)?;
+        }
+
+        let recipe = self.recipe(host_platform, &channel_config, build_number, &built_sources)?;
+        let channels = context.channels(
+            params.channel_base_urls.clone(),
+            &channel_config,
+            &recipe,
+            true,
+        )?;
+        if config.keep_build_script {
+            let path =
+                write_build_script(&recipe.build.script, host_platform, &params.work_directory)?;
+            tracing::info!("wrote the build script to {}", path.display());
+        }
+        let tool_config = config.tool_configuration(
+            self.cache_dir.clone(),
+            self.logging_output_handler.clone(),
+            channel_config.clone(),
+            B::KEEP_BUILD,
+        )?;
+
+        let mut response = CondaBuildResponse {
+            result: CondaBuildResult {
+                packages: Vec::new(),
+            },
+            sboms: Vec::new(),
+            log_file: None,
+        };
+        for recipe in self.outputs(recipe)? {
+            let (package, sbom) = self
+                .build_output(
+                    recipe,
+                    channels.clone(),
+                    &params,
+                    &tool_config,
+                    &cancellation,
+                )
+                .await?;
+            response.result.packages.push(package);
+            response.sboms.extend(sbom);
+        }
+        Ok(response)
+    }
+
+    /// Builds the package of a single output of a `conda/build` request and
+    /// returns it, together with its bill of materials if one was written.
+    async fn build_output(
+        &self,
+        recipe: Recipe,
+        channels: Vec<Url>,
+        params: &CondaBuildParams,
+        tool_config: &Configuration,
+        cancellation: &CancellationToken,
+    ) -> miette::Result<(CondaBuiltPackage, Option<PathBuf>)> {
+        let context = &self.context;
+        let config = &context.config;
+        let manifest_root = context.manifest_root();
+        let (build_platform, host_platform) = build_and_host_platform(params);
+        let build_configuration = self
+            .build_configuration(
+                &recipe,
+                channels,
+                build_platform,
+                host_platform,
+                &params.work_directory,
+            )
+            .await?;
+        let mut output = unresolved_output(recipe, build_configuration);
+        if !B::SOLVE_BEFORE_BUILD {
+            variants::apply_build_string(&mut output, config.build_string.as_deref())?;
+        }
+
+        let output = apply_lock_file(config.lock_mode, manifest_root, output)?;
+
+        let host_prefix = config.host_prefix(manifest_root);
+        let build_tools = unconstrained_build_tools(&output.recipe.requirements, B::BUILD_TOOLS);
+        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
+        let build = temp_recipe.within_context_async(move || async move {
+            let mut output = if B::SOLVE_BEFORE_BUILD || host_prefix.is_some() {
+                output
+                    .resolve_dependencies(tool_config)
+                    .await
+                    .map_err(|err| BuildBackendError::DependencyResolution(err.into()))?
+            } else {
+                output
+            };
+            if let Some(host_prefix) = host_prefix {
+                output = use_host_prefix(&host_prefix, output)?;
+            }
+            if B::SOLVE_BEFORE_BUILD {
+                self.backend.finalize_output(&mut output);
+                variants::apply_build_string(&mut output, config.build_string.as_deref())?;
+            }
+            run_build(output, tool_config).await
+        });
+        let (output, package) = until_cancelled(cancellation, build)
+            .await
+            .map_err(|err| explain_missing_build_tools(err, &build_tools))?;
+        update_lock_file(config.lock_mode, manifest_root, &output)?;
+        self.backend.after_build(context, &output);
+
+        add_info_files(
+            &package,
+            &config.info_files,
+            manifest_root,
+            &output.build_configuration.timestamp,
+        )?;
+        if config.store_recipe {
+            ensure_recipe_stored(&package)?;
+        }
+        if config.snapshot_environments {
+            for path in write_environment_snapshots(&output, &package)? {
+                tracing::info!("wrote an environment snapshot to {}", path.display());
+            }
+        }
+        let sbom = if config.sbom {
+            let path = write_sbom(&output, &package)?;
+            tracing::info!("wrote a bill of materials to {}", path.display());
+            Some(path)
+        } else {
+            None
+        };
+
+        check_relocatability(
+            config.check_relocatability,
+            &package,
+            &output,
+            &config.source_dir(manifest_root)?,
+        )?;
+
+        run_post_build_hook(
+            &config.post_build_hook,
+            &package,
+            manifest_root,
+            cancellation,
+        )
+        .await?;
+
+        if let Some(output_channel) = config.output_channel(manifest_root) {
+            let path = add_to_local_channel(&output_channel, &package, *output.target_platform())?;
+            tracing::info!(
+                "added the package to the local channel as {}",
+                path.display()
+            );
+        }
+
+        let package = CondaBuiltPackage {
+            output_file: package,
+            input_globs: self.backend.input_globs(),
+            name: output.name().as_normalized().to_string(),
+            version: output.version().to_string(),
+            build: output.build_string().into_owned(),
+            subdir: output.target_platform().to_string(),
+        };
+        Ok((package, sbom))
+    }
+}
+
+/// Returns the given match specs as strings, sorted and without duplicates,
+/// so the metadata does not depend on the order in which the dependencies
+/// were resolved.
+fn unique_sorted_specs<'a>(specs: impl IntoIterator<Item = &'a MatchSpec>) -> Vec<String> {
+    specs
+        .into_iter()
+        .map(MatchSpec::to_string)
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// Awaits `future`, or returns [`BuildBackendError::Cancelled`] as soon as
+/// `cancellation` is cancelled. In that case the future is dropped, together
+/// with the temporary directories and child processes that it owns.
+async fn until_cancelled<T>(
+    cancellation: &CancellationToken,
+    future: impl Future<Output = miette::Result<T>>,
+) -> miette::Result<T> {
+    tokio::select! {
+        result = future => result,
+        () = cancellation.cancelled() => Err(BuildBackendError::Cancelled.into()),
+    }
+}
+
+/// Returns an [`Output`] for `recipe` of which the dependencies are not
+/// resolved yet.
+pub(crate) fn unresolved_output(recipe: Recipe, build_configuration: BuildConfiguration) -> Output {
+    Output {
+        build_configuration,
+        recipe,
+        finalized_dependencies: None,
+        finalized_cache_dependencies: None,
+        finalized_sources: None,
+        build_summary: Arc::default(),
+        system_tools: Default::default(),
+        extra_meta: None,
+    }
+}
+
+#[async_trait::async_trait]
+impl<B: BuildBackend> Protocol for GenericBackend<B> {
+    async fn get_conda_metadata(
+        &self,
+        request: CondaMetadataRequest,
+        cancellation: CancellationToken,
+    ) -> miette::Result<CondaMetadataResponse> {
+        let CondaMetadataRequest {
+            params,
+            no_deps_metadata,
+            build_number,
+            built_sources,
+        } = request;
+        let context = &self.context;
+        let config = &context.config;
+        let channel_config = context.channel_config(params.channel_configuration.base_url);
+
+        let host_platform = params
+            .host_platform
+            .as_ref()
+            .map(|p| p.platform)
+            .unwrap_or(Platform::current());
+        context.check_platform(host_platform)?;
+
+        // TODO: Determine how and if we can determine this from the manifest.
+        let recipe = self.recipe(host_platform, &channel_config, build_number, &built_sources)?;
+        let channels =
+            context.channels(params.channel_base_urls, &channel_config, &recipe, true)?;
+        let mut outputs = Vec::new();
+        for recipe in self.outputs(recipe)? {
+            let build_configuration = self
+                .build_configuration(
+                    &recipe,
+                    channels.clone(),
+                    params.build_platform.clone(),
+                    params.host_platform.clone(),
+                    &params.work_directory,
+                )
+                .await?;
+            let mut output = unresolved_output(recipe, build_configuration);
+            if B::SOLVE_BEFORE_BUILD {
+                // The variant is only final once the dependencies are resolved, the
+                // build string is applied again below if they are.
+                variants::apply_partial_build_string(&mut output, config.build_string.as_deref())?;
+            } else {
+                variants::apply_build_string(&mut output, config.build_string.as_deref())?;
+            }
+            outputs.push(output);
+        }
+        if no_deps_metadata {
+            return Ok(CondaMetadataResponse::unresolved(&outputs));
+        }
+
+        // The dependencies differ per platform, so the cache is keyed by the resolved
+        // build configuration of the package itself, which is the last output.
+        let package = outputs.last().expect("a package has at least one output");
+        let cache_key = MetadataCacheKey::new(&package.build_configuration, &built_sources);
+        let channels = package.build_configuration.channels.clone();
+        if let Some(packages) = self.metadata_cache.get(&cache_key) {
+            return Ok(CondaMetadataResponse {
+                result: CondaMetadataResult {
+                    packages,
+                    input_globs: None,
+                },
+                channels,
+                dependencies_resolved: true,
+            });
+        }
+
+        let tool_config = config.tool_configuration(
+            self.cache_dir.clone(),
+            self.logging_output_handler.clone(),
+            channel_config.clone(),
+            B::KEEP_BUILD,
+        )?;
+        let mut packages = Vec::new();
+        for output in outputs {
+            packages.push(
+                self.resolved_metadata(output, &tool_config, &cancellation)
+                    .await?,
+            );
+        }
+        self.metadata_cache.insert(cache_key, packages.clone());
+
+        Ok(CondaMetadataResponse {
+            result: CondaMetadataResult {
+                packages,
+                input_globs: None,
+            },
+            channels,
+            dependencies_resolved: true,
+        })
+    }
+
+    async fn describe(&self, params: DescribeParams) -> miette::Result<DescribeResult> {
+        let channel_config = self
+            .context
+            .channel_config(params.channel_configuration.base_url);
+        let host_platform = params.host_platform.unwrap_or_else(Platform::current);
+        let recipe = self.recipe(host_platform, &channel_config, None, &[])?;
+        Ok(DescribeResult { recipe })
+    }
+
+    async fn effective_config(&self, params: DescribeParams) -> miette::Result<EffectiveConfig> {
+        let context = &self.context;
+        let channel_config = context.channel_config(params.channel_configuration.base_url);
+        let host_platform = params.host_platform.unwrap_or_else(Platform::current);
+        let recipe = self.recipe(host_platform, &channel_config, None, &[])?;
+        let channels = context.channels(None, &channel_config, &recipe, false)?;
+
+        Ok(EffectiveConfig {
+            manifest_path: context.manifest.path.clone(),
+            channels,
+            build_platform: Platform::current(),
+            host_platform,
+            target_platform: target_platform(
+                host_platform,
+                &recipe.build.noarch,
+                context.config.target_platform,
+            )?,
+            noarch: recipe.build.noarch,
+            build_number: recipe.build.number,
+            installer: self
+                .backend
+                .installer(context, host_platform, &channel_config)?,
+            archive_type: ArchiveType::Conda.extension().to_string(),
+            config: context.config.clone(),
+        }
+        .redacted())
+    }
+
+    async fn recipe_hash(&self, params: DescribeParams) -> miette::Result<RecipeHashResult> {
+        let channel_config = self
+            .context
+            .channel_config(params.channel_configuration.base_url);
+        let host_platform = params.host_platform.unwrap_or_else(Platform::current);
+        let recipe = self.recipe(host_platform, &channel_config, None, &[])?;
+        Ok(RecipeHashResult {
+            hash: recipe_hash(
+                &recipe,
+                self.context.manifest_root(),
+                host_platform,
+                &self.context.variant,
+            )?,
+        })
+    }
+
+    async fn input_globs(&self, _params: InputGlobsParams) -> miette::Result<InputGlobsResult> {
+        Ok(InputGlobsResult {
+            input_globs: self.backend.input_globs(),
+        })
+    }
+
+    async fn build_conda(
+        &self,
+        request: CondaBuildRequest,
+        cancellation: CancellationToken,
+    ) -> miette::Result<CondaBuildResponse> {
+        let Some(max_size) = self.context.config.build_log_max_size else {
+            return self.build(request, cancellation).await;
+        };
+
+        // The log is captured per build, so builds that run at the same time do
+        // not end up in each other's log.
+        let build_log =
+            BuildLogCapture::start(&request.params.work_directory, max_size * 1024 * 1024)?;
+        tracing::info!("capturing the build log to {}", build_log.path().display());
+        let response = self
+            .build(request, cancellation)
+            .instrument(build_log.span().clone())
+            .await?;
+        Ok(CondaBuildResponse {
+            log_file: Some(build_log.path().to_path_buf()),
+            ..response
+        })
+    }
+}
+
+/// Initializes a [`GenericBackend`] for the [`BuildBackend`] `B`.
+pub struct GenericBackendFactory<B> {
+    logging_output_handler: LoggingOutputHandler,
+    overrides: ConfigOverrides,
+    backend: PhantomData<fn() -> B>,
+}
+
+#[async_trait::async_trait]
+impl<B: BuildBackend> ProtocolFactory for GenericBackendFactory<B> {
+    type Protocol = GenericBackend<B>;
+
+    fn capabilities(&self) -> BackendCapabilities {
+        GenericBackend::<B>::static_capabilities()
+    }
+
+    async fn initialize(
+        &self,
+        params: InitializeParams,
+    ) -> miette::Result<(Self::Protocol, InitializeResult)> {
+        let instance = GenericBackend::<B>::new(
+            params.manifest_path.as_path(),
+            self.logging_output_handler.clone(),
+            params.cache_directory,
+            &self.overrides,
+        )?;
+
+        let capabilities = instance.capabilites(&params.capabilities);
+        Ok((instance, InitializeResult { capabilities }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, str::FromStr};
+
+    use pixi_build_types::ChannelConfiguration;
+    use rattler_conda_types::{GenericVirtualPackage, PackageName, ParseStrictness, Version};
+
+    use super::*;
+
+    fn build_params(
+        host_platform: Option<PlatformAndVirtualPackages>,
+        build_platform_virtual_packages: Option<Vec<GenericVirtualPackage>>,
+    ) -> CondaBuildParams {
+        CondaBuildParams {
+            host_platform,
+            build_platform_virtual_packages,
+            channel_base_urls: None,
+            channel_configuration: ChannelConfiguration {
+                base_url: Url::parse("https://conda.anaconda.org").unwrap(),
+            },
+            outputs: None,
+            work_directory: PathBuf::from("work"),
+        }
+    }
+
+    #[test]
+    fn build_environment_has_the_build_virtual_packages() {
+        let build_virtual_packages = vec![GenericVirtualPackage {
+            name: PackageName::new_unchecked("__glibc"),
+            version: Version::from_str("2.28").unwrap(),
+            build_string: "0".to_string(),
+        }];
+        let host_platform = PlatformAndVirtualPackages {
+            platform: Platform::Linux64,
+            virtual_packages: Some(Vec::new()),
+        };
+        let params = build_params(
+            Some(host_platform.clone()),
+            Some(build_virtual_packages.clone()),
+        );
+
+        let (build, host) = build_and_host_platform(&params);
+        let (build, host) = resolve_build_and_host_platform(build, host).unwrap();
+
+        assert_eq!(build.platform, Platform::current());
+        assert_eq!(build.virtual_packages, build_virtual_packages);
+        assert_eq!(host.platform, Platform::Linux64);
+        assert!(host.virtual_packages.is_empty());
+    }
+
+    #[test]
+    fn host_platform_defaults_to_the_current_platform() {
+        let params = build_params(None, Some(Vec::new()));
+
+        let (build, host) = build_and_host_platform(&params);
+        let (build, host) = resolve_build_and_host_platform(build, host).unwrap();
+
+        assert_eq!(build.platform, Platform::current());
+        assert!(build.virtual_packages.is_empty());
+        assert_eq!(host.platform, Platform::current());
+    }
+
+    #[test]
+    fn metadata_specs_are_sorted_and_unique() {
+        let specs = ["zlib", "numpy >=2", "python >=3.10", "numpy >=2", "libcurl"]
+            .map(|spec| MatchSpec::from_str(spec, ParseStrictness::Strict).unwrap());
+        assert_eq!(
+            unique_sorted_specs(&specs),
+            ["libcurl", "numpy >=2", "python >=3.10", "zlib"]
+        );
+    }
+}
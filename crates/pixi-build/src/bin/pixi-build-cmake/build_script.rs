@@ -1,10 +1,18 @@
 use minijinja::Environment;
+use pixi_build_backend::entry_points::CommandEntryPoint;
 use serde::Serialize;
 
 #[derive(Serialize)]
 pub struct BuildScriptContext {
     pub build_platform: BuildPlatform,
+    /// Absolute paths of the scripts to source before building.
+    pub activation_scripts: Vec<String>,
     pub source_dir: String,
+    /// Wrappers that are written to `bin` after the package is installed.
+    pub entry_points: Vec<CommandEntryPoint>,
+    /// The directory in which cmake configures the build, or `None` to use a
+    /// directory next to the source directory of the build.
+    pub build_dir: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -24,3 +32,27 @@ impl BuildScriptContext {
         rendered.split("\n").map(|s| s.to_string()).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(build_platform: BuildPlatform) -> BuildScriptContext {
+        BuildScriptContext {
+            build_platform,
+            activation_scripts: vec![],
+            source_dir: "src".to_string(),
+            entry_points: vec![],
+            build_dir: None,
+        }
+    }
+
+    #[test]
+    fn builds_with_the_job_count_of_the_environment() {
+        let unix = context(BuildPlatform::Unix).render().join("\n");
+        assert!(unix.contains("--parallel $CPU_COUNT"), "{unix}");
+
+        let windows = context(BuildPlatform::Windows).render().join("\n");
+        assert!(windows.contains("--parallel %CPU_COUNT%"), "{windows}");
+    }
+}
@@ -1,186 +1,88 @@
-use std::{
-    collections::BTreeMap,
-    path::{Path, PathBuf},
-    str::FromStr,
-    sync::Arc,
-};
-
-use chrono::Utc;
-use miette::{Context, IntoDiagnostic};
 use pixi_build_backend::{
-    dependencies::MatchspecExtractor,
+    backend::{BackendContext, BuildBackend},
+    config::NoArch,
+    dependencies::add_host_tools,
+    entry_points::command_entry_points,
     manifest_ext::ManifestExt,
-    protocol::{Protocol, ProtocolFactory},
-    utils::TemporaryRenderedRecipe,
-};
-use pixi_build_types::{
-    procedures::{
-        conda_build::{CondaBuildParams, CondaBuildResult, CondaBuiltPackage},
-        conda_metadata::{CondaMetadataParams, CondaMetadataResult},
-        initialize::{InitializeParams, InitializeResult},
-    },
-    BackendCapabilities, CondaPackageMetadata, FrontendCapabilities, PlatformAndVirtualPackages,
 };
-use pixi_manifest::{Dependencies, Manifest, SpecType};
-use pixi_spec::PixiSpec;
-use rattler_build::{
-    build::run_build,
-    console_utils::LoggingOutputHandler,
-    hash::HashInfo,
-    metadata::{
-        BuildConfiguration, Directories, Output, PackagingSettings, PlatformWithVirtualPackages,
-    },
-    recipe::{
-        parser::{Build, Dependency, Package, Requirements, ScriptContent},
-        Recipe,
-    },
-    render::resolved_dependencies::DependencyInfo,
-    tool_configuration::Configuration,
-};
-use rattler_conda_types::{
-    package::ArchiveType, ChannelConfig, MatchSpec, NoArchType, PackageName, Platform,
-};
-use rattler_package_streaming::write::CompressionLevel;
-use rattler_virtual_packages::VirtualPackageOverrides;
-use reqwest::Url;
+use rattler_build::recipe::{parser::ScriptContent, Recipe};
+use rattler_conda_types::{ChannelConfig, MatchSpec, PackageName, Platform};
 
 use crate::{
     build_script::{BuildPlatform, BuildScriptContext},
     stub::default_compiler,
 };
 
-pub struct CMakeBuildBackend {
-    logging_output_handler: LoggingOutputHandler,
-    manifest: Manifest,
-    cache_dir: Option<PathBuf>,
-}
+/// The keys of the `[tool.pixi-build]` table that are supported by this
+/// backend.
+const CONFIG_KEYS: &[&str] = &[
+    "activation-scripts",
+    "build-number",
+    "build-string",
+    "check-relocatability",
+    "compilers",
+    "dynamic-linking",
+    "entry-points",
+    "environment",
+    "environment-file",
+    "files",
+    "host-prefix",
+    "host-prefix-length",
+    "info-files",
+    "jobs",
+    "keep-self-references",
+    "license-url",
+    "metapackage",
+    "noarch",
+    "output-channel",
+    "pin-compatible",
+    "platform-output",
+    "post-build-hook",
+    "post-process",
+    "prefix-detection",
+    "recurse-submodules",
+    "repodata",
+    "reuse-build-dir",
+    "run-exports",
+    "sbom",
+    "schema-version",
+    "script-env",
+    "skip-build-tools",
+    "snapshot-environments",
+    "source-dir",
+    "store-recipe",
+    "test",
+    "tool-options",
+    "variant",
+    "version-source",
+];
+
+/// The build tools that the backend adds to the requirements if they are not
+/// declared in the manifest.
+const BUILD_TOOLS: &[&str] = &["cmake", "ninja"];
+
+/// Builds packages with cmake and ninja.
+#[derive(Default)]
+pub struct CMakeBuildBackend;
 
 impl CMakeBuildBackend {
-    /// Returns a new instance of [`CMakeBuildBackendFactory`].
-    ///
-    /// This type implements [`ProtocolFactory`] and can be used to initialize a
-    /// new [`CMakeBuildBackend`].
-    pub fn factory(logging_output_handler: LoggingOutputHandler) -> CMakeBuildBackendFactory {
-        CMakeBuildBackendFactory {
-            logging_output_handler,
-        }
-    }
-
-    /// Returns a new instance of [`CMakeBuildBackend`] by reading the manifest
-    /// at the given path.
-    pub fn new(
-        manifest_path: &Path,
-        logging_output_handler: LoggingOutputHandler,
-        cache_dir: Option<PathBuf>,
-    ) -> miette::Result<Self> {
-        // Load the manifest from the source directory
-        let manifest = Manifest::from_path(manifest_path).with_context(|| {
-            format!("failed to parse manifest from {}", manifest_path.display())
-        })?;
-
-        Ok(Self {
-            manifest,
-            logging_output_handler,
-            cache_dir,
-        })
-    }
-
-    /// Returns the capabilities of this backend based on the capabilities of
-    /// the frontend.
-    pub fn capabilites(
-        &self,
-        _frontend_capabilities: &FrontendCapabilities,
-    ) -> BackendCapabilities {
-        BackendCapabilities {
-            provides_conda_metadata: Some(true),
-            provides_conda_build: Some(true),
-        }
-    }
-
-    /// Returns the requirements of the project that should be used for a
-    /// recipe.
-    fn requirements(
-        &self,
-        host_platform: Platform,
-        channel_config: &ChannelConfig,
-    ) -> miette::Result<Requirements> {
-        let mut requirements = Requirements::default();
-        let default_features = [self.manifest.default_feature()];
-
-        // Get all different feature types
-        let run_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Run, Some(host_platform))),
-        );
-        let mut host_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Host, Some(host_platform))),
-        );
-        let build_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Build, Some(host_platform))),
-        );
-
-        // Ensure build tools are available in the host dependencies section.
-        for pkg_name in ["cmake", "ninja"] {
-            if host_dependencies.contains_key(pkg_name) {
-                // If the host dependencies already contain the package, we don't need to add it
-                // again.
-                continue;
-            }
-
-            if let Some(run_requirements) = run_dependencies.get(pkg_name) {
-                // Copy the run requirements to the host requirements.
-                for req in run_requirements {
-                    host_dependencies.insert(PackageName::from_str(pkg_name).unwrap(), req.clone());
-                }
-            } else {
-                host_dependencies.insert(
-                    PackageName::from_str(pkg_name).unwrap(),
-                    PixiSpec::default(),
-                );
-            }
-        }
-
-        requirements.build = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(build_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-        requirements.host = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(host_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-        requirements.run = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(run_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-
-        // Add compilers to the dependencies.
-        requirements.build.extend(
-            self.compiler_packages(host_platform)
-                .into_iter()
-                .map(Dependency::Spec),
-        );
-
-        Ok(requirements)
-    }
-
     /// Returns the matchspecs for the compiler packages. That should be
-    /// included in the build section of the recipe.
-    fn compiler_packages(&self, target_platform: Platform) -> Vec<MatchSpec> {
+    /// included in the build section of the recipe. The compilers that are
+    /// configured in `compilers` take precedence over the default compilers
+    /// of the platform.
+    fn compiler_packages(
+        &self,
+        context: &BackendContext,
+        target_platform: Platform,
+    ) -> Vec<MatchSpec> {
         let mut compilers = vec![];
 
         for lang in self.languages() {
-            if let Some(name) = default_compiler(target_platform, &lang) {
+            let name = match context.config.compiler(target_platform, &lang) {
+                Some(name) => Some(name.to_string()),
+                None => default_compiler(target_platform, &lang),
+            };
+            if let Some(name) = name {
                 // TODO: Read this from variants
                 // TODO: Read the version specification from variants
                 let compiler_package =
@@ -200,367 +102,319 @@ impl CMakeBuildBackend {
         // TODO: Can we figure this out from looking at the CMake?
         vec!["cxx".to_string()]
     }
+}
+
+impl BuildBackend for CMakeBuildBackend {
+    const CONFIG_KEYS: &'static [&'static str] = CONFIG_KEYS;
+    const BUILD_TOOLS: &'static [&'static str] = BUILD_TOOLS;
+    const DEFAULT_NOARCH: NoArch = NoArch::None;
+    const REUSES_BUILD_DIR: bool = true;
+    const KEEP_BUILD: bool = true;
 
-    /// Constructs a [`Recipe`] from the current manifest.
     fn recipe(
         &self,
+        context: &BackendContext,
         host_platform: Platform,
         channel_config: &ChannelConfig,
     ) -> miette::Result<Recipe> {
-        let manifest_root = self
-            .manifest
-            .path
-            .parent()
-            .expect("the project manifest must reside in a directory");
-
-        // Parse the package name from the manifest
-        let Some(name) = self.manifest.parsed.project.name.clone() else {
-            miette::bail!("a 'name' field is required in the project manifest");
-        };
-        let name = PackageName::from_str(&name).into_diagnostic()?;
-        let version = self.manifest.version_or_default().clone();
+        let config = &context.config;
+        let manifest_root = context.manifest_root();
+        let name = context.manifest.package_name()?;
 
-        let noarch_type = NoArchType::none();
+        let noarch_type = config.noarch_type(NoArch::None, &[NoArch::Generic, NoArch::None])?;
 
-        let requirements = self.requirements(host_platform, channel_config)?;
+        let mut dependencies = context.dependencies(host_platform)?;
+        // Ensure build tools are available in the host dependencies section. No
+        // other run dependency ends up in the host environment.
+        if !config.skip_build_tools {
+            add_host_tools(&mut dependencies.host, &dependencies.run, BUILD_TOOLS);
+        }
+        // Add compilers to the dependencies.
+        let requirements = context.requirements(
+            dependencies,
+            channel_config,
+            self.compiler_packages(context, host_platform),
+        )?;
         let build_platform = Platform::current();
-        let build_number = 0;
+        let submodule_source = context.submodule_source()?;
 
         let build_script = BuildScriptContext {
+            entry_points: command_entry_points(&config.entry_points)?,
+            activation_scripts: context.activation_scripts()?,
             build_platform: if build_platform.is_windows() {
                 BuildPlatform::Windows
             } else {
                 BuildPlatform::Unix
             },
-            source_dir: manifest_root.display().to_string(),
+            source_dir: match &submodule_source {
+                Some(source) => source.source_dir(build_platform),
+                None => config.source_dir(manifest_root)?.display().to_string(),
+            },
+            // The cache of cmake is tied to the source directory, which is a
+            // new clone for every build with submodules.
+            build_dir: config
+                .reusable_build_dir(manifest_root, &name, host_platform)
+                .filter(|_| submodule_source.is_none())
+                .map(|dir| dir.join("cmake-build").display().to_string()),
         }
         .render();
 
-        Ok(Recipe {
-            schema_version: 1,
-            context: Default::default(),
-            package: Package {
-                version: version.into(),
-                name,
-            },
-            cache: None,
-            // source: vec![Source::Path(PathSource {
-            //     // TODO: How can we use a git source?
-            //     path: manifest_root.to_path_buf(),
-            //     sha256: None,
-            //     md5: None,
-            //     patches: vec![],
-            //     target_directory: None,
-            //     file_name: None,
-            //     use_gitignore: true,
-            // })],
-            // We hack the source location
-            source: vec![],
-            build: Build {
-                number: build_number,
-                string: Default::default(),
-
-                // skip: Default::default(),
-                script: ScriptContent::Commands(build_script).into(),
-                noarch: noarch_type,
-
-                // TODO: Python is not exposed properly
-                //python: Default::default(),
-                // dynamic_linking: Default::default(),
-                // always_copy_files: Default::default(),
-                // always_include_files: Default::default(),
-                // merge_build_and_host_envs: false,
-                // variant: Default::default(),
-                // prefix_detection: Default::default(),
-                // post_process: vec![],
-                // files: Default::default(),
-                ..Build::default()
-            },
-            // TODO read from manifest
+        // Unless the sources are cloned with their submodules, they are built in
+        // place, so the recipe has no source.
+        context.recipe(
+            noarch_type,
             requirements,
-            tests: vec![],
-            about: Default::default(),
-            extra: Default::default(),
-        })
+            submodule_source
+                .map(|source| vec![source.source])
+                .unwrap_or_default(),
+            config.parallel_script(ScriptContent::Commands(build_script))?,
+        )
     }
 
-    /// Returns the build configuration for a recipe
-    pub async fn build_configuration(
-        &self,
-        recipe: &Recipe,
-        channels: Vec<Url>,
-        build_platform: Option<PlatformAndVirtualPackages>,
-        host_platform: Option<PlatformAndVirtualPackages>,
-        work_directory: &Path,
-    ) -> miette::Result<BuildConfiguration> {
-        // Parse the package name from the manifest
-        let Some(name) = self.manifest.parsed.project.name.clone() else {
-            miette::bail!("a 'name' field is required in the project manifest");
-        };
-        let name = PackageName::from_str(&name).into_diagnostic()?;
-
-        // TODO: Setup defaults
-        std::fs::create_dir_all(work_directory)
-            .into_diagnostic()
-            .context("failed to create output directory")?;
-        let directories = Directories::setup(
-            name.as_normalized(),
-            self.manifest.path.as_path(),
-            work_directory,
-            true,
-            &Utc::now(),
-        )
-        .into_diagnostic()
-        .context("failed to setup build directories")?;
-
-        let build_platform = build_platform.map(|p| PlatformWithVirtualPackages {
-            platform: p.platform,
-            virtual_packages: p.virtual_packages.unwrap_or_default(),
-        });
-
-        let host_platform = host_platform.map(|p| PlatformWithVirtualPackages {
-            platform: p.platform,
-            virtual_packages: p.virtual_packages.unwrap_or_default(),
-        });
-
-        let (build_platform, host_platform) = match (build_platform, host_platform) {
-            (Some(build_platform), Some(host_platform)) => (build_platform, host_platform),
-            (build_platform, host_platform) => {
-                let current_platform =
-                    PlatformWithVirtualPackages::detect(&VirtualPackageOverrides::from_env())
-                        .into_diagnostic()?;
-                (
-                    build_platform.unwrap_or_else(|| current_platform.clone()),
-                    host_platform.unwrap_or(current_platform),
-                )
-            }
-        };
+    fn input_globs(&self) -> Vec<String> {
+        [
+            // Source files
+            "**/*.{c,cc,cxx,cpp,h,hpp,hxx}",
+            // CMake files
+            "**/*.{cmake,cmake.in}",
+            "**/CMakeFiles.txt",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+}
 
-        let variant = BTreeMap::new();
-
-        Ok(BuildConfiguration {
-            target_platform: host_platform.platform,
-            host_platform,
-            build_platform,
-            hash: HashInfo::from_variant(&variant, &recipe.build.noarch),
-            variant,
-            directories,
-            channels,
-            channel_priority: Default::default(),
-            solve_strategy: Default::default(),
-            timestamp: chrono::Utc::now(),
-            subpackages: Default::default(), // TODO: ???
-            packaging_settings: PackagingSettings::from_args(
-                ArchiveType::Conda,
-                CompressionLevel::default(),
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr};
+
+    use pixi_build_backend::{
+        config::ConfigOverrides, dependencies::BuiltSourcePackage, recipe_hash::recipe_hash,
+    };
+    use rattler_build::recipe::parser::{Dependency, Source};
+    use rattler_conda_types::Version;
+
+    use super::*;
+
+    /// Writes the manifest of a project that depends on zlib, with the given
+    /// `[tool.pixi-build]` configuration, to `directory` and reads it.
+    fn context(directory: &Path, config: &str) -> BackendContext {
+        let manifest_path = directory.join("pixi.toml");
+        std::fs::write(
+            &manifest_path,
+            format!(
+                r#"
+                [project]
+                name = "package"
+                version = "1.0.0"
+                channels = []
+                platforms = ["{platform}"]
+
+                [host-dependencies]
+                zlib = "*"
+
+                [tool.pixi-build]
+                {config}
+                "#,
+                platform = Platform::current()
             ),
-            store_recipe: false,
-            force_colors: true,
-        })
+        )
+        .unwrap();
+        BackendContext::new::<CMakeBuildBackend>(&manifest_path, &ConfigOverrides::default())
+            .unwrap()
     }
-}
 
-fn input_globs() -> Vec<String> {
-    [
-        // Source files
-        "**/*.{c,cc,cxx,cpp,h,hpp,hxx}",
-        // CMake files
-        "**/*.{cmake,cmake.in}",
-        "**/CMakeFiles.txt",
-    ]
-    .iter()
-    .map(|s| s.to_string())
-    .collect()
-}
+    /// Returns the recipe of the project in `directory`.
+    fn recipe(directory: &Path, config: &str) -> (BackendContext, Recipe) {
+        let context = context(directory, config);
+        let channel_config = ChannelConfig::default_with_root_dir(directory.to_path_buf());
+        let recipe = CMakeBuildBackend
+            .recipe(&context, Platform::current(), &channel_config)
+            .unwrap();
+        (context, recipe)
+    }
 
-#[async_trait::async_trait]
-impl Protocol for CMakeBuildBackend {
-    async fn get_conda_metadata(
-        &self,
-        params: CondaMetadataParams,
-    ) -> miette::Result<CondaMetadataResult> {
-        let channel_config = ChannelConfig {
-            channel_alias: params.channel_configuration.base_url,
-            root_dir: self.manifest.manifest_root().to_path_buf(),
-        };
-        let channels = match params.channel_base_urls {
-            Some(channels) => channels,
-            None => self
-                .manifest
-                .resolved_project_channels(&channel_config)
-                .into_diagnostic()
-                .context("failed to determine channels from the manifest")?,
-        };
+    /// Returns the names of the host requirements of the project with the
+    /// given configuration.
+    fn host_requirements(config: &str) -> Vec<String> {
+        let directory = tempfile::tempdir().unwrap();
+        let (_, recipe) = recipe(directory.path(), config);
+        recipe
+            .requirements
+            .host
+            .iter()
+            .filter_map(|dependency| match dependency {
+                Dependency::Spec(spec) => spec.name.as_ref(),
+                _ => None,
+            })
+            .map(|name| name.as_normalized().to_string())
+            .collect()
+    }
 
-        let host_platform = params
-            .host_platform
-            .as_ref()
-            .map(|p| p.platform)
-            .unwrap_or(Platform::current());
-        if !self.manifest.supports_target_platform(host_platform) {
-            miette::bail!("the project does not support the target platform ({host_platform})");
-        }
+    /// Returns the recipe hash of the project in `directory`.
+    fn hash(directory: &Path, config: &str) -> String {
+        let (context, recipe) = recipe(directory, config);
+        recipe_hash(
+            &recipe,
+            context.manifest_root(),
+            Platform::current(),
+            &context.variant,
+        )
+        .unwrap()
+    }
 
-        // TODO: Determine how and if we can determine this from the manifest.
-        let recipe = self.recipe(host_platform, &channel_config)?;
-        let output = Output {
-            build_configuration: self
-                .build_configuration(
-                    &recipe,
-                    channels,
-                    params.build_platform,
-                    params.host_platform,
-                    &params.work_directory,
-                )
-                .await?,
-            recipe,
-            finalized_dependencies: None,
-            finalized_cache_dependencies: None,
-            finalized_sources: None,
-            build_summary: Arc::default(),
-            system_tools: Default::default(),
-            extra_meta: None,
-        };
-        let tool_config = Configuration::builder()
-            .with_opt_cache_dir(self.cache_dir.clone())
-            .with_logging_output_handler(self.logging_output_handler.clone())
-            .with_channel_config(channel_config.clone())
-            .with_testing(false)
-            .with_keep_build(true)
-            .finish();
-
-        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
-        let output = temp_recipe
-            .within_context_async(move || async move {
-                output
-                    .resolve_dependencies(&tool_config)
-                    .await
-                    .into_diagnostic()
-            })
-            .await?;
-
-        let finalized_deps = &output
-            .finalized_dependencies
-            .as_ref()
-            .expect("dependencies should be resolved at this point")
-            .run;
-
-        Ok(CondaMetadataResult {
-            packages: vec![CondaPackageMetadata {
-                name: output.name().clone(),
-                version: output.version().clone().into(),
-                build: output.build_string().into_owned(),
-                build_number: output.recipe.build.number,
-                subdir: output.build_configuration.target_platform,
-                depends: finalized_deps
-                    .depends
-                    .iter()
-                    .map(DependencyInfo::spec)
-                    .map(MatchSpec::to_string)
-                    .collect(),
-                constraints: finalized_deps
-                    .constraints
-                    .iter()
-                    .map(DependencyInfo::spec)
-                    .map(MatchSpec::to_string)
-                    .collect(),
-                license: output.recipe.about.license.map(|l| l.to_string()),
-                license_family: output.recipe.about.license_family,
-                noarch: output.recipe.build.noarch,
-            }],
-            input_globs: None,
-        })
+    #[test]
+    fn build_tools_are_added_to_the_host_requirements() {
+        assert_eq!(host_requirements(""), ["cmake", "ninja", "zlib"]);
     }
 
-    async fn build_conda(&self, params: CondaBuildParams) -> miette::Result<CondaBuildResult> {
-        let channel_config = ChannelConfig {
-            channel_alias: params.channel_configuration.base_url,
-            root_dir: self.manifest.manifest_root().to_path_buf(),
-        };
-        let channels = match params.channel_base_urls {
-            Some(channels) => channels,
-            None => self
-                .manifest
-                .resolved_project_channels(&channel_config)
-                .into_diagnostic()
-                .context("failed to determine channels from the manifest")?,
-        };
-        let host_platform = params
-            .host_platform
-            .as_ref()
-            .map(|p| p.platform)
-            .unwrap_or_else(Platform::current);
-        if !self.manifest.supports_target_platform(host_platform) {
-            miette::bail!("the project does not support the target platform ({host_platform})");
+    #[test]
+    fn build_tools_can_be_skipped() {
+        assert_eq!(host_requirements("skip-build-tools = true"), ["zlib"]);
+    }
+
+    #[test]
+    fn checkouts_in_different_directories_have_the_same_hash() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let config = r#"activation-scripts = ["activate.sh"]"#;
+        for directory in [&first, &second] {
+            std::fs::write(directory.path().join("activate.sh"), "").unwrap();
         }
+        assert_eq!(hash(first.path(), config), hash(second.path(), config));
+    }
 
-        let recipe = self.recipe(host_platform, &channel_config)?;
-        let output = Output {
-            build_configuration: self
-                .build_configuration(
-                    &recipe,
-                    channels,
-                    params.host_platform.clone(),
-                    Some(PlatformAndVirtualPackages {
-                        platform: host_platform,
-                        virtual_packages: params.build_platform_virtual_packages,
-                    }),
-                    &params.work_directory,
-                )
-                .await?,
-            recipe,
-            finalized_dependencies: None,
-            finalized_cache_dependencies: None,
-            finalized_sources: None,
-            build_summary: Arc::default(),
-            system_tools: Default::default(),
-            extra_meta: None,
-        };
-        let tool_config = Configuration::builder()
-            .with_opt_cache_dir(self.cache_dir.clone())
-            .with_logging_output_handler(self.logging_output_handler.clone())
-            .with_channel_config(channel_config.clone())
-            .with_testing(false)
-            .with_keep_build(true)
-            .finish();
-
-        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
-        let (output, package) = temp_recipe
-            .within_context_async(move || async move { run_build(output, &tool_config).await })
-            .await?;
-
-        Ok(CondaBuildResult {
-            packages: vec![CondaBuiltPackage {
-                output_file: package,
-                input_globs: input_globs(),
-                name: output.name().as_normalized().to_string(),
-                version: output.version().to_string(),
-                build: output.build_string().into_owned(),
-                subdir: output.target_platform().to_string(),
-            }],
-        })
+    #[test]
+    fn the_job_count_does_not_change_the_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        assert_eq!(
+            hash(directory.path(), "jobs = 1"),
+            hash(directory.path(), "jobs = 8")
+        );
+        assert_ne!(
+            hash(directory.path(), "jobs = 1"),
+            hash(directory.path(), "build-number = 1")
+        );
     }
-}
 
-pub struct CMakeBuildBackendFactory {
-    logging_output_handler: LoggingOutputHandler,
-}
+    #[test]
+    fn only_used_variant_keys_change_the_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        let (mut context, recipe) = recipe(directory.path(), "");
+        let hash = |context: &BackendContext| {
+            recipe_hash(
+                &recipe,
+                context.manifest_root(),
+                Platform::current(),
+                &context.variant,
+            )
+            .unwrap()
+        };
+        let original = hash(&context);
 
-#[async_trait::async_trait]
-impl ProtocolFactory for CMakeBuildBackendFactory {
-    type Protocol = CMakeBuildBackend;
+        context
+            .variant
+            .insert("python".to_string(), "3.12".to_string());
+        assert_eq!(hash(&context), original);
 
-    async fn initialize(
-        &self,
-        params: InitializeParams,
-    ) -> miette::Result<(Self::Protocol, InitializeResult)> {
-        let instance = CMakeBuildBackend::new(
-            params.manifest_path.as_path(),
-            self.logging_output_handler.clone(),
-            params.cache_directory,
-        )?;
+        context
+            .variant
+            .insert("zlib".to_string(), "1.3".to_string());
+        assert_ne!(hash(&context), original);
+    }
+
+    #[test]
+    fn built_sibling_dependencies_are_pinned() {
+        let directory = tempfile::tempdir().unwrap();
+        let parent = directory.path().join("parent");
+        std::fs::create_dir(&parent).unwrap();
+        std::fs::create_dir(directory.path().join("child")).unwrap();
+        let manifest_path = parent.join("pixi.toml");
+        std::fs::write(
+            &manifest_path,
+            format!(
+                r#"
+                [project]
+                name = "parent"
+                version = "1.0.0"
+                channels = []
+                platforms = ["{platform}"]
+
+                [dependencies]
+                child = {{ path = "../child" }}
+                "#,
+                platform = Platform::current()
+            ),
+        )
+        .unwrap();
+        let mut context =
+            BackendContext::new::<CMakeBuildBackend>(&manifest_path, &ConfigOverrides::default())
+                .unwrap();
+        let channel_config = ChannelConfig::default_with_root_dir(parent.clone());
+
+        // The sibling has not been built yet.
+        assert!(CMakeBuildBackend
+            .recipe(&context, Platform::current(), &channel_config)
+            .is_err());
+
+        context.built_sources = vec![BuiltSourcePackage {
+            name: PackageName::new_unchecked("child"),
+            version: Version::from_str("1.2.3").unwrap(),
+            build: "h123_0".to_string(),
+        }];
+        let recipe = CMakeBuildBackend
+            .recipe(&context, Platform::current(), &channel_config)
+            .unwrap();
+        let run = recipe
+            .requirements
+            .run
+            .iter()
+            .map(|dependency| match dependency {
+                Dependency::Spec(spec) => spec.to_string(),
+                _ => panic!("expected a match spec"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(run, ["child ==1.2.3 h123_0"]);
+    }
 
-        let capabilities = instance.capabilites(&params.capabilities);
-        Ok((instance, InitializeResult { capabilities }))
+    #[test]
+    fn submodules_are_built_from_a_git_source() {
+        let directory = tempfile::tempdir().unwrap();
+        let context = context(directory.path(), "recurse-submodules = true");
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=pixi", "-c", "user.email=pixi@example.com"])
+                .args(args)
+                .current_dir(directory.path())
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {args:?}: {output:?}");
+        };
+        git(&["init", "-q"]);
+        git(&["add", "pixi.toml"]);
+        git(&["commit", "-qm", "package"]);
+
+        let channel_config = ChannelConfig::default_with_root_dir(directory.path().to_path_buf());
+        let recipe = CMakeBuildBackend
+            .recipe(&context, Platform::current(), &channel_config)
+            .unwrap();
+        assert!(
+            matches!(recipe.source.as_slice(), [Source::Git(_)]),
+            "{:?}",
+            recipe.source
+        );
+        let ScriptContent::Commands(commands) = &recipe.build.script.content else {
+            panic!("the build script is not a list of commands");
+        };
+        let src_dir = if Platform::current().is_windows() {
+            "-S \"%SRC_DIR%\""
+        } else {
+            "-S \"$SRC_DIR\""
+        };
+        assert!(
+            commands.iter().any(|command| command.contains(src_dir)),
+            "{commands:?}"
+        );
     }
 }
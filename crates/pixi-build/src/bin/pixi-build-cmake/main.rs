@@ -3,10 +3,13 @@ mod cmake;
 mod stub;
 
 use cmake::CMakeBuildBackend;
+use pixi_build_backend::backend::GenericBackend;
 
 #[tokio::main]
 pub async fn main() {
-    if let Err(err) = pixi_build_backend::cli::main(CMakeBuildBackend::factory).await {
+    if let Err(err) =
+        pixi_build_backend::cli::main(GenericBackend::<CMakeBuildBackend>::factory).await
+    {
         eprintln!("{err:?}");
         std::process::exit(1);
     }
@@ -1,10 +1,20 @@
 use minijinja::Environment;
+use pixi_build_backend::config::InstallMode;
 use serde::Serialize;
 
 #[derive(Serialize)]
 pub struct BuildScriptContext {
     pub installer: Installer,
+    /// Additional arguments that are passed to the installer.
+    pub installer_args: Vec<String>,
+    /// Whether to install directly from the sources or to build a wheel first.
+    pub install_mode: InstallMode,
     pub build_platform: BuildPlatform,
+    /// Absolute paths of the scripts to source before building.
+    pub activation_scripts: Vec<String>,
+    /// The directory to install from, or `None` to install from the copy of
+    /// the sources in the work directory.
+    pub source_dir: Option<String>,
 }
 
 #[derive(Default, Serialize)]
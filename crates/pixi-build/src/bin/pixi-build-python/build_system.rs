@@ -0,0 +1,104 @@
+//! Support for the `[build-system]` table of a `pyproject.toml`.
+
+use std::path::Path;
+
+use miette::{Context, IntoDiagnostic};
+use rattler_conda_types::PackageName;
+
+/// Returns the requirement in `build-system.requires` of the
+/// `pyproject.toml` in `source_dir` that refers to the package `name` itself,
+/// if any. Such a package bootstraps itself, e.g. a build backend that is
+/// used to build its own package.
+pub(crate) fn self_build_requirement(
+    source_dir: &Path,
+    name: &PackageName,
+) -> miette::Result<Option<String>> {
+    let path = source_dir.join("pyproject.toml");
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path)
+        .into_diagnostic()
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let document: toml::Table = toml::from_str(&contents)
+        .into_diagnostic()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let name = normalize(name.as_normalized());
+    let requirement = document
+        .get("build-system")
+        .and_then(|build_system| build_system.get("requires"))
+        .and_then(|requires| requires.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|requirement| requirement.as_str())
+        .find(|requirement| normalize(requirement_name(requirement)) == name);
+    Ok(requirement.map(str::to_string))
+}
+
+/// Returns the name of a PEP 508 requirement, e.g. `setuptools` for
+/// `setuptools >= 61; python_version >= "3.8"`.
+fn requirement_name(requirement: &str) -> &str {
+    let requirement = requirement.trim_start();
+    let end = requirement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        .unwrap_or(requirement.len());
+    &requirement[..end]
+}
+
+/// Normalizes the name of a python package as described in PEP 503, so
+/// `My_Package` and `my-package` compare equal.
+fn normalize(name: &str) -> String {
+    name.split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a `pyproject.toml` with the given build requirements and
+    /// returns the requirement that refers to `name`.
+    fn self_requirement(requires: &str, name: &str) -> Option<String> {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("pyproject.toml"),
+            format!("[build-system]\nrequires = {requires}\n"),
+        )
+        .unwrap();
+        self_build_requirement(directory.path(), &PackageName::new_unchecked(name)).unwrap()
+    }
+
+    #[test]
+    fn finds_the_requirement_on_the_package_itself() {
+        assert_eq!(
+            self_requirement(r#"["wheel", "Flit_Core >=3.2, <4"]"#, "flit-core"),
+            Some("Flit_Core >=3.2, <4".to_string())
+        );
+        assert_eq!(
+            self_requirement(r#"["hatchling; python_version >= '3.8'"]"#, "hatchling"),
+            Some("hatchling; python_version >= '3.8'".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_other_requirements() {
+        assert_eq!(
+            self_requirement(r#"["setuptools-scm", "setuptools_rust"]"#, "setuptools"),
+            None
+        );
+    }
+
+    #[test]
+    fn sources_without_a_pyproject_have_no_requirement() {
+        let directory = tempfile::tempdir().unwrap();
+        let name = PackageName::new_unchecked("package");
+        assert_eq!(
+            self_build_requirement(directory.path(), &name).unwrap(),
+            None
+        );
+    }
+}
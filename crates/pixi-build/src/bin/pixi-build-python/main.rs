@@ -1,11 +1,15 @@
 mod build_script;
+mod build_system;
 mod python;
 
+use pixi_build_backend::backend::GenericBackend;
 use python::PythonBuildBackend;
 
 #[tokio::main]
 pub async fn main() {
-    if let Err(err) = pixi_build_backend::cli::main(PythonBuildBackend::factory).await {
+    if let Err(err) =
+        pixi_build_backend::cli::main(GenericBackend::<PythonBuildBackend>::factory).await
+    {
         eprintln!("{err:?}");
         std::process::exit(1);
     }
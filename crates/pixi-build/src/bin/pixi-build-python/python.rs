@@ -1,208 +1,211 @@
-use std::{
-    collections::BTreeMap,
-    path::{Path, PathBuf},
-    str::FromStr,
-    sync::Arc,
-};
-
-use chrono::Utc;
-use miette::{Context, IntoDiagnostic};
 use pixi_build_backend::{
-    dependencies::MatchspecExtractor,
+    backend::{BackendContext, BuildBackend, ManifestDependencies},
+    config::{InstallMode, NoArch, SourceMode, VersionSource},
+    dependencies::{add_host_tools, add_run_tool},
+    entry_points::python_entry_points,
     manifest_ext::ManifestExt,
-    protocol::{Protocol, ProtocolFactory},
-    utils::TemporaryRenderedRecipe,
-};
-use pixi_build_types::{
-    procedures::{
-        conda_build::{CondaBuildParams, CondaBuildResult, CondaBuiltPackage},
-        conda_metadata::{CondaMetadataParams, CondaMetadataResult},
-        initialize::{InitializeParams, InitializeResult},
-    },
-    BackendCapabilities, CondaPackageMetadata, FrontendCapabilities, PlatformAndVirtualPackages,
+    variants,
 };
-use pixi_manifest::{Dependencies, Manifest, SpecType};
-use pixi_spec::PixiSpec;
 use rattler_build::{
-    build::run_build,
-    console_utils::LoggingOutputHandler,
-    hash::HashInfo,
-    metadata::{
-        BuildConfiguration, Directories, Output, PackagingSettings, PlatformWithVirtualPackages,
-    },
+    metadata::Output,
     recipe::{
-        parser::{Build, Dependency, Package, PathSource, Requirements, ScriptContent, Source},
+        parser::{PathSource, Python, Requirements, ScriptContent, Source},
         Recipe,
     },
-    render::resolved_dependencies::DependencyInfo,
-    tool_configuration::Configuration,
-};
-use rattler_conda_types::{
-    package::ArchiveType, ChannelConfig, MatchSpec, NoArchType, PackageName, Platform,
 };
-use rattler_package_streaming::write::CompressionLevel;
-use rattler_virtual_packages::VirtualPackageOverrides;
-use reqwest::Url;
+use rattler_conda_types::{ChannelConfig, NoArchType, Platform};
 
-use crate::build_script::{BuildPlatform, BuildScriptContext, Installer};
+use crate::{
+    build_script::{BuildPlatform, BuildScriptContext, Installer},
+    build_system::self_build_requirement,
+};
 
-pub struct PythonBuildBackend {
-    logging_output_handler: LoggingOutputHandler,
-    manifest: Manifest,
-    cache_dir: Option<PathBuf>,
-}
+/// The keys of the `[tool.pixi-build]` table that are supported by this
+/// backend.
+const CONFIG_KEYS: &[&str] = &[
+    "activation-scripts",
+    "build-number",
+    "build-string",
+    "check-relocatability",
+    "dynamic-linking",
+    "entry-points",
+    "environment",
+    "environment-file",
+    "files",
+    "host-prefix",
+    "host-prefix-length",
+    "info-files",
+    "install-mode",
+    "installer-args",
+    "keep-self-references",
+    "license-url",
+    "merge-build-and-host-envs",
+    "metapackage",
+    "noarch",
+    "output-channel",
+    "pin-compatible",
+    "platform-output",
+    "post-build-hook",
+    "post-process",
+    "prefix-detection",
+    "recurse-submodules",
+    "repodata",
+    "run-exports",
+    "sbom",
+    "schema-version",
+    "script-env",
+    "skip-build-tools",
+    "snapshot-environments",
+    "source-dir",
+    "source-mode",
+    "store-recipe",
+    "test",
+    "tool-options",
+    "variant",
+    "version-source",
+];
+
+/// The build tools that the backend adds to the requirements if they are not
+/// declared in the manifest.
+const BUILD_TOOLS: &[&str] = &["pip", "uv", "python"];
+
+/// Builds python packages by installing them into the host prefix with pip
+/// or uv.
+///
+/// Data files are handled by the build backend of the package (e.g.
+/// setuptools or hatchling). Package data that is declared for a module ends
+/// up in `site-packages` next to the module, and `data_files` end up relative
+/// to the prefix (e.g. `share/`). For `noarch: python` packages rattler-build
+/// relocates `site-packages` and the entry points, all other files are kept
+/// at their location in the prefix. Files that are not declared as package
+/// data (e.g. through `MANIFEST.in` or `include_package_data`) are not
+/// installed and therefore not part of the package, and `data_files` with an
+/// absolute path are not supported.
+#[derive(Default)]
+pub struct PythonBuildBackend;
 
 impl PythonBuildBackend {
-    /// Returns a new instance of [`PythonBuildBackendFactory`].
-    ///
-    /// This type implements [`ProtocolFactory`] and can be used to initialize a
-    /// new [`PythonBuildBackend`].
-    pub fn factory(logging_output_handler: LoggingOutputHandler) -> PythonBuildBackendFactory {
-        PythonBuildBackendFactory {
-            logging_output_handler,
-        }
-    }
-
-    /// Returns a new instance of [`PythonBuildBackend`] by reading the manifest
-    /// at the given path.
-    pub fn new(
-        manifest_path: &Path,
-        logging_output_handler: LoggingOutputHandler,
-        cache_dir: Option<PathBuf>,
-    ) -> miette::Result<Self> {
-        // Load the manifest from the source directory
-        let manifest = Manifest::from_path(manifest_path).with_context(|| {
-            format!("failed to parse manifest from {}", manifest_path.display())
-        })?;
-
-        Ok(Self {
-            manifest,
-            logging_output_handler,
-            cache_dir,
-        })
-    }
-
-    /// Returns the capabilities of this backend based on the capabilities of
-    /// the frontend.
-    pub fn capabilites(
-        &self,
-        _frontend_capabilities: &FrontendCapabilities,
-    ) -> BackendCapabilities {
-        BackendCapabilities {
-            provides_conda_metadata: Some(true),
-            provides_conda_build: Some(true),
-        }
+    /// Returns the noarch type of the package, which defaults to
+    /// `noarch: python`.
+    fn noarch_type(&self, context: &BackendContext) -> miette::Result<NoArchType> {
+        context.config.noarch_type(
+            NoArch::Python,
+            &[NoArch::Python, NoArch::Generic, NoArch::None],
+        )
     }
 
     /// Returns the requirements of the project that should be used for a
-    /// recipe.
+    /// recipe, and the installer that installs the package.
     fn requirements(
         &self,
+        context: &BackendContext,
         host_platform: Platform,
         channel_config: &ChannelConfig,
     ) -> miette::Result<(Requirements, Installer)> {
-        let mut requirements = Requirements::default();
-        let default_features = [self.manifest.default_feature()];
-
-        // Get all different feature types
-        let run_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Run, Some(host_platform))),
-        );
-        let mut host_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Host, Some(host_platform))),
-        );
-        let build_dependencies = Dependencies::from(
-            default_features
-                .iter()
-                .filter_map(|f| f.dependencies(SpecType::Build, Some(host_platform))),
-        );
+        let ManifestDependencies {
+            build,
+            mut host,
+            mut run,
+        } = context.dependencies(host_platform)?;
 
         // Determine the installer to use
-        let installer = if host_dependencies.contains_key("uv")
-            || run_dependencies.contains_key("uv")
-            || build_dependencies.contains_key("uv")
-        {
-            Installer::Uv
-        } else {
-            Installer::Pip
-        };
-
-        // Ensure python and pip are available in the host dependencies section.
-        for pkg_name in [installer.package_name(), "python"] {
-            if host_dependencies.contains_key(pkg_name) {
-                // If the host dependencies already contain the package, we don't need to add it
-                // again.
-                continue;
-            }
-
-            if let Some(run_requirements) = run_dependencies.get(pkg_name) {
-                // Copy the run requirements to the host requirements.
-                for req in run_requirements {
-                    host_dependencies.insert(PackageName::from_str(pkg_name).unwrap(), req.clone());
-                }
+        let installer =
+            if host.contains_key("uv") || run.contains_key("uv") || build.contains_key("uv") {
+                Installer::Uv
             } else {
-                host_dependencies.insert(
-                    PackageName::from_str(pkg_name).unwrap(),
-                    PixiSpec::default(),
-                );
+                Installer::Pip
+            };
+
+        if !context.config.skip_build_tools {
+            // Ensure python and the installer are available in the host dependencies
+            // section. No other run dependency ends up in the host environment.
+            add_host_tools(&mut host, &run, &[installer.package_name(), "python"]);
+
+            // A noarch python package is installed into any python environment, so
+            // it has to carry the python requirement itself. Otherwise a lower
+            // bound that is only declared as a host dependency would be lost.
+            if self.noarch_type(context)?.is_python() {
+                add_run_tool(&mut run, &host, "python");
             }
         }
 
-        requirements.build = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(build_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-        requirements.host = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(host_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-        requirements.run = MatchspecExtractor::new(channel_config.clone())
-            .with_ignore_self(true)
-            .extract(run_dependencies)?
-            .into_iter()
-            .map(Dependency::Spec)
-            .collect();
-
+        let requirements = context.requirements(
+            ManifestDependencies { build, host, run },
+            channel_config,
+            vec![],
+        )?;
         Ok((requirements, installer))
     }
+}
+
+impl BuildBackend for PythonBuildBackend {
+    const CONFIG_KEYS: &'static [&'static str] = CONFIG_KEYS;
+    const BUILD_TOOLS: &'static [&'static str] = BUILD_TOOLS;
+    const DEFAULT_NOARCH: NoArch = NoArch::Python;
+    const REUSES_BUILD_DIR: bool = false;
+    const KEEP_BUILD: bool = false;
+    // The python version of the variant is only known after the solve.
+    const SOLVE_BEFORE_BUILD: bool = true;
 
-    /// Constructs a [`Recipe`] from the current manifest.
     fn recipe(
         &self,
+        context: &BackendContext,
         host_platform: Platform,
         channel_config: &ChannelConfig,
     ) -> miette::Result<Recipe> {
-        let manifest_root = self
-            .manifest
-            .path
-            .parent()
-            .expect("the project manifest must reside in a directory");
-
-        // Parse the package name from the manifest
-        let Some(name) = self.manifest.parsed.project.name.clone() else {
-            miette::bail!("a 'name' field is required in the project manifest");
-        };
-        let name = PackageName::from_str(&name).into_diagnostic()?;
-        let version = self.manifest.version_or_default().clone();
+        let config = &context.config;
+        let manifest_root = context.manifest_root();
+        let name = context.manifest.package_name()?;
 
-        // TODO: NoArchType???
-        let noarch_type = NoArchType::python();
+        let noarch_type = self.noarch_type(context)?;
 
         // TODO: Read from config / project.
-        let (requirements, installer) = self.requirements(host_platform, channel_config)?;
+        let (requirements, installer) =
+            self.requirements(context, host_platform, channel_config)?;
         let build_platform = Platform::current();
-        let build_number = 0;
+
+        let source_root = config.source_dir(manifest_root)?;
+        let merge_build_and_host_envs = match config.merge_build_and_host_envs {
+            Some(merge_build_and_host_envs) => merge_build_and_host_envs,
+            None => match self_build_requirement(&source_root, &name)? {
+                Some(requirement) => {
+                    tracing::warn!(
+                        "the package requires itself to build (`{requirement}` in `build-system.requires`), the build and host environments are merged so the build can use the package from the host environment. Set `merge-build-and-host-envs` to override this"
+                    );
+                    true
+                }
+                None => false,
+            },
+        };
+
+        // Either clone the sources with their submodules, copy them into the work
+        // directory or build directly from the source directory.
+        let (source, source_dir) = match (context.submodule_source()?, config.source_mode) {
+            (Some(source), _) => (
+                vec![source.source.clone()],
+                Some(source.source_dir(build_platform)),
+            ),
+            (None, SourceMode::Copy) => (
+                vec![Source::Path(PathSource {
+                    path: source_root,
+                    sha256: None,
+                    md5: None,
+                    patches: vec![],
+                    target_directory: None,
+                    file_name: None,
+                    use_gitignore: true,
+                })],
+                None,
+            ),
+            (None, SourceMode::InPlace) => (vec![], Some(source_root.display().to_string())),
+        };
 
         let build_script = BuildScriptContext {
             installer,
+            installer_args: config.installer_args()?.to_vec(),
+            install_mode: config.install_mode,
+            source_dir,
+            activation_scripts: context.activation_scripts()?,
             build_platform: if build_platform.is_windows() {
                 BuildPlatform::Windows
             } else {
@@ -211,350 +214,269 @@ impl PythonBuildBackend {
         }
         .render();
 
-        Ok(Recipe {
-            schema_version: 1,
-            package: Package {
-                version: version.into(),
-                name,
-            },
-            context: Default::default(),
-            cache: None,
-            source: vec![Source::Path(PathSource {
-                // TODO: How can we use a git source?
-                path: manifest_root.to_path_buf(),
-                sha256: None,
-                md5: None,
-                patches: vec![],
-                target_directory: None,
-                file_name: None,
-                use_gitignore: true,
-            })],
-            build: Build {
-                number: build_number,
-                string: Default::default(),
-
-                // skip: Default::default(),
-                script: ScriptContent::Commands(build_script).into(),
-                noarch: noarch_type,
-
-                // TODO: Python is not exposed properly
-                //python: Default::default(),
-                // dynamic_linking: Default::default(),
-                // always_copy_files: Default::default(),
-                // always_include_files: Default::default(),
-                // merge_build_and_host_envs: false,
-                // variant: Default::default(),
-                // prefix_detection: Default::default(),
-                // post_process: vec![],
-                // files: Default::default(),
-                ..Build::default()
-            },
-            // TODO read from manifest
-            requirements,
-            tests: vec![],
-            about: Default::default(),
-            extra: Default::default(),
-        })
+        let mut script = config
+            .script_env
+            .script(ScriptContent::Commands(build_script));
+        if config.version_source == VersionSource::GitTag {
+            // setuptools-scm and versioneer compute the version themselves, which
+            // fails if the sources are copied without the git metadata. Make sure
+            // they use the version of the package.
+            script.env.insert(
+                "SETUPTOOLS_SCM_PRETEND_VERSION".to_string(),
+                context.version.to_string(),
+            );
+        }
+
+        let mut recipe = context.recipe(noarch_type, requirements, source, script)?;
+        recipe.build.python = Python {
+            entry_points: python_entry_points(&config.entry_points)?,
+            ..Python::default()
+        };
+        recipe.build.merge_build_and_host_envs = merge_build_and_host_envs;
+        Ok(recipe)
     }
 
-    /// Returns the build configuration for a recipe
-    pub async fn build_configuration(
+    /// Determines the build input globs for given python package
+    /// even this will be probably backend specific, e.g setuptools
+    /// has a different way of determining the input globs than hatch etc.
+    ///
+    /// However, lets take everything in the directory as input for now
+    fn input_globs(&self) -> Vec<String> {
+        vec![
+            // Source files
+            "**/*.py",
+            "**/*.pyx",
+            "**/*.c",
+            "**/*.cpp",
+            "**/*.sh",
+            // Common data files
+            "**/*.json",
+            "**/*.yaml",
+            "**/*.yml",
+            "**/*.txt",
+            "**/*.toml",
+            "**/*.cfg",
+            "**/*.ini",
+            "**/*.csv",
+            "**/py.typed",
+            // Project configuration
+            "setup.py",
+            "setup.cfg",
+            "pyproject.toml",
+            "requirements*.txt",
+            "Pipfile",
+            "Pipfile.lock",
+            "poetry.lock",
+            "tox.ini",
+            // Build configuration
+            "Makefile",
+            "MANIFEST.in",
+            "tests/**/*.py",
+            "docs/**/*.rst",
+            "docs/**/*.md",
+            // Versioning
+            "VERSION",
+            "version.py",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    fn installer(
         &self,
-        recipe: &Recipe,
-        channels: Vec<Url>,
-        build_platform: Option<PlatformAndVirtualPackages>,
-        host_platform: Option<PlatformAndVirtualPackages>,
-        work_directory: &Path,
-    ) -> miette::Result<BuildConfiguration> {
-        // Parse the package name from the manifest
-        let Some(name) = self.manifest.parsed.project.name.clone() else {
-            miette::bail!("a 'name' field is required in the project manifest");
-        };
-        let name = PackageName::from_str(&name).into_diagnostic()?;
-
-        std::fs::create_dir_all(work_directory)
-            .into_diagnostic()
-            .context("failed to create output directory")?;
-        let directories = Directories::setup(
-            name.as_normalized(),
-            self.manifest.path.as_path(),
-            work_directory,
-            true,
-            &Utc::now(),
-        )
-        .into_diagnostic()
-        .context("failed to setup build directories")?;
-
-        let build_platform = build_platform.map(|p| PlatformWithVirtualPackages {
-            platform: p.platform,
-            virtual_packages: p.virtual_packages.unwrap_or_default(),
-        });
-
-        let host_platform = host_platform.map(|p| PlatformWithVirtualPackages {
-            platform: p.platform,
-            virtual_packages: p.virtual_packages.unwrap_or_default(),
-        });
-
-        let (build_platform, host_platform) = match (build_platform, host_platform) {
-            (Some(build_platform), Some(host_platform)) => (build_platform, host_platform),
-            (build_platform, host_platform) => {
-                let current_platform =
-                    rattler_build::metadata::PlatformWithVirtualPackages::detect(
-                        &VirtualPackageOverrides::from_env(),
-                    )
-                    .into_diagnostic()?;
-                (
-                    build_platform.unwrap_or_else(|| current_platform.clone()),
-                    host_platform.unwrap_or(current_platform),
-                )
-            }
-        };
+        context: &BackendContext,
+        host_platform: Platform,
+        channel_config: &ChannelConfig,
+    ) -> miette::Result<Option<String>> {
+        let (_, installer) = self.requirements(context, host_platform, channel_config)?;
+        Ok(Some(installer.package_name().to_string()))
+    }
 
-        let variant = BTreeMap::new();
-
-        Ok(BuildConfiguration {
-            // TODO: NoArch??
-            target_platform: Platform::NoArch,
-            host_platform,
-            build_platform,
-            hash: HashInfo::from_variant(&variant, &recipe.build.noarch),
-            variant,
-            directories,
-            channels,
-            channel_priority: Default::default(),
-            solve_strategy: Default::default(),
-            timestamp: chrono::Utc::now(),
-            subpackages: Default::default(), // TODO: ???
-            packaging_settings: PackagingSettings::from_args(
-                ArchiveType::Conda,
-                CompressionLevel::default(),
-            ),
-            store_recipe: false,
-            force_colors: true,
-        })
+    fn finalize_output(&self, output: &mut Output) {
+        variants::add_python_variant(output);
     }
-}
 
-/// Determines the build input globs for given python package
-/// even this will be probably backend specific, e.g setuptools
-/// has a different way of determining the input globs than hatch etc.
-///
-/// However, lets take everything in the directory as input for now
-fn input_globs() -> Vec<String> {
-    vec![
-        // Source files
-        "**/*.py",
-        "**/*.pyx",
-        "**/*.c",
-        "**/*.cpp",
-        "**/*.sh",
-        // Common data files
-        "**/*.json",
-        "**/*.yaml",
-        "**/*.yml",
-        "**/*.txt",
-        // Project configuration
-        "setup.py",
-        "setup.cfg",
-        "pyproject.toml",
-        "requirements*.txt",
-        "Pipfile",
-        "Pipfile.lock",
-        "poetry.lock",
-        "tox.ini",
-        // Build configuration
-        "Makefile",
-        "MANIFEST.in",
-        "tests/**/*.py",
-        "docs/**/*.rst",
-        "docs/**/*.md",
-        // Versioning
-        "VERSION",
-        "version.py",
-    ]
-    .iter()
-    .map(|s| s.to_string())
-    .collect()
+    fn after_build(&self, context: &BackendContext, output: &Output) {
+        if context.config.install_mode == InstallMode::Wheel {
+            let wheel_dir = output
+                .build_configuration
+                .directories
+                .build_dir
+                .join("wheels");
+            if wheel_dir.is_dir() {
+                tracing::info!(
+                    "the wheel of the package is kept in {}",
+                    wheel_dir.display()
+                );
+            }
+        }
+    }
 }
 
-#[async_trait::async_trait]
-impl Protocol for PythonBuildBackend {
-    async fn get_conda_metadata(
-        &self,
-        params: CondaMetadataParams,
-    ) -> miette::Result<CondaMetadataResult> {
-        let channel_config = ChannelConfig {
-            channel_alias: params.channel_configuration.base_url,
-            root_dir: self.manifest.manifest_root().to_path_buf(),
-        };
-        let channels = match params.channel_base_urls {
-            Some(channels) => channels,
-            None => self
-                .manifest
-                .resolved_project_channels(&channel_config)
-                .into_diagnostic()
-                .context("failed to determine channels from the manifest")?,
-        };
+#[cfg(test)]
+mod tests {
+    use globset::{Glob, GlobSet, GlobSetBuilder};
+    use jsonrpc_core::serde_json::{self, json};
+    use pixi_build_backend::{
+        backend::GenericBackend, conda_metadata::CondaMetadataRequest, config::ConfigOverrides,
+        protocol::Protocol,
+    };
+    use pixi_build_types::procedures::conda_metadata::CondaPackageMetadata;
+    use rattler_build::console_utils::LoggingOutputHandler;
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+
+    /// Returns the input globs of the backend as a glob set.
+    fn input_globs() -> GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        for glob in PythonBuildBackend.input_globs() {
+            builder.add(Glob::new(&glob).unwrap());
+        }
+        builder.build().unwrap()
+    }
 
-        let host_platform = params
-            .host_platform
-            .as_ref()
-            .map(|p| p.platform)
-            .unwrap_or(Platform::current());
-        if !self.manifest.supports_target_platform(host_platform) {
-            miette::bail!("the project does not support the target platform ({host_platform})");
+    #[test]
+    fn package_data_files_are_inputs() {
+        let globs = input_globs();
+        for path in [
+            "src/package/__init__.py",
+            "src/package/data/schema.json",
+            "src/package/data/defaults.yaml",
+            "src/package/templates/config.toml",
+            "src/package/py.typed",
+            "pyproject.toml",
+        ] {
+            assert!(globs.is_match(path), "{path}");
         }
+    }
 
-        // TODO: Determine how and if we can determine this from the manifest.
-        let recipe = self.recipe(host_platform, &channel_config)?;
-        let output = Output {
-            build_configuration: self
-                .build_configuration(
-                    &recipe,
-                    channels,
-                    params.build_platform,
-                    params.host_platform,
-                    &params.work_directory,
-                )
-                .await?,
-            recipe,
-            finalized_dependencies: None,
-            finalized_cache_dependencies: None,
-            finalized_sources: None,
-            build_summary: Arc::default(),
-            system_tools: Default::default(),
-            extra_meta: None,
-        };
-        let tool_config = Configuration::builder()
-            .with_opt_cache_dir(self.cache_dir.clone())
-            .with_logging_output_handler(self.logging_output_handler.clone())
-            .with_channel_config(channel_config.clone())
-            .with_testing(false)
-            .finish();
-
-        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
-        let output = temp_recipe
-            .within_context_async(move || async move {
-                output
-                    .resolve_dependencies(&tool_config)
-                    .await
-                    .into_diagnostic()
-            })
-            .await?;
-
-        let finalized_deps = &output
-            .finalized_dependencies
-            .as_ref()
-            .expect("dependencies should be resolved at this point")
-            .run;
-
-        Ok(CondaMetadataResult {
-            packages: vec![CondaPackageMetadata {
-                name: output.name().clone(),
-                version: output.version().clone().into(),
-                build: output.build_string().into_owned(),
-                build_number: output.recipe.build.number,
-                subdir: output.build_configuration.target_platform,
-                depends: finalized_deps
-                    .depends
-                    .iter()
-                    .map(DependencyInfo::spec)
-                    .map(MatchSpec::to_string)
-                    .collect(),
-                constraints: finalized_deps
-                    .constraints
-                    .iter()
-                    .map(DependencyInfo::spec)
-                    .map(MatchSpec::to_string)
-                    .collect(),
-                license: output.recipe.about.license.map(|l| l.to_string()),
-                license_family: output.recipe.about.license_family,
-                noarch: output.recipe.build.noarch,
-            }],
-            input_globs: None,
-        })
+    #[test]
+    fn build_artifacts_are_not_inputs() {
+        let globs = input_globs();
+        for path in [
+            "dist/package-1.0-py3-none-any.whl",
+            "src/package.egg-info/PKG-INFO",
+        ] {
+            assert!(!globs.is_match(path), "{path}");
+        }
     }
 
-    async fn build_conda(&self, params: CondaBuildParams) -> miette::Result<CondaBuildResult> {
-        let channel_config = ChannelConfig {
-            channel_alias: params.channel_configuration.base_url,
-            root_dir: self.manifest.manifest_root().to_path_buf(),
-        };
-        let channels = match params.channel_base_urls {
-            Some(channels) => channels,
-            None => self
-                .manifest
-                .resolved_project_channels(&channel_config)
-                .into_diagnostic()
-                .context("failed to determine channels from the manifest")?,
-        };
+    /// Returns the metadata of the packages that `conda/getMetadata` reports
+    /// without resolving the dependencies, for a project with build number 3
+    /// and the given `[tool.pixi-build]` configuration. `build_number` is the
+    /// build number of the request.
+    async fn no_deps_packages(
+        config: &str,
+        build_number: Option<u64>,
+    ) -> Vec<CondaPackageMetadata> {
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_path = directory.path().join("pixi.toml");
+        std::fs::write(
+            &manifest_path,
+            format!(
+                r#"
+                [project]
+                name = "package"
+                version = "1.0.0"
+                channels = []
+                platforms = ["{platform}"]
+
+                [host-dependencies]
+                hatchling = "*"
+
+                [tool.pixi-build]
+                build-number = 3
+                {config}
+                "#,
+                platform = Platform::current()
+            ),
+        )
+        .unwrap();
+        let backend = GenericBackend::<PythonBuildBackend>::new(
+            &manifest_path,
+            LoggingOutputHandler::default(),
+            None,
+            &ConfigOverrides::default(),
+        )
+        .unwrap();
+        let request: CondaMetadataRequest = serde_json::from_value(json!({
+            "hostPlatform": { "platform": Platform::current(), "virtualPackages": [] },
+            "buildPlatform": { "platform": Platform::current(), "virtualPackages": [] },
+            "channelBaseUrls": ["https://prefix.dev/conda-forge"],
+            "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+            "workDirectory": directory.path().join("work"),
+            "noDepsMetadata": true,
+            "buildNumber": build_number,
+        }))
+        .unwrap();
+        let response = backend
+            .get_conda_metadata(request, CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(!response.dependencies_resolved);
+        response.result.packages
+    }
 
-        let host_platform = params
-            .host_platform
-            .as_ref()
-            .map(|p| p.platform)
-            .unwrap_or_else(Platform::current);
-        if !self.manifest.supports_target_platform(host_platform) {
-            miette::bail!("the project does not support the target platform ({host_platform})");
-        }
+    /// Returns the metadata of the package of [`no_deps_packages`].
+    async fn no_deps_metadata(config: &str, build_number: Option<u64>) -> CondaPackageMetadata {
+        let mut packages = no_deps_packages(config, build_number).await;
+        assert_eq!(packages.len(), 1);
+        packages.remove(0)
+    }
 
-        let recipe = self.recipe(host_platform, &channel_config)?;
-        let output = Output {
-            build_configuration: self
-                .build_configuration(&recipe, channels, None, None, &params.work_directory)
-                .await?,
-            recipe,
-            finalized_dependencies: None,
-            finalized_cache_dependencies: None,
-            finalized_sources: None,
-            build_summary: Arc::default(),
-            system_tools: Default::default(),
-            extra_meta: None,
-        };
-        let tool_config = Configuration::builder()
-            .with_opt_cache_dir(self.cache_dir.clone())
-            .with_logging_output_handler(self.logging_output_handler.clone())
-            .with_channel_config(channel_config.clone())
-            .with_testing(false)
-            .finish();
-
-        let temp_recipe = TemporaryRenderedRecipe::from_output(&output)?;
-        let (output, package) = temp_recipe
-            .within_context_async(move || async move { run_build(output, &tool_config).await })
-            .await?;
-
-        Ok(CondaBuildResult {
-            packages: vec![CondaBuiltPackage {
-                output_file: package,
-                input_globs: input_globs(),
-                name: output.name().as_normalized().to_string(),
-                version: output.version().to_string(),
-                build: output.build_string().into_owned(),
-                subdir: output.target_platform().to_string(),
-            }],
-        })
+    /// Returns the build string of [`no_deps_metadata`] without a build number
+    /// in the request.
+    async fn no_deps_build_string(config: &str) -> String {
+        no_deps_metadata(config, None).await.build
     }
-}
 
-pub struct PythonBuildBackendFactory {
-    logging_output_handler: LoggingOutputHandler,
-}
+    #[tokio::test]
+    async fn build_string_is_applied_without_resolving_the_dependencies() {
+        assert_eq!(
+            no_deps_build_string(r#"build-string = "custom_{build_number}""#).await,
+            "custom_3"
+        );
+    }
+
+    #[tokio::test]
+    async fn build_string_with_unresolved_variant_keys_is_applied_later() {
+        assert_eq!(
+            no_deps_build_string(r#"build-string = "py{python}_{build_number}""#).await,
+            no_deps_build_string("").await
+        );
+    }
 
-#[async_trait::async_trait]
-impl ProtocolFactory for PythonBuildBackendFactory {
-    type Protocol = PythonBuildBackend;
+    #[tokio::test]
+    async fn build_number_of_the_request_takes_precedence() {
+        let config = r#"build-string = "custom_{build_number}""#;
+        let metadata = no_deps_metadata(config, Some(7)).await;
+        assert_eq!(metadata.build_number, 7);
+        assert_eq!(metadata.build, "custom_7");
 
-    async fn initialize(
-        &self,
-        params: InitializeParams,
-    ) -> miette::Result<(Self::Protocol, InitializeResult)> {
-        let instance = PythonBuildBackend::new(
-            params.manifest_path.as_path(),
-            self.logging_output_handler.clone(),
-            params.cache_directory,
-        )?;
+        let metadata = no_deps_metadata(config, None).await;
+        assert_eq!(metadata.build_number, 3);
+    }
+
+    #[tokio::test]
+    async fn platform_outputs_are_separate_packages() {
+        let packages = no_deps_packages(
+            r#"platform-output = { name = "package-helper", files = ["bin/helper*"] }"#,
+            None,
+        )
+        .await;
+        let [helper, package] = packages.as_slice() else {
+            panic!("expected two packages: {packages:?}");
+        };
+
+        assert_eq!(helper.name.as_normalized(), "package-helper");
+        assert_eq!(helper.subdir, Platform::current());
+        assert!(helper.noarch.is_none());
+        assert_eq!(helper.version, package.version);
 
-        let capabilities = instance.capabilites(&params.capabilities);
-        Ok((instance, InitializeResult { capabilities }))
+        assert_eq!(package.name.as_normalized(), "package");
+        assert_eq!(package.subdir, Platform::NoArch);
+        assert_eq!(package.noarch, NoArchType::python());
     }
 }
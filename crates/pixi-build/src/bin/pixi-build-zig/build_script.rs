@@ -0,0 +1,62 @@
+use minijinja::Environment;
+use pixi_build_backend::entry_points::CommandEntryPoint;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct BuildScriptContext {
+    pub build_platform: BuildPlatform,
+    /// Absolute paths of the scripts to source before building.
+    pub activation_scripts: Vec<String>,
+    pub source_dir: String,
+    /// Wrappers that are written to `bin` after the package is installed.
+    pub entry_points: Vec<CommandEntryPoint>,
+    /// The zig cache directory, or `None` to use a directory next to the
+    /// source directory of the build.
+    pub cache_dir: Option<String>,
+    /// The zig target triple to compile for, or `None` to compile for the
+    /// native target.
+    pub target: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildPlatform {
+    Windows,
+    Unix,
+}
+
+impl BuildScriptContext {
+    pub fn render(&self) -> Vec<String> {
+        let env = Environment::new();
+        let template = env
+            .template_from_str(include_str!("build_script.j2"))
+            .unwrap();
+        let rendered = template.render(self).unwrap().to_string();
+        rendered.split("\n").map(|s| s.to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(build_platform: BuildPlatform) -> BuildScriptContext {
+        BuildScriptContext {
+            build_platform,
+            activation_scripts: vec![],
+            source_dir: "src".to_string(),
+            entry_points: vec![],
+            cache_dir: None,
+            target: None,
+        }
+    }
+
+    #[test]
+    fn builds_with_the_job_count_of_the_environment() {
+        let unix = context(BuildPlatform::Unix).render().join("\n");
+        assert!(unix.contains("-j$CPU_COUNT"), "{unix}");
+
+        let windows = context(BuildPlatform::Windows).render().join("\n");
+        assert!(windows.contains("-j%CPU_COUNT%"), "{windows}");
+    }
+}
@@ -0,0 +1,16 @@
+mod build_script;
+mod target;
+mod zig;
+
+use pixi_build_backend::backend::GenericBackend;
+use zig::ZigBuildBackend;
+
+#[tokio::main]
+pub async fn main() {
+    if let Err(err) =
+        pixi_build_backend::cli::main(GenericBackend::<ZigBuildBackend>::factory).await
+    {
+        eprintln!("{err:?}");
+        std::process::exit(1);
+    }
+}
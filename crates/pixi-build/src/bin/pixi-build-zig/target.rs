@@ -0,0 +1,24 @@
+use rattler_conda_types::Platform;
+
+/// Returns the zig target triple that corresponds with the given conda
+/// platform, or `None` if zig has no equivalent target.
+pub(crate) fn zig_target(platform: Platform) -> Option<&'static str> {
+    Some(match platform {
+        Platform::Linux32 => "x86-linux-gnu",
+        Platform::Linux64 => "x86_64-linux-gnu",
+        Platform::LinuxAarch64 => "aarch64-linux-gnu",
+        Platform::LinuxArmV6l => "arm-linux-gnueabihf",
+        Platform::LinuxArmV7l => "arm-linux-gnueabihf",
+        Platform::LinuxPpc64le => "powerpc64le-linux-gnu",
+        Platform::LinuxPpc64 => "powerpc64-linux-gnu",
+        Platform::LinuxS390X => "s390x-linux-gnu",
+        Platform::Osx64 => "x86_64-macos",
+        Platform::OsxArm64 => "aarch64-macos",
+        Platform::Win32 => "x86-windows",
+        Platform::Win64 => "x86_64-windows",
+        Platform::WinArm64 => "aarch64-windows",
+        Platform::EmscriptenWasm32 => "wasm32-emscripten",
+        Platform::WasiWasm32 => "wasm32-wasi",
+        _ => return None,
+    })
+}
@@ -0,0 +1,250 @@
+use pixi_build_backend::{
+    backend::{BackendContext, BuildBackend},
+    config::NoArch,
+    entry_points::command_entry_points,
+    manifest_ext::ManifestExt,
+};
+use pixi_spec::PixiSpec;
+use rattler_build::recipe::{parser::ScriptContent, Recipe};
+use rattler_conda_types::{ChannelConfig, PackageName, Platform};
+
+use crate::{
+    build_script::{BuildPlatform, BuildScriptContext},
+    target::zig_target,
+};
+
+/// The keys of the `[tool.pixi-build]` table that are supported by this
+/// backend.
+const CONFIG_KEYS: &[&str] = &[
+    "activation-scripts",
+    "build-number",
+    "build-string",
+    "check-relocatability",
+    "dynamic-linking",
+    "entry-points",
+    "environment",
+    "environment-file",
+    "files",
+    "host-prefix",
+    "host-prefix-length",
+    "info-files",
+    "jobs",
+    "keep-self-references",
+    "license-url",
+    "metapackage",
+    "noarch",
+    "output-channel",
+    "pin-compatible",
+    "platform-output",
+    "post-build-hook",
+    "post-process",
+    "prefix-detection",
+    "recurse-submodules",
+    "repodata",
+    "reuse-build-dir",
+    "run-exports",
+    "sbom",
+    "schema-version",
+    "script-env",
+    "skip-build-tools",
+    "snapshot-environments",
+    "source-dir",
+    "store-recipe",
+    "test",
+    "tool-options",
+    "variant",
+    "version-source",
+];
+
+/// The build tools that the backend adds to the requirements if they are not
+/// declared in the manifest.
+const BUILD_TOOLS: &[&str] = &["zig"];
+
+/// Builds packages with `zig build`.
+#[derive(Default)]
+pub struct ZigBuildBackend;
+
+impl BuildBackend for ZigBuildBackend {
+    const CONFIG_KEYS: &'static [&'static str] = CONFIG_KEYS;
+    const BUILD_TOOLS: &'static [&'static str] = BUILD_TOOLS;
+    const DEFAULT_NOARCH: NoArch = NoArch::None;
+    const REUSES_BUILD_DIR: bool = true;
+    const KEEP_BUILD: bool = true;
+
+    fn recipe(
+        &self,
+        context: &BackendContext,
+        host_platform: Platform,
+        channel_config: &ChannelConfig,
+    ) -> miette::Result<Recipe> {
+        let config = &context.config;
+        let manifest_root = context.manifest_root();
+        let name = context.manifest.package_name()?;
+
+        let noarch_type = config.noarch_type(NoArch::None, &[NoArch::Generic, NoArch::None])?;
+
+        let mut dependencies = context.dependencies(host_platform)?;
+        // Ensure zig is available in the build dependencies section. Zig acts as
+        // the compiler so it has to run on the build machine.
+        if !config.skip_build_tools && !dependencies.build.contains_key("zig") {
+            dependencies
+                .build
+                .insert(PackageName::new_unchecked("zig"), PixiSpec::default());
+        }
+        let requirements = context.requirements(dependencies, channel_config, vec![])?;
+        let build_platform = Platform::current();
+
+        // Only pass an explicit target to zig when cross-compiling, otherwise zig
+        // compiles for the native target. A noarch package is not compiled for a
+        // specific platform, so it always uses the native target.
+        let compile_platform = config.target_platform.unwrap_or(host_platform);
+        let target = if noarch_type.is_none() && compile_platform != build_platform {
+            let Some(target) = zig_target(compile_platform) else {
+                miette::bail!("zig does not support cross-compiling to {compile_platform}");
+            };
+            Some(target.to_string())
+        } else {
+            None
+        };
+
+        let submodule_source = context.submodule_source()?;
+        let build_script = BuildScriptContext {
+            entry_points: command_entry_points(&config.entry_points)?,
+            activation_scripts: context.activation_scripts()?,
+            build_platform: if build_platform.is_windows() {
+                BuildPlatform::Windows
+            } else {
+                BuildPlatform::Unix
+            },
+            source_dir: match &submodule_source {
+                Some(source) => source.source_dir(build_platform),
+                None => config.source_dir(manifest_root)?.display().to_string(),
+            },
+            cache_dir: config
+                .reusable_build_dir(manifest_root, &name, host_platform)
+                .map(|dir| dir.join("zig-cache").display().to_string()),
+            target,
+        }
+        .render();
+
+        // Unless the sources are cloned with their submodules, they are built in
+        // place, so the recipe has no source.
+        context.recipe(
+            noarch_type,
+            requirements,
+            submodule_source
+                .map(|source| vec![source.source])
+                .unwrap_or_default(),
+            config.parallel_script(ScriptContent::Commands(build_script))?,
+        )
+    }
+
+    fn input_globs(&self) -> Vec<String> {
+        [
+            // Source files
+            "**/*.zig",
+            "**/*.{c,cc,cxx,cpp,h,hpp,hxx}",
+            // Build files
+            "build.zig",
+            "build.zig.zon",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pixi_build_backend::config::ConfigOverrides;
+
+    use super::*;
+
+    /// Returns the recipe for `host_platform` of a project with the given
+    /// `[tool.pixi-build]` configuration in `directory`.
+    fn recipe(directory: &Path, config: &str, host_platform: Platform) -> miette::Result<Recipe> {
+        let manifest_path = directory.join("pixi.toml");
+        std::fs::write(
+            &manifest_path,
+            format!(
+                r#"
+                [project]
+                name = "package"
+                version = "1.0.0"
+                channels = []
+                platforms = ["{platform}"]
+
+                [tool.pixi-build]
+                {config}
+                "#,
+                platform = Platform::current()
+            ),
+        )
+        .unwrap();
+        let context =
+            BackendContext::new::<ZigBuildBackend>(&manifest_path, &ConfigOverrides::default())
+                .unwrap();
+        let channel_config = ChannelConfig::default_with_root_dir(directory.to_path_buf());
+        ZigBuildBackend.recipe(&context, host_platform, &channel_config)
+    }
+
+    /// Returns the `-Dtarget` option of the build script of the project for
+    /// `host_platform`, if any.
+    fn target_option(config: &str, host_platform: Platform) -> Option<String> {
+        let directory = tempfile::tempdir().unwrap();
+        let recipe = recipe(directory.path(), config, host_platform).unwrap();
+        let ScriptContent::Commands(commands) = &recipe.build.script.content else {
+            panic!("the build script is not a list of commands");
+        };
+        commands
+            .iter()
+            .flat_map(|command| command.split_whitespace())
+            .find(|word| word.starts_with("-Dtarget="))
+            .map(str::to_string)
+    }
+
+    /// Returns a platform other than the current one that zig can compile
+    /// for.
+    fn cross_platform() -> Platform {
+        if Platform::current() == Platform::Linux64 {
+            Platform::OsxArm64
+        } else {
+            Platform::Linux64
+        }
+    }
+
+    #[test]
+    fn native_builds_have_no_target() {
+        assert_eq!(target_option("", Platform::current()), None);
+    }
+
+    #[test]
+    fn cross_builds_pass_the_zig_target() {
+        let platform = cross_platform();
+        assert_eq!(
+            target_option("", platform),
+            Some(format!("-Dtarget={}", zig_target(platform).unwrap()))
+        );
+    }
+
+    #[test]
+    fn unsupported_cross_targets_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let err = recipe(directory.path(), "", Platform::ZosZ).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("zig does not support cross-compiling to zos-z"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn noarch_packages_are_built_for_the_native_target() {
+        assert_eq!(
+            target_option(r#"noarch = "generic""#, cross_platform()),
+            None
+        );
+    }
+}
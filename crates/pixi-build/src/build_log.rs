@@ -0,0 +1,224 @@
+//! Captures the log of a build to a file in the work directory.
+//!
+//! The capture is a [`tracing_subscriber`] layer that is installed next to the
+//! console output when the backend starts. Every [`BuildLogCapture`] owns a
+//! span, and the layer writes the events that are emitted within that span,
+//! or within any of its child spans, to the file of the capture. Builds that
+//! run at the same time therefore each get their own log, and the console
+//! output is not affected.
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use miette::{Context, IntoDiagnostic};
+use parking_lot::Mutex;
+use tracing::{span, Span, Subscriber};
+use tracing_subscriber::{fmt, layer, registry::LookupSpan, Layer, Registry};
+
+/// The name of the log file in the work directory.
+pub const BUILD_LOG_FILE_NAME: &str = "build.log";
+
+/// The name of the file that contains the previous part of the log after the
+/// log file was rotated.
+pub const ROTATED_BUILD_LOG_FILE_NAME: &str = "build.log.1";
+
+thread_local! {
+    /// The captures of the spans that are entered on this thread, innermost
+    /// last. Spans that are not part of a build have no capture.
+    static ENTERED: RefCell<Vec<Option<Capture>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A log file that is rotated once it exceeds a maximum size. The previous
+/// part of the log is kept in a single rotated file, so at least the last
+/// `max_size` bytes of the log are always available.
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_size: u64,
+}
+
+impl RotatingFile {
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.written += buf.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        std::fs::rename(
+            &self.path,
+            self.path.with_file_name(ROTATED_BUILD_LOG_FILE_NAME),
+        )?;
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+/// The log file of a build. It is stored in the extensions of the span of
+/// the build.
+#[derive(Clone)]
+struct Capture(Arc<Mutex<RotatingFile>>);
+
+/// Tracks the captures of the spans that are entered on the current thread.
+struct CaptureScope;
+
+impl<S> Layer<S> for CaptureScope
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &span::Id, ctx: layer::Context<'_, S>) {
+        let capture = ctx.span_scope(id).and_then(|mut scope| {
+            scope.find_map(|span| span.extensions().get::<Capture>().cloned())
+        });
+        ENTERED.with(|entered| entered.borrow_mut().push(capture));
+    }
+
+    fn on_exit(&self, _id: &span::Id, _ctx: layer::Context<'_, S>) {
+        ENTERED.with(|entered| entered.borrow_mut().pop());
+    }
+}
+
+/// Writes to the capture of the innermost entered span, or discards the
+/// output if it is not part of a build.
+struct CaptureWriter(Option<Capture>);
+
+impl CaptureWriter {
+    fn current() -> Self {
+        Self(ENTERED.with(|entered| entered.borrow().last().cloned().flatten()))
+    }
+}
+
+impl Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(Capture(file)) = &self.0 {
+            file.lock().write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(Capture(file)) = &self.0 {
+            file.lock().file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the layer that writes the log of every build to its capture.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    CaptureScope.and_then(
+        fmt::layer()
+            .with_ansi(false)
+            .with_writer(CaptureWriter::current),
+    )
+}
+
+/// Captures the log of a build to a file for as long as it is alive. Only
+/// the events within [`BuildLogCapture::span`] are captured.
+pub struct BuildLogCapture {
+    path: PathBuf,
+    span: Span,
+    capture: Capture,
+}
+
+impl BuildLogCapture {
+    /// Starts capturing the log to [`BUILD_LOG_FILE_NAME`] in
+    /// `work_directory`. The file is rotated once it exceeds `max_size`
+    /// bytes.
+    pub fn start(work_directory: &Path, max_size: u64) -> miette::Result<Self> {
+        let path = work_directory.join(BUILD_LOG_FILE_NAME);
+        std::fs::create_dir_all(work_directory).into_diagnostic()?;
+        let file = File::create(&path)
+            .into_diagnostic()
+            .with_context(|| format!("failed to create the build log {}", path.display()))?;
+        let capture = Capture(Arc::new(Mutex::new(RotatingFile {
+            path: path.clone(),
+            file,
+            written: 0,
+            max_size,
+        })));
+
+        // The span is created at the error level, so it is not disabled by the
+        // log level of the console.
+        let span = tracing::error_span!("build", log = %path.display());
+        span.with_subscriber(|(id, dispatch)| {
+            if let Some(registry) = dispatch.downcast_ref::<Registry>() {
+                if let Some(span) = registry.span(id) {
+                    span.extensions_mut().insert(capture.clone());
+                }
+            }
+        });
+        Ok(Self {
+            path,
+            span,
+            capture,
+        })
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the span of the build. The log of everything that runs within
+    /// it is captured.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+}
+
+impl Drop for BuildLogCapture {
+    fn drop(&mut self) {
+        let _ = self.capture.0.lock().file.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_builds_are_captured_to_their_own_log() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let first_log = BuildLogCapture::start(first.path(), u64::MAX).unwrap();
+            let second_log = BuildLogCapture::start(second.path(), u64::MAX).unwrap();
+            first_log.span().in_scope(|| {
+                tracing::info!("building the first package");
+                tracing::info_span!("script").in_scope(|| tracing::info!("running the script"));
+            });
+            second_log
+                .span()
+                .in_scope(|| tracing::info!("building the second package"));
+            tracing::info!("not part of a build");
+        });
+
+        let first = std::fs::read_to_string(first.path().join(BUILD_LOG_FILE_NAME)).unwrap();
+        assert!(first.contains("building the first package"), "{first}");
+        assert!(first.contains("running the script"), "{first}");
+        assert!(!first.contains("second package"), "{first}");
+        assert!(!first.contains("not part of a build"), "{first}");
+
+        let second = std::fs::read_to_string(second.path().join(BUILD_LOG_FILE_NAME)).unwrap();
+        assert!(second.contains("building the second package"), "{second}");
+        assert!(!second.contains("first package"), "{second}");
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSetBuilder};
+use miette::{Context, IntoDiagnostic};
+use rattler_digest::{compute_file_digest, digest::Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+/// The name of the file that records the builds that succeeded.
+pub const BUILD_STATE_FILE_NAME: &str = "build-state.yaml";
+
+/// Records the outputs that were built successfully, so that an interrupted
+/// run can be resumed without rebuilding them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BuildState {
+    outputs: BTreeMap<String, SucceededOutput>,
+}
+
+/// An output that was built successfully.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SucceededOutput {
+    /// The packages that were built.
+    pub packages: Vec<PathBuf>,
+
+    /// The globs of the files that were used as inputs of the build.
+    pub input_globs: Vec<String>,
+
+    /// The hash of the inputs at the time of the build.
+    pub input_hash: String,
+
+    /// The hash of the effective configuration of the backend at the time of
+    /// the build, which includes the global configuration file and the
+    /// overrides on the command line.
+    #[serde(default)]
+    pub config_hash: String,
+}
+
+impl BuildState {
+    /// Reads the build state from `path`. Returns an empty state if the file
+    /// does not exist.
+    pub fn from_path(path: &Path) -> miette::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).into_diagnostic(),
+        };
+        serde_yaml::from_str(&contents)
+            .into_diagnostic()
+            .with_context(|| format!("failed to parse the build state {}", path.display()))
+    }
+
+    /// Writes the build state to `path`.
+    pub fn write(&self, path: &Path) -> miette::Result<()> {
+        let contents = serde_yaml::to_string(self).into_diagnostic()?;
+        std::fs::write(path, contents)
+            .into_diagnostic()
+            .with_context(|| format!("failed to write the build state {}", path.display()))
+    }
+
+    /// Returns the recorded output with the given key if it is still up to
+    /// date, i.e. its packages still exist and neither its inputs nor the
+    /// serialized effective configuration `config` changed.
+    pub fn up_to_date(
+        &self,
+        key: &str,
+        source_dir: &Path,
+        config: &str,
+    ) -> miette::Result<Option<&SucceededOutput>> {
+        let Some(output) = self.outputs.get(key) else {
+            return Ok(None);
+        };
+        if !output.packages.iter().all(|package| package.is_file()) {
+            return Ok(None);
+        }
+        if hash_config(config) != output.config_hash {
+            return Ok(None);
+        }
+        if hash_inputs(source_dir, &output.input_globs)? != output.input_hash {
+            return Ok(None);
+        }
+        Ok(Some(output))
+    }
+
+    /// Records that the output with the given key was built successfully with
+    /// the serialized effective configuration `config`.
+    pub fn record(
+        &mut self,
+        key: String,
+        source_dir: &Path,
+        config: &str,
+        packages: Vec<PathBuf>,
+        input_globs: Vec<String>,
+    ) -> miette::Result<()> {
+        let input_hash = hash_inputs(source_dir, &input_globs)?;
+        self.outputs.insert(
+            key,
+            SucceededOutput {
+                packages,
+                input_globs,
+                input_hash,
+                config_hash: hash_config(config),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Computes a hash of the serialized effective configuration of the backend.
+fn hash_config(config: &str) -> String {
+    format!("{:x}", Sha256::digest(config.as_bytes()))
+}
+
+/// Computes a hash over the paths and contents of all files in `source_dir`
+/// that match one of `input_globs`. Hidden directories such as `.pixi` are
+/// skipped.
+pub fn hash_inputs(source_dir: &Path, input_globs: &[String]) -> miette::Result<String> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in input_globs {
+        builder.add(
+            Glob::new(glob)
+                .into_diagnostic()
+                .with_context(|| format!("invalid input glob '{glob}'"))?,
+        );
+    }
+    let glob_set = builder.build().into_diagnostic()?;
+
+    let mut files = Vec::new();
+    let walker = walkdir::WalkDir::new(source_dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+    for entry in walker {
+        let entry = entry.into_diagnostic()?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative_path = entry
+            .path()
+            .strip_prefix(source_dir)
+            .expect("walked paths are inside the source directory");
+        if glob_set.is_match(relative_path) {
+            files.push(relative_path.to_path_buf());
+        }
+    }
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let digest = compute_file_digest::<Sha256>(source_dir.join(&file))
+            .into_diagnostic()
+            .with_context(|| format!("failed to hash {}", file.display()))?;
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(digest);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendConfig, ConfigOverrides};
+
+    /// Returns the serialized effective configuration with the given
+    /// `overrides`.
+    fn config(overrides: ConfigOverrides) -> String {
+        serde_yaml::to_string(&BackendConfig::default().with_overrides(&overrides)).unwrap()
+    }
+
+    #[test]
+    fn changing_an_override_invalidates_the_recorded_output() {
+        let directory = tempfile::tempdir().unwrap();
+        let package = directory.path().join("package-1.0-h123_0.conda");
+        std::fs::write(&package, "").unwrap();
+        let jobs = |jobs| {
+            config(ConfigOverrides {
+                jobs: Some(jobs),
+                ..ConfigOverrides::default()
+            })
+        };
+
+        let mut state = BuildState::default();
+        state
+            .record(
+                "linux-64".to_string(),
+                directory.path(),
+                &jobs(2),
+                vec![package],
+                vec!["pixi.toml".to_string()],
+            )
+            .unwrap();
+
+        assert!(state
+            .up_to_date("linux-64", directory.path(), &jobs(2))
+            .unwrap()
+            .is_some());
+        assert!(state
+            .up_to_date("linux-64", directory.path(), &jobs(4))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,141 @@
+use itertools::Itertools;
+use miette::Diagnostic;
+use rattler_build::recipe::parser::{Dependency, Requirements};
+use thiserror::Error;
+
+/// An error that is returned when the environments of a package cannot be
+/// solved and the error refers to a build tool that the backend requires.
+///
+/// Backends add the tools they need (e.g. `cmake`) without a version
+/// constraint. If the channels of the project do not provide them, the
+/// solver error alone does not make it obvious how to fix the problem.
+#[derive(Debug, Error, Diagnostic)]
+#[error("failed to solve the environments of the package, the build tools {} could not be resolved", .tools.iter().map(|tool| format!("`{tool}`")).join(", "))]
+pub struct MissingBuildToolsError {
+    /// The build tools that are referred to in the solver error.
+    pub tools: Vec<String>,
+
+    #[help]
+    help: String,
+
+    #[source]
+    source: Box<dyn std::error::Error + Send + Sync + 'static>,
+}
+
+/// Returns the `tools` that occur in the build or host requirements without
+/// any version or build constraint. These are the tools that were added by
+/// the backend, or that the user did not constrain.
+pub fn unconstrained_build_tools(requirements: &Requirements, tools: &[&str]) -> Vec<String> {
+    requirements
+        .build
+        .iter()
+        .chain(requirements.host.iter())
+        .filter_map(|dependency| match dependency {
+            Dependency::Spec(spec) => Some(spec),
+            _ => None,
+        })
+        .filter(|spec| spec.version.is_none() && spec.build.is_none())
+        .filter_map(|spec| spec.name.as_ref())
+        .map(|name| name.as_normalized())
+        .filter(|name| tools.contains(name))
+        .map(ToString::to_string)
+        .unique()
+        .collect()
+}
+
+/// The phrases with which solvers report that no package provides a
+/// requirement. The name of the requirement directly follows the phrase.
+const NOTHING_PROVIDES: &[&str] = &[
+    "nothing provides requested ",
+    "nothing provides ",
+    "No candidates were found for ",
+];
+
+/// Returns the names of the packages that the solver error `message`
+/// reports as not being provided by any channel.
+fn unprovided_packages(message: &str) -> Vec<&str> {
+    let mut packages = Vec::new();
+    for line in message.lines() {
+        let mut rest = line;
+        while let Some((start, phrase)) = NOTHING_PROVIDES
+            .iter()
+            .filter_map(|phrase| rest.find(phrase).map(|start| (start, phrase)))
+            .min_by_key(|(start, phrase)| (*start, std::cmp::Reverse(phrase.len())))
+        {
+            rest = &rest[start + phrase.len()..];
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                .unwrap_or(rest.len());
+            packages.push(rest[..end].trim_end_matches('.'));
+            rest = &rest[end..];
+        }
+    }
+    packages
+}
+
+/// Turns an error that occurred while solving the environments into a
+/// [`MissingBuildToolsError`] if the solver reports that nothing provides one
+/// of the `tools`. Other errors, e.g. conflicts that merely mention a tool,
+/// are returned as is.
+pub fn explain_missing_build_tools(err: miette::Report, tools: &[String]) -> miette::Report {
+    let message = err.chain().map(ToString::to_string).join("\n");
+    let unprovided = unprovided_packages(&message);
+    let missing = tools
+        .iter()
+        .filter(|tool| unprovided.contains(&tool.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        return err;
+    }
+
+    let help = format!(
+        "the backend requires {} to build the package, but the channels of the project do not seem to provide them. Add a channel that does (e.g. `conda-forge`), or declare the tools explicitly in the dependencies of the manifest.",
+        missing.iter().map(|tool| format!("`{tool}`")).join(" and ")
+    );
+    MissingBuildToolsError {
+        tools: missing,
+        help,
+        source: err.into(),
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tools(tools: &[&str]) -> Vec<String> {
+        tools.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn missing_build_tools_are_explained() {
+        let err = miette::miette!(
+            "Cannot solve the request because of: nothing provides requested cmake *\nnothing provides ninja needed by package-1.0-h0_0"
+        );
+        let err = explain_missing_build_tools(err, &tools(&["cmake", "ninja", "make"]));
+        let err = err
+            .downcast_ref::<MissingBuildToolsError>()
+            .expect("a missing build tools error");
+        assert_eq!(err.tools, ["cmake", "ninja"]);
+
+        let err = miette::miette!("No candidates were found for uv *.");
+        let err = explain_missing_build_tools(err, &tools(&["uv", "python"]));
+        assert_eq!(
+            err.downcast_ref::<MissingBuildToolsError>().unwrap().tools,
+            ["uv"]
+        );
+    }
+
+    #[test]
+    fn unrelated_conflicts_are_returned_as_is() {
+        let message = "Cannot solve the request because of: package-1.0 requires python >=3.12, but python 3.11 is pinned\nnothing provides python-dateutil needed by pandas-2.2";
+        let err = explain_missing_build_tools(
+            miette::miette!("{message}"),
+            &tools(&["python", "pip", "cmake"]),
+        );
+        assert!(err.downcast_ref::<MissingBuildToolsError>().is_none());
+        assert_eq!(err.to_string(), message);
+    }
+}
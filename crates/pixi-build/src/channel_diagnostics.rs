@@ -0,0 +1,62 @@
+//! Diagnostics about the channels that are used to resolve the dependencies
+//! of a package. These help to find common misconfigurations, such as a
+//! misspelled channel or a channel that does not provide packages for the
+//! platform, before the environments are solved.
+
+use itertools::Itertools;
+use rattler_build::{
+    metadata::Output, recipe::parser::Dependency, tool_configuration::Configuration,
+};
+use rattler_conda_types::{Channel, MatchSpec, Platform};
+
+/// Queries every channel of `output` separately for the dependencies of the
+/// package and reports for each channel whether its repodata could be
+/// fetched and how many records match the dependencies.
+pub async fn diagnose_channels(output: &Output, tool_config: &Configuration) {
+    let build_configuration = &output.build_configuration;
+    let platforms = [
+        build_configuration.build_platform.platform,
+        build_configuration.host_platform.platform,
+        Platform::NoArch,
+    ]
+    .into_iter()
+    .unique()
+    .collect::<Vec<_>>();
+    let requirements = &output.recipe.requirements;
+    let specs = requirements
+        .build
+        .iter()
+        .chain(requirements.host.iter())
+        .chain(requirements.run.iter())
+        .filter_map(|dependency| match dependency {
+            Dependency::Spec(spec) => Some(spec.clone()),
+            _ => None,
+        })
+        .collect::<Vec<MatchSpec>>();
+    let subdirs = platforms.iter().join(", ");
+
+    for url in &build_configuration.channels {
+        let channel = Channel::from_url(url.clone());
+        let repodata = tool_config
+            .repodata_gateway
+            .query(vec![channel], platforms.clone(), specs.clone())
+            .await;
+        match repodata {
+            Err(err) => tracing::warn!(
+                "failed to fetch the repodata of {url} for {subdirs}: {err}. Check that the channel exists and provides packages for these platforms."
+            ),
+            Ok(repodata) => {
+                let records = repodata.iter().map(|repodata| repodata.len()).sum::<usize>();
+                if records == 0 {
+                    tracing::warn!(
+                        "the channel {url} does not provide any package for the dependencies of the package for {subdirs}"
+                    );
+                } else {
+                    tracing::info!(
+                        "the channel {url} provides {records} records for the dependencies of the package for {subdirs}"
+                    );
+                }
+            }
+        }
+    }
+}
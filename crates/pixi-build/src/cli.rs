@@ -2,25 +2,44 @@ use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
+use itertools::Itertools;
+use jsonrpc_core::serde_json;
 use miette::{Context, IntoDiagnostic};
 use pixi_build_types::{
     procedures::{
-        conda_build::CondaBuildParams,
-        conda_metadata::{CondaMetadataParams, CondaMetadataResult},
+        conda_build::{CondaBuildParams, CondaBuildResult},
+        conda_metadata::CondaMetadataParams,
         initialize::InitializeParams,
     },
     ChannelConfiguration, FrontendCapabilities, PlatformAndVirtualPackages,
 };
-use rattler_build::console_utils::{get_default_env_filter, LoggingOutputHandler};
+use rattler_build::{
+    console_utils::{get_default_env_filter, LoggingOutputHandler},
+    metadata::PlatformWithVirtualPackages,
+};
 use rattler_conda_types::{ChannelConfig, GenericVirtualPackage, Platform};
 use rattler_virtual_packages::{VirtualPackage, VirtualPackageOverrides};
+use reqwest::Url;
+use serde::Serialize;
 use tempfile::TempDir;
+use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
+    build_log,
+    build_state::{BuildState, BUILD_STATE_FILE_NAME},
+    conda_build::CondaBuildRequest,
+    conda_metadata::{CondaMetadataRequest, CondaMetadataResponse},
+    config::ConfigOverrides,
     consts,
+    describe::DescribeParams,
+    effective_config::EffectiveConfig,
+    input_globs::InputGlobsParams,
+    manifest_ext::find_manifest,
+    platform::resolve_build_and_host_platform,
     protocol::{Protocol, ProtocolFactory},
-    server::Server,
+    reproducible::{compare_packages, ignore_globs},
+    server::{default_max_parallel_solves, Server, DEFAULT_MAX_REQUEST_SIZE},
 };
 
 #[allow(missing_docs)]
@@ -34,29 +53,206 @@ pub struct App {
     #[clap(long)]
     http_port: Option<u16>,
 
-    /// Enable verbose logging.
+    /// The maximum size of a single json-rpc request in bytes.
+    #[clap(long, default_value_t = DEFAULT_MAX_REQUEST_SIZE)]
+    max_request_size: usize,
+
+    /// The maximum number of metadata requests that resolve their dependencies
+    /// at the same time. Additional requests are queued. Defaults to the
+    /// number of available CPUs.
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_parallel_solves: Option<u64>,
+
+    /// The url that is used to resolve channel names that are not a full url
+    /// (e.g. `conda-forge`). Defaults to `https://conda.anaconda.org`.
+    #[clap(long, global = true, value_parser = parse_channel_alias)]
+    channel_alias: Option<Url>,
+
+    /// Control the verbosity of the logging. With `--quiet` only warnings and
+    /// errors are logged and the build command does not report progress.
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// store data as key value pair
     GetCondaMetadata {
+        /// The path to the manifest, or to a directory that contains it.
         #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
         manifest_path: PathBuf,
 
         #[clap(long)]
         host_platform: Option<Platform>,
+
+        /// The virtual packages of the host platform, e.g. `__cuda=12.0=0`.
+        /// If specified, these replace the virtual packages that are
+        /// detected on the current machine. Without `--host-platform` they
+        /// apply to the current platform.
+        #[clap(long = "host-virtual-package", value_name = "PACKAGE")]
+        host_virtual_packages: Vec<GenericVirtualPackage>,
+
+        /// Only return the metadata that is known from the manifest, without
+        /// resolving the dependencies. The dependencies and constraints of
+        /// the package are left empty.
+        #[clap(long)]
+        no_deps: bool,
     },
     CondaBuild {
+        /// The path to the manifest, or to a directory that contains it.
+        #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
+        manifest_path: PathBuf,
+
+        /// Skip outputs that were already built successfully by a previous
+        /// run and whose inputs and effective configuration did not change
+        /// since. Packages are kept in `.pixi/build-backend/output` next to
+        /// the manifest.
+        #[clap(long, conflicts_with = "verify_reproducible")]
+        resume: bool,
+
+        /// Build the package twice into separate directories and verify that
+        /// the packages are identical. Exits with an error and reports the
+        /// differing files if they are not.
+        #[clap(long)]
+        verify_reproducible: bool,
+
+        /// Paths inside the package that are ignored when verifying that the
+        /// build is reproducible, e.g. `info/index.json` which contains the
+        /// build timestamp.
+        #[clap(
+            long = "verify-ignore",
+            value_name = "GLOB",
+            requires = "verify_reproducible"
+        )]
+        verify_ignore: Vec<String>,
+
+        /// Overrides a virtual package of the build platform, e.g.
+        /// `__cuda=12.0=0`. Virtual packages that are not overridden are
+        /// detected on the current machine.
+        #[clap(long = "build-virtual-package", value_name = "PACKAGE")]
+        build_virtual_packages: Vec<GenericVirtualPackage>,
+    },
+    /// Print the effective configuration of the backend after the global
+    /// configuration file, the manifest and the command line options have
+    /// been applied, without building anything. Credentials in urls are
+    /// redacted.
+    Config {
+        /// The path to the manifest, or to a directory that contains it.
+        #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
+        manifest_path: PathBuf,
+
+        #[clap(long)]
+        host_platform: Option<Platform>,
+
+        /// Print the configuration as JSON instead of YAML.
+        #[clap(long)]
+        json: bool,
+    },
+    /// Write the recipe that is synthesized from the manifest to a file that
+    /// can be built with rattler-build directly, without pixi-build.
+    ExportRecipe {
+        /// The path to the manifest, or to a directory that contains it.
+        #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
+        manifest_path: PathBuf,
+
+        #[clap(long)]
+        host_platform: Option<Platform>,
+
+        /// The file to write the recipe to, e.g. `recipe.yaml`.
+        #[clap(long, short)]
+        output: PathBuf,
+    },
+    /// Print a hash of the recipe that is synthesized from the manifest,
+    /// which changes whenever the package would be built differently.
+    RecipeHash {
+        /// The path to the manifest, or to a directory that contains it.
+        #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
+        manifest_path: PathBuf,
+
+        #[clap(long)]
+        host_platform: Option<Platform>,
+    },
+    /// Print the globs of the files that a build of the package depends on,
+    /// one per line, without building anything.
+    InputGlobs {
+        /// The path to the manifest, or to a directory that contains it.
         #[clap(env, long, env = "PIXI_PROJECT_MANIFEST", default_value = consts::PROJECT_MANIFEST)]
         manifest_path: PathBuf,
+
+        #[clap(long)]
+        host_platform: Option<Platform>,
+    },
+    /// Print the capabilities of the backend
+    Capabilities,
+    /// Print the build and host platform with the virtual packages that a
+    /// build would use
+    VirtualPackages {
+        #[clap(long)]
+        host_platform: Option<Platform>,
     },
 }
 
-async fn run_server<T: ProtocolFactory>(port: Option<u16>, protocol: T) -> miette::Result<()> {
-    let server = Server::new(protocol);
+/// Parses the channel alias from the command line. Channel names are appended
+/// to the alias, so it must be a url that can serve as a base and it always
+/// ends with a slash.
+fn parse_channel_alias(value: &str) -> Result<Url, String> {
+    let mut url = Url::parse(value).map_err(|err| format!("invalid url: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https" | "file") {
+        return Err(format!(
+            "unsupported scheme `{}`, expected `http`, `https` or `file`",
+            url.scheme()
+        ));
+    }
+    if url.cannot_be_a_base() {
+        return Err("the url cannot be used as a base for channel names".to_string());
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
+}
+
+/// The resolved platform and virtual packages of a build or host environment.
+#[derive(Serialize)]
+struct ResolvedPlatform {
+    platform: Platform,
+    virtual_packages: Vec<String>,
+}
+
+impl From<PlatformWithVirtualPackages> for ResolvedPlatform {
+    fn from(value: PlatformWithVirtualPackages) -> Self {
+        Self {
+            platform: value.platform,
+            virtual_packages: value
+                .virtual_packages
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// The output of the `virtual-packages` command.
+#[derive(Serialize)]
+struct ResolvedPlatforms {
+    build: ResolvedPlatform,
+    host: ResolvedPlatform,
+}
+
+async fn run_server<T: ProtocolFactory>(
+    port: Option<u16>,
+    max_request_size: usize,
+    max_parallel_solves: Option<u64>,
+    protocol: T,
+) -> miette::Result<()> {
+    let server = Server::new(protocol)
+        .with_max_request_size(max_request_size)
+        .with_max_parallel_solves(
+            max_parallel_solves.map_or_else(default_max_parallel_solves, |max| max as usize),
+        );
     if let Some(port) = port {
         server.run_over_http(port)
     } else {
@@ -64,7 +260,7 @@ async fn run_server<T: ProtocolFactory>(port: Option<u16>, protocol: T) -> miett
     }
 }
 
-pub async fn main<T: ProtocolFactory, F: FnOnce(LoggingOutputHandler) -> T>(
+pub async fn main<T: ProtocolFactory, F: FnOnce(LoggingOutputHandler, ConfigOverrides) -> T>(
     factory: F,
 ) -> miette::Result<()> {
     let args = App::parse();
@@ -73,35 +269,185 @@ pub async fn main<T: ProtocolFactory, F: FnOnce(LoggingOutputHandler) -> T>(
     let log_handler = LoggingOutputHandler::default();
     let registry = tracing_subscriber::registry()
         .with(get_default_env_filter(args.verbose.log_level_filter()).into_diagnostic()?);
-    registry.with(log_handler.clone()).init();
+    registry
+        .with(log_handler.clone())
+        .with(build_log::layer())
+        .init();
 
-    let factory = factory(log_handler);
+    // Progress messages are suppressed whenever `--quiet` lowers the log level
+    // below the default, regardless of how it is combined with `--verbose`.
+    let quiet = args
+        .verbose
+        .log_level()
+        .map_or(true, |level| level < log::Level::Info);
+
+    let factory = factory(log_handler, args.overrides);
 
     match args.command {
-        None => run_server(args.http_port, factory).await,
-        Some(Commands::CondaBuild { manifest_path }) => build(factory, &manifest_path).await,
+        None => {
+            run_server(
+                args.http_port,
+                args.max_request_size,
+                args.max_parallel_solves,
+                factory,
+            )
+            .await
+        }
+        Some(Commands::CondaBuild {
+            manifest_path,
+            verify_reproducible: true,
+            verify_ignore,
+            build_virtual_packages,
+            ..
+        }) => {
+            verify_reproducible(
+                factory,
+                &find_manifest(&manifest_path)?,
+                args.channel_alias,
+                build_virtual_packages,
+                &verify_ignore,
+                quiet,
+            )
+            .await
+        }
+        Some(Commands::CondaBuild {
+            manifest_path,
+            resume,
+            build_virtual_packages,
+            ..
+        }) => {
+            build(
+                factory,
+                &find_manifest(&manifest_path)?,
+                args.channel_alias,
+                build_virtual_packages,
+                resume,
+                quiet,
+            )
+            .await
+        }
         Some(Commands::GetCondaMetadata {
             manifest_path,
             host_platform,
+            host_virtual_packages,
+            no_deps,
         }) => {
-            let metadata = get_conda_metadata(factory, &manifest_path, host_platform).await?;
+            let manifest_path = find_manifest(&manifest_path)?;
+            let metadata = get_conda_metadata(
+                factory,
+                &manifest_path,
+                args.channel_alias,
+                host_platform,
+                host_virtual_packages,
+                no_deps,
+            )
+            .await?;
             println!("{}", serde_yaml::to_string(&metadata).unwrap());
             Ok(())
         }
+        Some(Commands::Config {
+            manifest_path,
+            host_platform,
+            json,
+        }) => {
+            let manifest_path = find_manifest(&manifest_path)?;
+            let config =
+                effective_config(factory, &manifest_path, args.channel_alias, host_platform)
+                    .await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&config).unwrap());
+            } else {
+                println!("{}", serde_yaml::to_string(&config).unwrap());
+            }
+            Ok(())
+        }
+        Some(Commands::ExportRecipe {
+            manifest_path,
+            host_platform,
+            output,
+        }) => {
+            let manifest_path = find_manifest(&manifest_path)?;
+            export_recipe(
+                factory,
+                &manifest_path,
+                args.channel_alias,
+                host_platform,
+                &output,
+            )
+            .await?;
+            if !quiet {
+                eprintln!("Exported the recipe to {}", output.display());
+            }
+            Ok(())
+        }
+        Some(Commands::RecipeHash {
+            manifest_path,
+            host_platform,
+        }) => {
+            let manifest_path = find_manifest(&manifest_path)?;
+            let channel_config = channel_config(&manifest_path, args.channel_alias);
+            let (protocol, _initialize_result) = factory
+                .initialize(InitializeParams {
+                    manifest_path,
+                    capabilities: FrontendCapabilities {},
+                    cache_directory: None,
+                })
+                .await?;
+            let result = protocol
+                .recipe_hash(DescribeParams {
+                    host_platform,
+                    channel_configuration: ChannelConfiguration {
+                        base_url: channel_config.channel_alias,
+                    },
+                })
+                .await?;
+            println!("{}", result.hash);
+            Ok(())
+        }
+        Some(Commands::InputGlobs {
+            manifest_path,
+            host_platform,
+        }) => {
+            let manifest_path = find_manifest(&manifest_path)?;
+            let (protocol, _initialize_result) = factory
+                .initialize(InitializeParams {
+                    manifest_path,
+                    capabilities: FrontendCapabilities {},
+                    cache_directory: None,
+                })
+                .await?;
+            let result = protocol
+                .input_globs(InputGlobsParams { host_platform })
+                .await?;
+            for glob in result.input_globs {
+                println!("{glob}");
+            }
+            Ok(())
+        }
+        Some(Commands::Capabilities) => {
+            println!(
+                "{}",
+                serde_yaml::to_string(&factory.capabilities()).unwrap()
+            );
+            Ok(())
+        }
+        Some(Commands::VirtualPackages { host_platform }) => {
+            let platforms = resolve_virtual_packages(host_platform)?;
+            println!("{}", serde_yaml::to_string(&platforms).unwrap());
+            Ok(())
+        }
     }
 }
 
 async fn get_conda_metadata(
     factory: impl ProtocolFactory,
     manifest_path: &Path,
+    channel_alias: Option<Url>,
     host_platform: Option<Platform>,
-) -> miette::Result<CondaMetadataResult> {
-    let channel_config = ChannelConfig::default_with_root_dir(
-        manifest_path
-            .parent()
-            .expect("manifest should always reside in a directory")
-            .to_path_buf(),
-    );
+    host_virtual_packages: Vec<GenericVirtualPackage>,
+    no_deps_metadata: bool,
+) -> miette::Result<CondaMetadataResponse> {
+    let channel_config = channel_config(manifest_path, channel_alias);
 
     let (protocol, _initialize_result) = factory
         .initialize(InitializeParams {
@@ -111,39 +457,83 @@ async fn get_conda_metadata(
         })
         .await?;
 
-    let virtual_packages: Vec<_> = VirtualPackage::detect(&VirtualPackageOverrides::from_env())
-        .into_diagnostic()?
-        .into_iter()
-        .map(GenericVirtualPackage::from)
-        .collect();
+    let host_platform = if host_virtual_packages.is_empty() {
+        match host_platform {
+            Some(platform) => Some(PlatformAndVirtualPackages {
+                platform,
+                virtual_packages: Some(detect_virtual_packages()?),
+            }),
+            None => None,
+        }
+    } else {
+        Some(PlatformAndVirtualPackages {
+            platform: host_platform.unwrap_or_else(Platform::current),
+            virtual_packages: Some(host_virtual_packages),
+        })
+    };
 
     let tempdir = TempDir::new_in(".")
         .into_diagnostic()
         .context("failed to create a temporary directory in the current directory")?;
 
     protocol
-        .get_conda_metadata(CondaMetadataParams {
-            build_platform: None,
-            host_platform: host_platform.map(|platform| PlatformAndVirtualPackages {
-                platform,
-                virtual_packages: Some(virtual_packages.clone()),
-            }),
-            channel_base_urls: None,
+        .get_conda_metadata(
+            CondaMetadataRequest {
+                params: CondaMetadataParams {
+                    build_platform: None,
+                    host_platform,
+                    channel_base_urls: None,
+                    channel_configuration: ChannelConfiguration {
+                        base_url: channel_config.channel_alias,
+                    },
+                    work_directory: tempdir.path().to_path_buf(),
+                },
+                no_deps_metadata,
+                build_number: None,
+                built_sources: Vec::new(),
+            },
+            CancellationToken::new(),
+        )
+        .await
+}
+
+async fn effective_config(
+    factory: impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_alias: Option<Url>,
+    host_platform: Option<Platform>,
+) -> miette::Result<EffectiveConfig> {
+    let channel_config = channel_config(manifest_path, channel_alias);
+
+    let (protocol, _initialize_result) = factory
+        .initialize(InitializeParams {
+            manifest_path: manifest_path.to_path_buf(),
+            capabilities: FrontendCapabilities {},
+            cache_directory: None,
+        })
+        .await?;
+
+    protocol
+        .effective_config(DescribeParams {
+            host_platform,
             channel_configuration: ChannelConfiguration {
                 base_url: channel_config.channel_alias,
             },
-            work_directory: tempdir.path().to_path_buf(),
         })
         .await
 }
 
-async fn build(factory: impl ProtocolFactory, manifest_path: &Path) -> miette::Result<()> {
-    let channel_config = ChannelConfig::default_with_root_dir(
-        manifest_path
-            .parent()
-            .expect("manifest should always reside in a directory")
-            .to_path_buf(),
-    );
+/// Writes the recipe that the backend synthesizes from the manifest to
+/// `output`. All paths in the recipe, such as the source directory, are
+/// absolute, so the recipe does not depend on the directory it is written to.
+async fn export_recipe(
+    factory: impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_alias: Option<Url>,
+    host_platform: Option<Platform>,
+    output: &Path,
+) -> miette::Result<()> {
+    let channel_config = channel_config(manifest_path, channel_alias);
 
     let (protocol, _initialize_result) = factory
         .initialize(InitializeParams {
@@ -153,23 +543,173 @@ async fn build(factory: impl ProtocolFactory, manifest_path: &Path) -> miette::R
         })
         .await?;
 
-    let work_dir = TempDir::new_in(".")
-        .into_diagnostic()
-        .context("failed to create a temporary directory in the current directory")?;
-
     let result = protocol
-        .build_conda(CondaBuildParams {
-            host_platform: None,
-            build_platform_virtual_packages: None,
-            channel_base_urls: None,
+        .describe(DescribeParams {
+            host_platform,
             channel_configuration: ChannelConfiguration {
                 base_url: channel_config.channel_alias,
             },
-            outputs: None,
-            work_directory: work_dir.path().to_path_buf(),
         })
         .await?;
 
+    let recipe = serde_yaml::to_string(&result.recipe).into_diagnostic()?;
+    let contents = format!(
+        "# Exported by pixi-build from {}\n{recipe}",
+        manifest_path.display()
+    );
+    std::fs::write(output, contents)
+        .into_diagnostic()
+        .with_context(|| format!("failed to write the recipe to {}", output.display()))
+}
+
+/// Returns the channel configuration for the manifest, using `channel_alias`
+/// instead of the default alias if specified.
+fn channel_config(manifest_path: &Path, channel_alias: Option<Url>) -> ChannelConfig {
+    let mut channel_config = ChannelConfig::default_with_root_dir(
+        manifest_path
+            .parent()
+            .expect("manifest should always reside in a directory")
+            .to_path_buf(),
+    );
+    if let Some(channel_alias) = channel_alias {
+        channel_config.channel_alias = channel_alias;
+    }
+    channel_config
+}
+
+/// Detects the virtual packages of the current machine, taking the overrides
+/// from the environment into account.
+fn detect_virtual_packages() -> miette::Result<Vec<GenericVirtualPackage>> {
+    Ok(VirtualPackage::detect(&VirtualPackageOverrides::from_env())
+        .into_diagnostic()?
+        .into_iter()
+        .map(GenericVirtualPackage::from)
+        .collect())
+}
+
+/// Returns the virtual packages of the current machine with the given
+/// `overrides` applied, or `None` if there are no overrides so that the
+/// backend detects them itself.
+fn override_virtual_packages(
+    overrides: Vec<GenericVirtualPackage>,
+) -> miette::Result<Option<Vec<GenericVirtualPackage>>> {
+    if overrides.is_empty() {
+        return Ok(None);
+    }
+    let mut virtual_packages = detect_virtual_packages()?;
+    virtual_packages.retain(|package| {
+        !overrides
+            .iter()
+            .any(|override_| override_.name == package.name)
+    });
+    virtual_packages.extend(overrides);
+    Ok(Some(virtual_packages))
+}
+
+/// Resolves the build and host platform the same way a backend does when it
+/// is invoked through [`get_conda_metadata`].
+fn resolve_virtual_packages(host_platform: Option<Platform>) -> miette::Result<ResolvedPlatforms> {
+    let host_platform = match host_platform {
+        Some(platform) => Some(PlatformAndVirtualPackages {
+            platform,
+            virtual_packages: Some(detect_virtual_packages()?),
+        }),
+        None => None,
+    };
+
+    let (build, host) = resolve_build_and_host_platform(None, host_platform)?;
+    Ok(ResolvedPlatforms {
+        build: build.into(),
+        host: host.into(),
+    })
+}
+
+async fn build(
+    factory: impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_alias: Option<Url>,
+    build_virtual_packages: Vec<GenericVirtualPackage>,
+    resume: bool,
+    quiet: bool,
+) -> miette::Result<()> {
+    let manifest_root = manifest_path
+        .parent()
+        .expect("manifest should always reside in a directory");
+    let channel_config = channel_config(manifest_path, channel_alias);
+
+    // When resuming, the packages and the state of previous runs are stored in a
+    // persistent directory instead of a temporary one.
+    let temp_dir;
+    let work_directory = if resume {
+        let work_directory = manifest_root
+            .join(".pixi")
+            .join("build-backend")
+            .join("output");
+        std::fs::create_dir_all(&work_directory)
+            .into_diagnostic()
+            .context("failed to create the output directory")?;
+        work_directory
+    } else {
+        temp_dir = TempDir::new_in(".")
+            .into_diagnostic()
+            .context("failed to create a temporary directory in the current directory")?;
+        temp_dir.path().to_path_buf()
+    };
+
+    let state_path = work_directory.join(BUILD_STATE_FILE_NAME);
+    let output_key = Platform::current().to_string();
+    let mut state = if resume {
+        // The effective configuration includes the global configuration file
+        // and the overrides on the command line, which are not input files.
+        let config = serialized_effective_config(&factory, manifest_path, &channel_config).await?;
+        let state = BuildState::from_path(&state_path)?;
+        if let Some(output) = state.up_to_date(&output_key, manifest_root, &config)? {
+            if !quiet {
+                for package in &output.packages {
+                    eprintln!("Skipping '{}', it is up to date", package.display());
+                }
+            }
+            return Ok(());
+        }
+        Some((state, config))
+    } else {
+        None
+    };
+
+    let build_virtual_packages = override_virtual_packages(build_virtual_packages)?;
+    let result = build_packages(
+        &factory,
+        manifest_path,
+        &channel_config,
+        build_virtual_packages,
+        &work_directory,
+    )
+    .await?;
+
+    if let Some((state, config)) = &mut state {
+        // The manifest itself is always an input of the build.
+        let manifest_glob = manifest_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let input_globs = result
+            .packages
+            .iter()
+            .flat_map(|package| package.input_globs.iter().cloned())
+            .chain(manifest_glob)
+            .unique()
+            .collect();
+        let packages = result
+            .packages
+            .iter()
+            .map(|package| package.output_file.clone())
+            .collect();
+        state.record(output_key, manifest_root, config, packages, input_globs)?;
+        state.write(&state_path)?;
+    }
+
+    if quiet {
+        return Ok(());
+    }
     for package in result.packages {
         eprintln!("Successfully build '{}'", package.output_file.display());
         eprintln!("Use following globs to revalidate: ");
@@ -180,3 +720,141 @@ async fn build(factory: impl ProtocolFactory, manifest_path: &Path) -> miette::R
 
     Ok(())
 }
+
+/// Initializes the backend for the manifest and returns its effective
+/// configuration for the current platform, serialized as yaml.
+async fn serialized_effective_config(
+    factory: &impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_config: &ChannelConfig,
+) -> miette::Result<String> {
+    let (protocol, _initialize_result) = factory
+        .initialize(InitializeParams {
+            manifest_path: manifest_path.to_path_buf(),
+            capabilities: FrontendCapabilities {},
+            cache_directory: None,
+        })
+        .await?;
+
+    let config = protocol
+        .effective_config(DescribeParams {
+            host_platform: None,
+            channel_configuration: ChannelConfiguration {
+                base_url: channel_config.channel_alias.clone(),
+            },
+        })
+        .await?;
+    serde_yaml::to_string(&config).into_diagnostic()
+}
+
+/// Initializes the backend for the manifest and builds its packages into
+/// `work_directory`.
+async fn build_packages(
+    factory: &impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_config: &ChannelConfig,
+    build_virtual_packages: Option<Vec<GenericVirtualPackage>>,
+    work_directory: &Path,
+) -> miette::Result<CondaBuildResult> {
+    let (protocol, _initialize_result) = factory
+        .initialize(InitializeParams {
+            manifest_path: manifest_path.to_path_buf(),
+            capabilities: FrontendCapabilities {},
+            cache_directory: None,
+        })
+        .await?;
+
+    protocol
+        .build_conda(
+            CondaBuildRequest {
+                params: CondaBuildParams {
+                    host_platform: None,
+                    build_platform_virtual_packages: build_virtual_packages,
+                    channel_base_urls: None,
+                    channel_configuration: ChannelConfiguration {
+                        base_url: channel_config.channel_alias.clone(),
+                    },
+                    outputs: None,
+                    work_directory: work_directory.to_path_buf(),
+                },
+                build_number: None,
+                built_sources: Vec::new(),
+            },
+            CancellationToken::new(),
+        )
+        .await
+        .map(|response| response.result)
+}
+
+/// Builds the packages twice into separate directories and compares the
+/// results. Returns an error if any of the packages differ.
+async fn verify_reproducible(
+    factory: impl ProtocolFactory,
+    manifest_path: &Path,
+    channel_alias: Option<Url>,
+    build_virtual_packages: Vec<GenericVirtualPackage>,
+    ignore: &[String],
+    quiet: bool,
+) -> miette::Result<()> {
+    let channel_config = channel_config(manifest_path, channel_alias);
+    let build_virtual_packages = override_virtual_packages(build_virtual_packages)?;
+    let ignore = ignore_globs(ignore)?;
+
+    // The directories must outlive the comparison of the packages in them.
+    let first_directory = TempDir::new_in(".")
+        .into_diagnostic()
+        .context("failed to create a temporary directory in the current directory")?;
+    let second_directory = TempDir::new_in(".")
+        .into_diagnostic()
+        .context("failed to create a temporary directory in the current directory")?;
+    let first = build_packages(
+        &factory,
+        manifest_path,
+        &channel_config,
+        build_virtual_packages.clone(),
+        first_directory.path(),
+    )
+    .await?;
+    let second = build_packages(
+        &factory,
+        manifest_path,
+        &channel_config,
+        build_virtual_packages,
+        second_directory.path(),
+    )
+    .await?;
+
+    if first.packages.len() != second.packages.len() {
+        miette::bail!(
+            "the builds produced a different number of packages ({} and {})",
+            first.packages.len(),
+            second.packages.len()
+        );
+    }
+
+    let mut reproducible = true;
+    for (first, second) in first.packages.iter().zip(&second.packages) {
+        let differences = compare_packages(&first.output_file, &second.output_file, &ignore)?;
+        let name = first
+            .output_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        if differences.is_empty() {
+            if !quiet {
+                eprintln!("'{name}' is reproducible");
+            }
+        } else {
+            reproducible = false;
+            eprintln!("'{name}' is not reproducible:");
+            for difference in differences {
+                eprintln!("  - {difference}");
+            }
+        }
+    }
+
+    if !reproducible {
+        miette::bail!("the packages differ between two builds");
+    }
+    Ok(())
+}
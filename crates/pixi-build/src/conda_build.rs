@@ -0,0 +1,52 @@
+//! Extensions of the `conda/build` procedure of `pixi_build_types`.
+
+use std::path::PathBuf;
+
+use pixi_build_types::procedures::conda_build::{CondaBuildParams, CondaBuildResult};
+use serde::{Deserialize, Serialize};
+
+use crate::dependencies::BuiltSourcePackage;
+
+/// The parameters of the `conda/build` method. This is the
+/// [`CondaBuildParams`] with additional options that frontends can opt in to.
+/// Frontends that do not know about them get the default behavior.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CondaBuildRequest {
+    /// The parameters of the request.
+    #[serde(flatten)]
+    pub params: CondaBuildParams,
+
+    /// The build number of the packages. This takes precedence over the
+    /// `build-number` of the manifest and the command line.
+    #[serde(default)]
+    pub build_number: Option<u64>,
+
+    /// The source dependencies of the package that the frontend has already
+    /// built. Dependencies on them are pinned to the exact version and build
+    /// string of the built packages instead of being rejected.
+    #[serde(default)]
+    pub built_sources: Vec<BuiltSourcePackage>,
+}
+
+/// The response of the `conda/build` method. This is the
+/// [`CondaBuildResult`] with additional files that were written for the
+/// built packages. The additional fields are ignored by frontends that do not
+/// know about them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CondaBuildResponse {
+    /// The built packages.
+    #[serde(flatten)]
+    pub result: CondaBuildResult,
+
+    /// The software bills of materials of the built packages, see
+    /// [`crate::sbom`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sboms: Vec<PathBuf>,
+
+    /// The log of the build, if `--build-log-max-size` is set, see
+    /// [`crate::build_log`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+}
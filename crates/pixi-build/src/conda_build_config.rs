@@ -0,0 +1,90 @@
+//! Support for the `conda_build_config.yaml` file of conda-build.
+//!
+//! Only a subset of the file is supported. Every top-level key is a variant
+//! key with either a single value or a list of values. Since only a single
+//! variant is built, only the first value of a list is used. Selectors (e.g.
+//! `# [linux]`) are comments in YAML and are therefore ignored, and version
+//! numbers should be quoted so that e.g. `3.10` is not read as `3.1`. The
+//! keys `zip_keys`, `pin_run_as_build`, `extend_keys` and `ignore_version`
+//! are not supported and ignored with a warning.
+//!
+//! The variant keys are applied to the dependencies of the package with
+//! [`crate::variants::apply_variant`].
+
+use std::{collections::BTreeMap, path::Path};
+
+use miette::{Context, IntoDiagnostic};
+use serde_yaml::Value;
+
+/// The name of the file that is read from the manifest directory.
+pub const CONDA_BUILD_CONFIG_FILE_NAME: &str = "conda_build_config.yaml";
+
+/// Keys of conda-build that have a special meaning and are not supported.
+const UNSUPPORTED_KEYS: &[&str] = &[
+    "zip_keys",
+    "pin_run_as_build",
+    "extend_keys",
+    "ignore_version",
+];
+
+/// Reads the variant keys from the `conda_build_config.yaml` in
+/// `manifest_root`. Returns an empty variant if the file does not exist.
+/// Parts of the file that are not supported are reported as warnings.
+pub fn load_conda_build_config(manifest_root: &Path) -> miette::Result<BTreeMap<String, String>> {
+    let path = manifest_root.join(CONDA_BUILD_CONFIG_FILE_NAME);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err).into_diagnostic(),
+    };
+    let config: Option<serde_yaml::Mapping> = serde_yaml::from_str(&contents)
+        .into_diagnostic()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let mut variant = BTreeMap::new();
+    for (key, value) in config.unwrap_or_default() {
+        let Some(key) = key.as_str() else {
+            tracing::warn!("ignoring a non-string key in {}", path.display());
+            continue;
+        };
+        if UNSUPPORTED_KEYS.contains(&key) {
+            tracing::warn!(
+                "`{key}` in {} is not supported and is ignored",
+                path.display()
+            );
+            continue;
+        }
+
+        let value = match value {
+            Value::Sequence(values) => {
+                if values.len() > 1 {
+                    tracing::warn!(
+                        "`{key}` in {} has multiple values, only the first one is used",
+                        path.display()
+                    );
+                }
+                values.into_iter().next()
+            }
+            value => Some(value),
+        };
+        match value.as_ref().and_then(scalar_to_string) {
+            Some(value) => {
+                variant.insert(key.to_string(), value);
+            }
+            None => tracing::warn!(
+                "`{key}` in {} does not have a supported value and is ignored",
+                path.display()
+            ),
+        }
+    }
+    Ok(variant)
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,97 @@
+//! Extensions of the `conda/getMetadata` procedure of `pixi_build_types`.
+
+use pixi_build_types::procedures::conda_metadata::{
+    CondaMetadataParams, CondaMetadataResult, CondaPackageMetadata,
+};
+use rattler_build::metadata::Output;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use crate::dependencies::BuiltSourcePackage;
+
+/// The parameters of the `conda/getMetadata` method. This is the
+/// [`CondaMetadataParams`] with additional options that frontends can opt in
+/// to. Frontends that do not know about them get the default behavior.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CondaMetadataRequest {
+    /// The parameters of the request.
+    #[serde(flatten)]
+    pub params: CondaMetadataParams,
+
+    /// Return the metadata that is known from the recipe, without resolving
+    /// the dependencies of the package. This is much faster, but the run
+    /// dependencies and constraints of the packages are left empty, see
+    /// [`CondaMetadataResponse::dependencies_resolved`]. A `build-string`
+    /// template that references a variant key that is only known from the
+    /// resolved host environment, like `{python}`, is not applied either, so
+    /// the default build string is reported instead.
+    #[serde(default)]
+    pub no_deps_metadata: bool,
+
+    /// The build number of the packages. This takes precedence over the
+    /// `build-number` of the manifest and the command line.
+    #[serde(default)]
+    pub build_number: Option<u64>,
+
+    /// The source dependencies of the package that the frontend has already
+    /// built. Dependencies on them are pinned to the exact version and build
+    /// string of the built packages instead of being rejected.
+    #[serde(default)]
+    pub built_sources: Vec<BuiltSourcePackage>,
+}
+
+/// The response of the `conda/getMetadata` method. This is the
+/// [`CondaMetadataResult`] with additional information about how the metadata
+/// was determined. The additional fields are ignored by frontends that do not
+/// know about them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CondaMetadataResponse {
+    /// The metadata of the packages.
+    #[serde(flatten)]
+    pub result: CondaMetadataResult,
+
+    /// The channels that were used to resolve the dependencies of the
+    /// packages, in order of priority.
+    pub channels: Vec<Url>,
+
+    /// Whether the dependencies of the packages were resolved. If `false`,
+    /// the `depends` and `constraints` of the packages are empty because they
+    /// are unknown, not because the packages have no dependencies.
+    pub dependencies_resolved: bool,
+}
+
+impl CondaMetadataResponse {
+    /// Returns the metadata of `outputs` that is known without resolving
+    /// their dependencies, for requests with
+    /// [`CondaMetadataRequest::no_deps_metadata`].
+    pub fn unresolved(outputs: &[Output]) -> Self {
+        let packages = outputs
+            .iter()
+            .map(|output| CondaPackageMetadata {
+                name: output.name().clone(),
+                version: output.version().clone().into(),
+                build: output.build_string().into_owned(),
+                build_number: output.recipe.build.number,
+                subdir: output.build_configuration.target_platform,
+                depends: Vec::new(),
+                constraints: Vec::new(),
+                license: output.recipe.about.license.as_ref().map(|l| l.to_string()),
+                license_family: output.recipe.about.license_family.clone(),
+                noarch: output.recipe.build.noarch,
+            })
+            .collect();
+        Self {
+            result: CondaMetadataResult {
+                packages,
+                input_globs: None,
+            },
+            channels: outputs
+                .first()
+                .map(|output| output.build_configuration.channels.clone())
+                .unwrap_or_default(),
+            dependencies_resolved: false,
+        }
+    }
+}
@@ -0,0 +1,2120 @@
+use std::{
+    collections::BTreeMap,
+    io::IsTerminal,
+    num::NonZeroUsize,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use itertools::Itertools;
+use miette::{Context, IntoDiagnostic};
+use pixi_manifest::Manifest;
+use rattler_build::{
+    console_utils::LoggingOutputHandler,
+    recipe::parser::{
+        CommandsTest, CommandsTestRequirements, Dependency, DynamicLinking, GlobVec, PinCompatible,
+        PostProcess, PrefixDetection, PrefixIgnore, RunExports, Script, ScriptContent,
+        SerializableRegex, TestType, VariantKeyUsage,
+    },
+    render::pin::{Pin, PinArgs, PinBound},
+    tool_configuration::{reqwest_client_from_auth_storage, Configuration, ConfigurationBuilder},
+};
+use rattler_conda_types::{
+    ChannelConfig, MatchSpec, NoArchType, PackageName, ParseStrictness, Platform, Version,
+};
+use reqwest::Url;
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+
+use crate::{
+    environment_file::EnvironmentFile, info_files::validate_info_file_names, lock::LockMode,
+    manifest_ext::ManifestExt, repodata_retry::RepodataConfig, variants::validate_build_string,
+};
+
+/// The keys in the `[tool.pixi-build]` table that are supported by every
+/// backend.
+const COMMON_KEYS: &[&str] = &["requires-version", "strict"];
+
+/// The environment variable that the build script of compiled backends reads
+/// the number of parallel jobs from, see [`BackendConfig::parallel_script`].
+pub const JOBS_ENV_VAR: &str = "CPU_COUNT";
+
+/// The version of the backend, which is compared against `requires-version`.
+pub const BACKEND_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The compilers that `compilers` is expected to refer to.
+const KNOWN_COMPILERS: &[&str] = &[
+    "clang",
+    "clangxx",
+    "emscripten",
+    "flang",
+    "gcc",
+    "gfortran",
+    "gxx",
+    "vs2017",
+    "vs2019",
+    "vs2022",
+];
+
+/// The recipe schema versions that are supported by the version of
+/// rattler-build this crate is built against.
+pub const SUPPORTED_SCHEMA_VERSIONS: &[u64] = &[1];
+
+/// The recipe schema version that is used if none is configured.
+const DEFAULT_SCHEMA_VERSION: u64 = 1;
+
+/// The configuration of a build backend. This is read from the
+/// `[tool.pixi-build]` table of the manifest and the global configuration
+/// file.
+///
+/// The precedence of the configuration sources is: command line overrides,
+/// the manifest, the global configuration file and finally the built-in
+/// defaults.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackendConfig {
+    /// If `true`, warnings about the configuration and the recipe, e.g.
+    /// unknown keys or a defaulted version, are reported as an error instead.
+    /// See [`BackendConfig::report_warnings`].
+    #[serde(default)]
+    pub strict: bool,
+
+    /// The minimum version of the backend that is required to build the
+    /// package, e.g. because the configuration uses options that older
+    /// backends do not understand. See [`check_required_version`].
+    pub requires_version: Option<String>,
+
+    /// Determines which files end up in the package.
+    #[serde(default)]
+    pub files: FilesConfig,
+
+    /// Additional options that are passed on to the build tool.
+    #[serde(default)]
+    pub tool_options: ToolOptions,
+
+    /// How downloads of repodata are retried, see
+    /// [`crate::repodata_retry`].
+    #[serde(default)]
+    pub repodata: RepodataConfig,
+
+    /// Scripts, relative to the manifest directory, that are sourced before
+    /// the build commands are executed. They run after the build and host
+    /// environments have been activated (including any `activate.d` scripts of
+    /// the host dependencies), in the order in which they are specified.
+    #[serde(default)]
+    pub activation_scripts: Vec<PathBuf>,
+
+    /// The run exports that the package imposes on packages that depend on
+    /// it.
+    #[serde(default)]
+    pub run_exports: RunExportsConfig,
+
+    /// Do not add the build tools of the backend (e.g. python and the
+    /// installer, or cmake and ninja) to the dependencies. The manifest has to
+    /// declare any tool that the build needs.
+    #[serde(default)]
+    pub skip_build_tools: bool,
+
+    /// Keep path dependencies that point to the package itself instead of
+    /// dropping them. Only needed if a dependency is wrongly detected as a
+    /// self reference.
+    #[serde(default)]
+    pub keep_self_references: bool,
+
+    /// Run constraints that pin packages of the host environment to versions
+    /// compatible with the ones the package was built against, keyed by
+    /// package name. The packages must be host dependencies.
+    #[serde(default)]
+    pub pin_compatible: BTreeMap<String, PinCompatibleConfig>,
+
+    /// The URL of the license of the package, which ends up as `license_url`
+    /// in the package metadata.
+    pub license_url: Option<Url>,
+
+    /// A conda `environment.yml`, relative to the manifest directory, whose
+    /// dependencies and channels are added to the package. See
+    /// [`crate::environment_file`] for how it is merged with the manifest.
+    pub environment_file: Option<PathBuf>,
+
+    /// The environment of the project whose dependencies are used for the
+    /// package. Defaults to the default environment.
+    pub environment: Option<String>,
+
+    /// Where the version of the package is read from.
+    #[serde(default)]
+    pub version_source: VersionSource,
+
+    /// How the source directory is made available to the build.
+    #[serde(default)]
+    pub source_mode: SourceMode,
+
+    /// Additional arguments that are passed to the installer (pip or uv),
+    /// e.g. `["--index-url", "https://example.com/simple"]`. Each argument is
+    /// passed as is, without splitting it on whitespace.
+    #[serde(default)]
+    pub installer_args: Vec<String>,
+
+    /// How the package is installed into the prefix.
+    #[serde(default)]
+    pub install_mode: InstallMode,
+
+    /// The noarch type of the package. If not specified, the backend decides.
+    pub noarch: Option<NoArch>,
+
+    /// Determines in which files the build prefix is detected and replaced
+    /// when the package is installed.
+    #[serde(default)]
+    pub prefix_detection: PrefixDetectionConfig,
+
+    /// Options for the checks and the relocation of shared libraries.
+    #[serde(default)]
+    pub dynamic_linking: DynamicLinkingConfig,
+
+    /// A template for the build string of the package, e.g.
+    /// `py{python}h{hash}_{build_number}`. See
+    /// [`crate::variants::apply_build_string`] for the placeholders. Without
+    /// resolving the dependencies the `{python}` placeholder is unknown, so
+    /// metadata requests with `noDepsMetadata` report the default build
+    /// string for such templates.
+    pub build_string: Option<String>,
+
+    /// Determines which variant keys end up in the build string.
+    #[serde(default)]
+    pub variant: VariantConfig,
+
+    /// Install the host dependencies into the build environment, so that a
+    /// single environment is used for the build. If not specified, the python
+    /// backend merges them for packages that require themselves to build.
+    pub merge_build_and_host_envs: Option<bool>,
+
+    /// Build a metapackage, which only contains the run dependencies of the
+    /// package. Nothing is built and the build and host dependencies are
+    /// ignored, see [`crate::metapackage`].
+    #[serde(default)]
+    pub metapackage: bool,
+
+    /// Split the platform specific files of a noarch package into a separate
+    /// output, see [`crate::platform_output`].
+    pub platform_output: Option<PlatformOutputConfig>,
+
+    /// The compilers that compiled backends use instead of the default
+    /// compiler of a platform, keyed by platform and language, e.g.
+    /// `linux-64 = { c = "clang", cxx = "clangxx" }`. The values are the
+    /// names of the compiler packages without the `_<platform>` suffix.
+    #[serde(default)]
+    pub compilers: BTreeMap<String, BTreeMap<String, String>>,
+
+    /// Regex replacements that are applied to the files of the package after
+    /// the build, e.g. to fix interpreter lines.
+    #[serde(default)]
+    pub post_process: Vec<PostProcessConfig>,
+
+    /// Environment variables that are set when the build script runs.
+    #[serde(default)]
+    pub script_env: ScriptEnvConfig,
+
+    /// Executables that the package provides, keyed by name. See
+    /// [`crate::entry_points`] for how the targets are interpreted.
+    #[serde(default)]
+    pub entry_points: BTreeMap<String, String>,
+
+    /// A local channel, relative to the manifest directory, to which built
+    /// packages are added. The channel is also used to resolve dependencies,
+    /// which makes packages available to subsequent builds immediately.
+    pub output_channel: Option<PathBuf>,
+
+    /// An existing conda environment, relative to the manifest directory,
+    /// whose packages are used as the host environment instead of solving it.
+    /// See [`crate::host_prefix::use_host_prefix`].
+    pub host_prefix: Option<PathBuf>,
+
+    /// Additional files in the `info/` directory of the package, keyed by
+    /// their path inside `info/`. The values are the source files relative to
+    /// the manifest directory. See [`crate::info_files::add_info_files`].
+    #[serde(default)]
+    pub info_files: BTreeMap<String, PathBuf>,
+
+    /// Store the rendered recipe in `info/recipe` of the package, which some
+    /// channels require for provenance.
+    #[serde(default)]
+    pub store_recipe: bool,
+
+    /// Write a snapshot of the build and host environments next to the built
+    /// package, see [`crate::environment_snapshot`].
+    #[serde(default)]
+    pub snapshot_environments: bool,
+
+    /// Write a CycloneDX software bill of materials next to the built
+    /// package, see [`crate::sbom`].
+    #[serde(default)]
+    pub sbom: bool,
+
+    /// Whether the built package is checked for absolute paths of the build,
+    /// see [`crate::relocatability::check_relocatability`].
+    #[serde(default)]
+    pub check_relocatability: RelocatabilityCheck,
+
+    /// A command that runs after the package is built, before it is reported
+    /// to the frontend or added to the output channel. The first element is
+    /// the program, the remaining elements are arguments. See
+    /// [`crate::hooks::run_post_build_hook`] for how it is invoked.
+    #[serde(default)]
+    pub post_build_hook: Vec<String>,
+
+    /// The directory that contains the sources of the package, relative to
+    /// the manifest directory. Defaults to the manifest directory itself.
+    pub source_dir: Option<PathBuf>,
+
+    /// Build the sources from a clone of their git repository at the current
+    /// commit, with the submodules checked out recursively, see
+    /// [`crate::submodules`].
+    #[serde(default)]
+    pub recurse_submodules: bool,
+
+    /// The number of parallel jobs that compiled backends use to build the
+    /// package. Defaults to the number of available CPUs.
+    pub jobs: Option<usize>,
+
+    /// The schema version of the recipe that is passed to rattler-build.
+    /// Defaults to `1`.
+    pub schema_version: Option<u64>,
+
+    /// The build number of the package. Defaults to `0`.
+    pub build_number: Option<u64>,
+
+    /// Tests that are embedded in the package.
+    #[serde(default)]
+    pub test: TestConfig,
+
+    /// Reuse the build directory across builds instead of creating a fresh
+    /// one for every invocation, see [`BackendConfig::reusable_build_dir`].
+    ///
+    /// This speeds up iterative development because incremental build tools
+    /// can pick up their state from a previous build. However, the state is
+    /// not invalidated when the environment changes. Stale artifacts from a
+    /// previous build (e.g. after changing dependencies or compiler flags) may
+    /// end up in the package, and two builds of the same package should not
+    /// run at the same time. Remove the directory to start from scratch and
+    /// keep this disabled for release builds.
+    #[serde(default)]
+    pub reuse_build_dir: bool,
+
+    /// The length of the host prefix of the build. A longer prefix allows
+    /// binaries of the package to be installed into longer paths. Defaults to
+    /// the length that rattler-build uses, see [`crate::prefix_padding`] for
+    /// the trade-offs.
+    pub host_prefix_length: Option<usize>,
+
+    /// How the lock file is used. This can only be set from the command line.
+    #[serde(skip_deserializing)]
+    pub lock_mode: LockMode,
+
+    /// The platform the package is built for, if it differs from the host
+    /// platform. This can only be set from the command line.
+    #[serde(skip_deserializing)]
+    pub target_platform: Option<Platform>,
+
+    /// Report per channel whether the dependencies of the package can be
+    /// found before the environments are solved. This can only be set from
+    /// the command line.
+    #[serde(skip_deserializing)]
+    pub diagnose_channels: bool,
+
+    /// Capture the log of a build to a file in the work directory that is
+    /// rotated once it exceeds this size in megabytes. This can only be set
+    /// from the command line.
+    #[serde(skip_deserializing)]
+    pub build_log_max_size: Option<u64>,
+
+    /// Whether the output of the build is colored. This can only be set from
+    /// the command line.
+    #[serde(skip_deserializing)]
+    pub color: ColorMode,
+
+    /// Write the rendered build script to the work directory. This can only
+    /// be set from the command line.
+    #[serde(skip_deserializing)]
+    pub keep_build_script: bool,
+
+    /// A directory with repodata that replaces all channels when the
+    /// dependencies are resolved. This is a testing feature that can only be
+    /// set from the command line, see
+    /// [`crate::local_channel::apply_repodata_source`].
+    #[serde(skip_deserializing)]
+    pub repodata_source: Option<PathBuf>,
+
+    /// The warnings about the configuration that were found while reading it,
+    /// which are reported by [`BackendConfig::report_warnings`].
+    #[serde(skip)]
+    warnings: Vec<String>,
+}
+
+impl BackendConfig {
+    /// Reads the backend configuration from the `[tool.pixi-build]` table of
+    /// the manifest, layered on top of the global configuration file (see
+    /// [`global_config_path`]). Values in the manifest take precedence over
+    /// values in the global configuration file, and tables are merged
+    /// recursively. If neither specifies a value, the default is used.
+    ///
+    /// `supported_keys` are the top-level keys of the table that the calling
+    /// backend understands. Any other key in the table of the manifest results
+    /// in a warning, which is reported by [`BackendConfig::report_warnings`].
+    pub fn from_manifest(manifest: &Manifest, supported_keys: &[&str]) -> miette::Result<Self> {
+        Self::from_manifest_and_global_config(manifest, read_global_config()?, supported_keys)
+    }
+
+    /// Reads the backend configuration from the `[tool.pixi-build]` table of
+    /// the manifest, layered on top of `global`, the contents of the global
+    /// configuration file. See [`BackendConfig::from_manifest`].
+    ///
+    /// Only keys of the manifest are reported as not supported by the
+    /// backend. The global configuration file is shared by all backends, so
+    /// it may contain keys that only other backends support.
+    fn from_manifest_and_global_config(
+        manifest: &Manifest,
+        global: toml::Table,
+        supported_keys: &[&str],
+    ) -> miette::Result<Self> {
+        let document: toml::Table = toml::from_str(&manifest.contents)
+            .into_diagnostic()
+            .with_context(|| format!("failed to parse {}", manifest.path.display()))?;
+
+        let mut table = global;
+        let mut manifest_keys = Vec::new();
+        match document.get("tool").and_then(|tool| tool.get("pixi-build")) {
+            Some(toml::Value::Table(manifest_table)) => {
+                manifest_keys.extend(manifest_table.keys().cloned());
+                merge_tables(&mut table, manifest_table.clone())
+            }
+            Some(_) => miette::bail!(
+                "expected [tool.pixi-build] in {} to be a table",
+                manifest.path.display()
+            ),
+            None => {}
+        }
+        if table.is_empty() {
+            return Ok(Self::default());
+        }
+
+        // Check the version before anything else, an older backend may not
+        // understand the rest of the configuration.
+        if let Some(required) = table.get("requires-version") {
+            let Some(required) = required.as_str() else {
+                miette::bail!("`requires-version` must be a string");
+            };
+            check_required_version(required, manifest)?;
+        }
+        check_single_noarch(&table)?;
+        let table = toml::Value::Table(table);
+
+        let mut unknown_keys = Vec::new();
+        let mut config: Self =
+            serde_ignored::deserialize(table.clone(), |path| unknown_keys.push(path.to_string()))
+                .into_diagnostic()
+                .with_context(|| {
+                    format!(
+                        "failed to parse the pixi-build configuration for {}",
+                        manifest.path.display()
+                    )
+                })?;
+
+        let mut warnings = unknown_keys
+            .iter()
+            .map(|key| format!("unknown key `{key}` in the pixi-build configuration"))
+            .collect::<Vec<_>>();
+        warnings.extend(
+            manifest_keys
+                .iter()
+                .filter(|key| !unknown_keys.contains(key))
+                .filter(|key| !COMMON_KEYS.contains(&key.as_str()))
+                .filter(|key| !supported_keys.contains(&key.as_str()))
+                .map(|key| {
+                    format!(
+                        "the key `{key}` in the pixi-build configuration is not supported by this backend"
+                    )
+                }),
+        );
+
+        // Validate the configuration upfront so mistakes are reported as soon as
+        // possible.
+        config.files.files()?;
+        config.files.always_include_files()?;
+        config.activation_scripts(manifest.manifest_root())?;
+        config.source_dir(manifest.manifest_root())?;
+        if config.version_source == VersionSource::GitTag && manifest.version().is_some() {
+            miette::bail!(
+                "the version is read from git tags, so the manifest must not specify a `version`"
+            );
+        }
+        manifest.environment_features(config.environment.as_deref())?;
+        config.run_exports.run_exports()?;
+        config.pin_compatible()?;
+        config.test.tests()?;
+        config.installer_args()?;
+        config.schema_version()?;
+        config.jobs()?;
+        config.prefix_detection.prefix_detection()?;
+        config.dynamic_linking.dynamic_linking()?;
+        config.script_env.validate()?;
+        config.post_process()?;
+        config.repodata.validate()?;
+        if let Some(build_string) = &config.build_string {
+            validate_build_string(build_string)?;
+        }
+        if let Some(platform_output) = &config.platform_output {
+            platform_output.validate(&manifest.package_name()?, config.metapackage)?;
+        }
+        warnings.extend(config.validate_compilers()?);
+        validate_info_file_names(&config.info_files)?;
+
+        config.warnings = warnings;
+        Ok(config)
+    }
+
+    /// Returns the stable directory that is used for builds of the package
+    /// `name` for `host_platform` if `reuse-build-dir` is enabled. This is
+    /// `.pixi/build-backend/<name>/<host_platform>` in the manifest directory.
+    pub fn reusable_build_dir(
+        &self,
+        manifest_root: &Path,
+        name: &PackageName,
+        host_platform: Platform,
+    ) -> Option<PathBuf> {
+        self.reuse_build_dir.then(|| {
+            manifest_root
+                .join(".pixi")
+                .join("build-backend")
+                .join(name.as_normalized())
+                .join(host_platform.as_str())
+        })
+    }
+
+    /// Returns the additional arguments for the installer. Arguments are
+    /// quoted in the build script, so they must not contain quotes or line
+    /// breaks themselves.
+    pub fn installer_args(&self) -> miette::Result<&[String]> {
+        for arg in &self.installer_args {
+            if arg.is_empty() || arg.contains(['"', '\n', '\r']) {
+                miette::bail!(
+                    "invalid argument '{arg}' in `installer-args`, arguments must be non-empty and cannot contain quotes or line breaks"
+                );
+            }
+        }
+        Ok(&self.installer_args)
+    }
+
+    /// Returns the path of the local output channel, if any.
+    pub fn output_channel(&self, manifest_root: &Path) -> Option<PathBuf> {
+        self.output_channel
+            .as_ref()
+            .map(|channel| manifest_root.join(channel))
+    }
+
+    /// Returns the path of the existing environment that is used as the host
+    /// environment, if any.
+    pub fn host_prefix(&self, manifest_root: &Path) -> Option<PathBuf> {
+        self.host_prefix
+            .as_ref()
+            .map(|prefix| manifest_root.join(prefix))
+    }
+
+    /// Returns the `pin_compatible` run constraints that should be added to
+    /// `requirements.run_constraints` in the recipe. rattler-build resolves
+    /// them to concrete version ranges against the host environment.
+    pub fn pin_compatible(&self) -> miette::Result<Vec<Dependency>> {
+        self.pin_compatible
+            .iter()
+            .map(|(name, pin)| {
+                let package_name =
+                    PackageName::from_str(name)
+                        .into_diagnostic()
+                        .with_context(|| {
+                            format!("invalid package name '{name}' in `pin-compatible`")
+                        })?;
+                Ok(Dependency::PinCompatible(PinCompatible {
+                    pin_compatible: Pin {
+                        name: package_name,
+                        args: pin.pin_args(name)?,
+                    },
+                }))
+            })
+            .collect()
+    }
+
+    /// Reads the `environment-file`, if one is configured.
+    pub fn environment_file(
+        &self,
+        manifest_root: &Path,
+    ) -> miette::Result<Option<EnvironmentFile>> {
+        self.environment_file
+            .as_ref()
+            .map(|path| EnvironmentFile::from_path(&manifest_root.join(path)))
+            .transpose()
+    }
+
+    /// Returns the compiler that is configured for `language` on `platform`,
+    /// if any.
+    pub fn compiler(&self, platform: Platform, language: &str) -> Option<&str> {
+        self.compilers
+            .get(platform.as_str())
+            .and_then(|compilers| compilers.get(language))
+            .map(String::as_str)
+    }
+
+    /// Checks that the `compilers` refer to valid platforms and package
+    /// names. Returns a warning for every compiler that is not one of the
+    /// [`KNOWN_COMPILERS`], which is likely a typo.
+    fn validate_compilers(&self) -> miette::Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        for (platform, compilers) in &self.compilers {
+            let platform = Platform::from_str(platform)
+                .into_diagnostic()
+                .with_context(|| format!("invalid platform '{platform}' in `compilers`"))?;
+            for (language, compiler) in compilers {
+                let package = format!("{compiler}_{platform}");
+                PackageName::from_str(&package)
+                    .into_diagnostic()
+                    .with_context(|| {
+                        format!(
+                            "invalid compiler '{compiler}' in `compilers.{platform}.{language}`"
+                        )
+                    })?;
+                if !KNOWN_COMPILERS.contains(&compiler.as_str()) {
+                    warnings.push(format!(
+                        "`compilers.{platform}.{language}` is not a known compiler, check that the package `{package}` exists"
+                    ));
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Returns the post processing steps that should be used for
+    /// `build.post_process` in the recipe.
+    pub fn post_process(&self) -> miette::Result<Vec<PostProcess>> {
+        self.post_process
+            .iter()
+            .enumerate()
+            .map(|(index, post_process)| post_process.post_process(index))
+            .collect()
+    }
+
+    /// Returns the number of parallel jobs to build the package with.
+    pub fn jobs(&self) -> miette::Result<usize> {
+        match self.jobs {
+            Some(0) => miette::bail!("`jobs` must be at least 1"),
+            Some(jobs) => Ok(jobs),
+            None => Ok(std::thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+        }
+    }
+
+    /// Returns the script that should be used for `build.script` in the
+    /// recipe of a compiled backend, which runs `content` in the configured
+    /// environment with the number of parallel [`jobs`](Self::jobs) in
+    /// [`JOBS_ENV_VAR`]. The job count is passed through the environment
+    /// rather than rendered into `content`, so it does not change the script.
+    pub fn parallel_script(&self, content: ScriptContent) -> miette::Result<Script> {
+        let mut script = self.script_env.script(content);
+        script
+            .env
+            .insert(JOBS_ENV_VAR.to_string(), self.jobs()?.to_string());
+        Ok(script)
+    }
+
+    /// Returns the schema version of the recipe. Returns an error if the
+    /// configured version is not supported by rattler-build.
+    pub fn schema_version(&self) -> miette::Result<u64> {
+        let schema_version = self.schema_version.unwrap_or(DEFAULT_SCHEMA_VERSION);
+        if !SUPPORTED_SCHEMA_VERSIONS.contains(&schema_version) {
+            miette::bail!(
+                "recipe schema version {schema_version} is not supported, supported versions are: {}",
+                SUPPORTED_SCHEMA_VERSIONS.iter().join(", ")
+            );
+        }
+        Ok(schema_version)
+    }
+
+    /// Returns the noarch type of the package, or `default` if none is
+    /// configured. Returns an error if the configured noarch type is not one
+    /// of the `supported` types of the backend.
+    pub fn noarch_type(&self, default: NoArch, supported: &[NoArch]) -> miette::Result<NoArchType> {
+        let noarch = self.noarch.unwrap_or(default);
+        if !supported.contains(&noarch) {
+            miette::bail!(
+                "`noarch = \"{}\"` is not supported by this backend, expected one of {}",
+                noarch.as_str(),
+                supported
+                    .iter()
+                    .map(|noarch| format!("\"{}\"", noarch.as_str()))
+                    .join(", ")
+            );
+        }
+        Ok(noarch.into())
+    }
+
+    /// Reports the warnings about the configuration and the recipe of the
+    /// package in `manifest`, or returns a single error that lists all of
+    /// them if `strict` is enabled. `default_noarch` is the noarch type that
+    /// the backend assumes if none is configured. Assuming the default is
+    /// expected behavior, so it is only mentioned and never an error.
+    ///
+    /// This should be called once the command line overrides have been
+    /// applied, because they can enable `strict`.
+    pub fn report_warnings(
+        &self,
+        manifest: &Manifest,
+        default_noarch: NoArch,
+    ) -> miette::Result<()> {
+        let mut warnings = self.warnings.clone();
+        if self.version_source == VersionSource::Manifest && manifest.version().is_none() {
+            warnings.push(format!(
+                "the manifest does not specify a `version`, `{}` is used",
+                manifest.version_or_default()
+            ));
+        }
+        if self.noarch.is_none() && default_noarch != NoArch::None && !self.metapackage {
+            tracing::info!(
+                "`noarch` is not specified, the package is assumed to be `noarch = \"{}\"`",
+                default_noarch.as_str()
+            );
+        }
+
+        if self.strict && !warnings.is_empty() {
+            miette::bail!(
+                "the package produced warnings, which are errors in strict mode:\n{}",
+                warnings
+                    .iter()
+                    .map(|warning| format!("  - {warning}"))
+                    .join("\n")
+            );
+        }
+        for warning in warnings {
+            tracing::warn!("{warning}");
+        }
+        Ok(())
+    }
+
+    /// Returns the rattler-build tool configuration that backends use to
+    /// resolve and build packages. Every backend uses this, so the same
+    /// options behave the same for all of them.
+    ///
+    /// Tests of the recipe are never run and the build directory is kept if
+    /// `keep_build` is `true`, which is the default of the backend, unless the
+    /// `tool-options` say otherwise. Returns an error if the download client
+    /// for the `repodata` options cannot be created.
+    pub fn tool_configuration(
+        &self,
+        cache_dir: Option<PathBuf>,
+        logging_output_handler: LoggingOutputHandler,
+        channel_config: ChannelConfig,
+        keep_build: bool,
+    ) -> miette::Result<Configuration> {
+        let mut builder = Configuration::builder()
+            .with_opt_cache_dir(cache_dir)
+            .with_logging_output_handler(logging_output_handler)
+            .with_channel_config(channel_config)
+            .with_testing(false)
+            .with_keep_build(keep_build);
+        if let Some(middleware) = self.repodata.middleware() {
+            let client = reqwest_client_from_auth_storage(None)
+                .into_diagnostic()
+                .context("failed to create the download client")?;
+            builder = builder.with_reqwest_client(
+                reqwest_middleware::ClientBuilder::from_client(client)
+                    .with(middleware)
+                    .build(),
+            );
+        }
+        Ok(self.tool_options.apply(builder).finish())
+    }
+
+    /// Applies the overrides that were specified on the command line.
+    pub fn with_overrides(mut self, overrides: &ConfigOverrides) -> Self {
+        if let Some(build_number) = overrides.build_number {
+            self.build_number = Some(build_number);
+        }
+        if let Some(output_channel) = &overrides.output_channel {
+            self.output_channel = Some(output_channel.clone());
+        }
+        if let Some(host_prefix) = &overrides.host_prefix {
+            self.host_prefix = Some(host_prefix.clone());
+        }
+        if let Some(jobs) = overrides.jobs {
+            self.jobs = Some(jobs);
+        }
+        if let Some(post_build_hook) = &overrides.post_build_hook {
+            self.post_build_hook = vec![post_build_hook.clone()];
+        }
+        if let Some(check_relocatability) = overrides.check_relocatability {
+            self.check_relocatability = check_relocatability;
+        }
+        if let Some(color) = overrides.color {
+            self.color = color;
+        }
+        if let Some(install_mode) = overrides.install_mode {
+            self.install_mode = install_mode;
+        }
+        if let Some(target_platform) = overrides.target_platform {
+            self.target_platform = Some(target_platform);
+        }
+        if let Some(build_log_max_size) = overrides.build_log_max_size {
+            self.build_log_max_size = Some(build_log_max_size);
+        }
+        if overrides.diagnose_channels {
+            self.diagnose_channels = true;
+        }
+        if overrides.strict {
+            self.strict = true;
+        }
+        if overrides.snapshot_environments {
+            self.snapshot_environments = true;
+        }
+        if overrides.sbom {
+            self.sbom = true;
+        }
+        if overrides.keep_build_script {
+            self.keep_build_script = true;
+        }
+        if let Some(repodata_source) = &overrides.repodata_source {
+            self.repodata_source = Some(repodata_source.clone());
+        }
+        if overrides.frozen {
+            self.lock_mode = LockMode::Frozen;
+        } else if overrides.locked {
+            self.lock_mode = LockMode::Locked;
+        }
+        self
+    }
+
+    /// Returns the absolute paths of the activation scripts, or an error if
+    /// one of the scripts does not exist.
+    pub fn activation_scripts(&self, manifest_root: &Path) -> miette::Result<Vec<PathBuf>> {
+        self.activation_scripts
+            .iter()
+            .map(|script| {
+                let path = manifest_root.join(script);
+                if !path.is_file() {
+                    miette::bail!("the activation script '{}' does not exist", path.display());
+                }
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Returns the absolute path of the directory that contains the sources of
+    /// the package. The directory must exist and reside within the manifest
+    /// directory.
+    pub fn source_dir(&self, manifest_root: &Path) -> miette::Result<PathBuf> {
+        let Some(source_dir) = &self.source_dir else {
+            return Ok(manifest_root.to_path_buf());
+        };
+        if source_dir.is_absolute()
+            || source_dir
+                .components()
+                .any(|component| component == Component::ParentDir)
+        {
+            miette::bail!(
+                "the source directory '{}' must be a relative path within the manifest directory",
+                source_dir.display()
+            );
+        }
+
+        let path = manifest_root.join(source_dir);
+        if !path.is_dir() {
+            miette::bail!("the source directory '{}' does not exist", path.display());
+        }
+
+        // Resolve symlinks to ensure the directory does not escape the manifest
+        // directory.
+        let canonical_path = std::fs::canonicalize(&path).into_diagnostic()?;
+        let canonical_root = std::fs::canonicalize(manifest_root).into_diagnostic()?;
+        if !canonical_path.starts_with(&canonical_root) {
+            miette::bail!(
+                "the source directory '{}' resolves to a path outside of the manifest directory",
+                source_dir.display()
+            );
+        }
+
+        Ok(path)
+    }
+}
+
+/// The environment variable that can be used to override the location of the
+/// global configuration file.
+pub const GLOBAL_CONFIG_ENV: &str = "PIXI_BUILD_CONFIG";
+
+/// Returns the path of the global configuration file. This is the path in
+/// the `PIXI_BUILD_CONFIG` environment variable if set, otherwise
+/// `pixi-build/config.toml` in the user's configuration directory (e.g.
+/// `~/.config/pixi-build/config.toml` on Linux).
+///
+/// The global configuration file uses the same keys as the
+/// `[tool.pixi-build]` table of the manifest. Relative paths in it are
+/// resolved against the directory of the manifest that is being built.
+pub fn global_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(GLOBAL_CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    dirs::config_dir().map(|dir| dir.join("pixi-build").join("config.toml"))
+}
+
+/// Reads the global configuration file, returning an empty table if it does
+/// not exist.
+fn read_global_config() -> miette::Result<toml::Table> {
+    let Some(path) = global_config_path() else {
+        return Ok(toml::Table::new());
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(toml::Table::new()),
+        Err(err) => {
+            return Err(err)
+                .into_diagnostic()
+                .with_context(|| format!("failed to read {}", path.display()))
+        }
+    };
+    toml::from_str(&contents)
+        .into_diagnostic()
+        .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Recursively merges `other` into `base`. Values in `other` take precedence.
+fn merge_tables(base: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(other)) => {
+                merge_tables(base, other)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Checks that [`BACKEND_VERSION`] is at least the version `required` by
+/// `manifest`.
+pub fn check_required_version(required: &str, manifest: &Manifest) -> miette::Result<()> {
+    let parse = |version: &str| {
+        Version::from_str(version)
+            .into_diagnostic()
+            .with_context(|| format!("invalid version '{version}' in `requires-version`"))
+    };
+    if parse(BACKEND_VERSION)? < parse(required)? {
+        miette::bail!(
+            "{} requires pixi-build >= {required}, this is {BACKEND_VERSION}",
+            manifest.path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Configuration values that are specified on the command line. These take
+/// precedence over the values in the manifest and the global configuration
+/// file.
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct ConfigOverrides {
+    /// Overrides the build number of the package.
+    #[clap(long, global = true)]
+    pub build_number: Option<u64>,
+
+    /// Require the lock file to be up to date with the manifest. The
+    /// environments are never solved, instead an error is returned if they
+    /// are not locked.
+    #[clap(long, global = true, conflicts_with = "frozen")]
+    pub locked: bool,
+
+    /// Use the environments from the lock file as is, without checking them
+    /// against the manifest. The environments are never solved and the lock
+    /// file is never updated.
+    #[clap(long, global = true)]
+    pub frozen: bool,
+
+    /// Overrides the local channel to which built packages are added.
+    #[clap(long, global = true, value_parser = absolute_path)]
+    pub output_channel: Option<PathBuf>,
+
+    /// Overrides the existing environment that is used as the host
+    /// environment.
+    #[clap(long, global = true, value_parser = absolute_path)]
+    pub host_prefix: Option<PathBuf>,
+
+    /// Overrides the number of parallel jobs that compiled backends use.
+    #[clap(long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// Overrides the program that runs after the package is built. The path
+    /// of the package is passed as the only argument.
+    #[clap(long, global = true)]
+    pub post_build_hook: Option<String>,
+
+    /// The platform the package is built for. Defaults to the host platform,
+    /// or `noarch` for noarch packages.
+    #[clap(long, global = true)]
+    pub target_platform: Option<Platform>,
+
+    /// Report for every channel whether its repodata could be fetched and how
+    /// many records match the dependencies of the package, before the
+    /// environments are solved.
+    #[clap(long, global = true)]
+    pub diagnose_channels: bool,
+
+    /// Capture the log of every build to `build.log` in its work directory.
+    /// The file is rotated to `build.log.1` once it exceeds this size in
+    /// megabytes.
+    #[clap(long, global = true, value_name = "MB", value_parser = clap::value_parser!(u64).range(1..))]
+    pub build_log_max_size: Option<u64>,
+
+    /// Write the rendered build script to the work directory of the build
+    /// for inspection.
+    #[clap(long, global = true)]
+    pub keep_build_script: bool,
+
+    /// Resolve the dependencies against the repodata in this directory
+    /// instead of the channels, e.g. to reproduce a solve with patched
+    /// repodata. The directory must be laid out like a channel, with a
+    /// `noarch/repodata.json` and a `repodata.json` for every other platform
+    /// that is resolved. The packages are still downloaded from the urls in
+    /// the repodata, so a build needs network access unless the packages are
+    /// already cached. Intended for testing only.
+    #[clap(long, global = true, value_name = "DIR")]
+    pub repodata_source: Option<PathBuf>,
+
+    /// Fail instead of warning about the configuration and the recipe, e.g.
+    /// about unknown keys or a defaulted version.
+    #[clap(long, global = true)]
+    pub strict: bool,
+
+    /// Write a snapshot of the build and host environments next to the built
+    /// package.
+    #[clap(long, global = true)]
+    pub snapshot_environments: bool,
+
+    /// Write a CycloneDX software bill of materials next to the built
+    /// package.
+    #[clap(long, global = true)]
+    pub sbom: bool,
+
+    /// Overrides whether the built package is checked for absolute paths of
+    /// the build.
+    #[clap(long, global = true, value_enum)]
+    pub check_relocatability: Option<RelocatabilityCheck>,
+
+    /// Whether the output of the build is colored. With `auto` the output is
+    /// colored if stderr is a terminal, unless `NO_COLOR` or `CLICOLOR_FORCE`
+    /// says otherwise.
+    #[clap(long, global = true, value_enum)]
+    pub color: Option<ColorMode>,
+
+    /// Overrides how the python backend installs the package.
+    #[clap(long, global = true, value_enum)]
+    pub install_mode: Option<InstallMode>,
+}
+
+/// Parses a path from the command line and makes it absolute, so that it does
+/// not depend on the directory of the manifest.
+fn absolute_path(value: &str) -> Result<PathBuf, String> {
+    std::path::absolute(value).map_err(|err| err.to_string())
+}
+
+/// Determines how the source directory is made available to the build.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceMode {
+    /// The source directory is copied into the work directory before building.
+    /// This ensures the build cannot modify the original sources.
+    #[default]
+    Copy,
+
+    /// The build runs directly against the original source directory, as if
+    /// the work directory was a symlink to it. This avoids copying large
+    /// source trees, but build tools may write build artifacts into (or
+    /// otherwise modify) the source directory. Since no actual symlink is
+    /// created, this also works on platforms where creating symlinks requires
+    /// elevated privileges.
+    InPlace,
+}
+
+/// Determines how absolute paths of the build that remain in a built package
+/// are reported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelocatabilityCheck {
+    /// The package is not checked.
+    #[default]
+    Off,
+
+    /// Every occurrence is reported as a warning.
+    Warn,
+
+    /// The build fails if the package contains an absolute path of the build.
+    Error,
+}
+
+/// Determines whether the output of a build is colored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorMode {
+    /// Color the output if stderr is a terminal. `NO_COLOR` disables and
+    /// `CLICOLOR_FORCE` enables colors regardless of the terminal.
+    #[default]
+    Auto,
+
+    /// Always color the output.
+    Always,
+
+    /// Never color the output.
+    Never,
+}
+
+impl ColorMode {
+    /// Returns whether the output should be colored, which is used for
+    /// `force_colors` in the build configuration.
+    pub fn force_colors(&self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                    false
+                } else if std::env::var_os("CLICOLOR_FORCE").is_some_and(|value| value != "0") {
+                    true
+                } else {
+                    std::io::stderr().is_terminal()
+                }
+            }
+        }
+    }
+}
+
+/// Determines where the version of the package is read from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionSource {
+    /// The version in the manifest, or `0.1.0` if it does not specify one.
+    #[default]
+    Manifest,
+
+    /// The most recent tag of the git repository that contains the manifest,
+    /// see [`crate::dynamic_version`].
+    GitTag,
+}
+
+/// Determines how the python backend installs the package into the prefix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallMode {
+    /// The package is installed directly from the source directory.
+    #[default]
+    Direct,
+
+    /// A wheel is built first, which is then installed. The wheel is kept in
+    /// the `wheels` directory next to the work directory, which makes it
+    /// possible to inspect it and to distinguish build failures from install
+    /// failures.
+    Wheel,
+}
+
+/// Returns an error if `noarch` in `table` is an array or a table, e.g. to
+/// build a noarch and a platform specific output of one package. That is
+/// configured with `platform-output` instead, see [`NoArch`].
+fn check_single_noarch(table: &toml::Table) -> miette::Result<()> {
+    if let Some(toml::Value::Array(_) | toml::Value::Table(_)) = table.get("noarch") {
+        miette::bail!(
+            help = "configure the platform specific files in `[tool.pixi-build.platform-output]` to build them as a separate output",
+            "`noarch` must be a single noarch type"
+        );
+    }
+    Ok(())
+}
+
+/// The platform specific output of a noarch package, e.g. a small compiled
+/// helper of an otherwise portable python package. The files of the build
+/// that match `files` end up in the output `name` instead of the noarch
+/// package, see [`crate::platform_output`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PlatformOutputConfig {
+    /// The name of the platform specific output.
+    pub name: PackageName,
+
+    /// Globs of the files that belong to the platform specific output, e.g.
+    /// `bin/helper*`.
+    pub files: Vec<String>,
+}
+
+impl PlatformOutputConfig {
+    /// Checks that the output has files and a name that differs from the
+    /// `package_name` of the manifest, and that the package is not a
+    /// metapackage, which has no files to split.
+    fn validate(&self, package_name: &PackageName, metapackage: bool) -> miette::Result<()> {
+        if &self.name == package_name {
+            miette::bail!(
+                "the platform output must not have the name of the package, `{}`",
+                package_name.as_source()
+            );
+        }
+        if self.files.is_empty() {
+            miette::bail!("`platform-output.files` must contain at least one glob");
+        }
+        if metapackage {
+            miette::bail!("a metapackage has no files, so it cannot have a `platform-output`");
+        }
+        validate_globs("platform-output.files", &self.files)
+    }
+}
+
+/// The noarch type of a package.
+///
+/// The type applies to the whole package. A package that is mostly portable
+/// but ships a small platform specific helper configures the helper as a
+/// [`PlatformOutputConfig`], so it is built as a separate platform specific
+/// package that the noarch package depends on. Configuring several noarch
+/// types is rejected by [`BackendConfig::from_manifest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NoArch {
+    /// A python package that can be installed for any platform and python
+    /// version.
+    Python,
+
+    /// A package that does not contain platform specific files, e.g. data or
+    /// scripts.
+    Generic,
+
+    /// A package that is built for a specific platform.
+    None,
+}
+
+impl NoArch {
+    /// Returns the name of the noarch type as it is written in the
+    /// configuration.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoArch::Python => "python",
+            NoArch::Generic => "generic",
+            NoArch::None => "none",
+        }
+    }
+}
+
+impl From<NoArch> for NoArchType {
+    fn from(value: NoArch) -> Self {
+        match value {
+            NoArch::Python => NoArchType::python(),
+            NoArch::Generic => NoArchType::generic(),
+            NoArch::None => NoArchType::none(),
+        }
+    }
+}
+
+/// Configuration of the files that are included in the package.
+///
+/// By default, all files that are added to the prefix by the build script end
+/// up in the package. Specifying `include` globs restricts the package to only
+/// the new files that match one of the globs. Files that match one of the
+/// `always-include` globs are included even if they already existed in the
+/// prefix before the build started (e.g. because they are part of a host
+/// dependency), and regardless of whether they match an `include` glob.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FilesConfig {
+    /// Globs of the files to include in the package.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Globs of the files to exclude from the package. Files that are
+    /// installed into the prefix by the build but match one of these globs
+    /// are not packaged, even if they match `include`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Globs of the files to always include in the package.
+    #[serde(default)]
+    pub always_include: Vec<String>,
+}
+
+impl FilesConfig {
+    /// Returns the globs that should be used for `build.files` in the recipe.
+    pub fn files(&self) -> miette::Result<GlobVec> {
+        validate_globs("files.include", &self.include)?;
+        validate_globs("files.exclude", &self.exclude)?;
+        Ok(GlobVec::from_vec(
+            self.include.iter().map(String::as_str).collect(),
+            Some(self.exclude.iter().map(String::as_str).collect()),
+        ))
+    }
+
+    /// Returns the globs that should be used for `build.always_include_files`
+    /// in the recipe.
+    pub fn always_include_files(&self) -> miette::Result<GlobVec> {
+        glob_vec("files.always-include", &self.always_include)
+    }
+}
+
+/// Configuration of the detection of the build prefix in the files of the
+/// package. By default, the build prefix is detected in all text and binary
+/// files and replaced when the package is installed.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PrefixDetectionConfig {
+    /// Skip all binary files. This avoids scanning (and rewriting) large
+    /// prebuilt binaries.
+    #[serde(default)]
+    pub ignore_binary_files: bool,
+
+    /// Globs of the files, text or binary, in which the build prefix is
+    /// neither detected nor replaced.
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+impl PrefixDetectionConfig {
+    /// Returns the value that should be used for `build.prefix_detection` in
+    /// the recipe.
+    pub fn prefix_detection(&self) -> miette::Result<PrefixDetection> {
+        let ignore = if self.ignore.is_empty() {
+            PrefixIgnore::default()
+        } else {
+            PrefixIgnore::Patterns(glob_vec("prefix-detection.ignore", &self.ignore)?)
+        };
+        Ok(PrefixDetection {
+            ignore_binary_files: self.ignore_binary_files,
+            ignore,
+            ..PrefixDetection::default()
+        })
+    }
+}
+
+/// Configuration of the handling of shared libraries in the package.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DynamicLinkingConfig {
+    /// The rpaths, relative to the prefix, that are set on the shared
+    /// libraries and executables. Defaults to `lib/`.
+    #[serde(default)]
+    pub rpaths: Vec<String>,
+
+    /// Globs of the rpaths that are allowed in addition to the ones in the
+    /// prefix.
+    #[serde(default)]
+    pub rpath_allowlist: Vec<String>,
+
+    /// Globs of the shared libraries that are allowed to be linked against
+    /// without being provided by a dependency of the package, e.g. system
+    /// libraries.
+    #[serde(default)]
+    pub missing_dso_allowlist: Vec<String>,
+}
+
+impl DynamicLinkingConfig {
+    /// Returns the value that should be used for `build.dynamic_linking` in
+    /// the recipe.
+    pub fn dynamic_linking(&self) -> miette::Result<DynamicLinking> {
+        Ok(DynamicLinking {
+            rpaths: self.rpaths.clone(),
+            rpath_allowlist: glob_vec("dynamic-linking.rpath-allowlist", &self.rpath_allowlist)?,
+            missing_dso_allowlist: glob_vec(
+                "dynamic-linking.missing-dso-allowlist",
+                &self.missing_dso_allowlist,
+            )?,
+            ..DynamicLinking::default()
+        })
+    }
+}
+
+/// Determines which keys of the variant (see
+/// [`crate::conda_build_config`]) contribute to the build string of the
+/// package. By default only the keys that correspond to a build or host
+/// dependency are used.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VariantConfig {
+    /// Variant keys that are used even if no dependency refers to them.
+    #[serde(default)]
+    pub use_keys: Vec<String>,
+
+    /// Variant keys that are never used, e.g. because they are only relevant
+    /// for solving the environments.
+    #[serde(default)]
+    pub ignore_keys: Vec<String>,
+}
+
+impl VariantConfig {
+    /// Returns the value that should be used for `build.variant` in the
+    /// recipe.
+    pub fn key_usage(&self) -> VariantKeyUsage {
+        VariantKeyUsage {
+            use_keys: self.use_keys.clone(),
+            ignore_keys: self.ignore_keys.clone(),
+            ..VariantKeyUsage::default()
+        }
+    }
+}
+
+/// A regex replacement that is applied to files of the package after the
+/// build, before it is packaged.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PostProcessConfig {
+    /// Globs of the files, relative to the prefix, that the replacement is
+    /// applied to.
+    pub files: Vec<String>,
+
+    /// The regular expression that is searched for.
+    pub regex: String,
+
+    /// The replacement of every match, which can refer to capture groups,
+    /// e.g. `$1`.
+    pub replacement: String,
+}
+
+impl PostProcessConfig {
+    /// Returns the post processing step of the `index`-th entry of
+    /// `post-process`.
+    fn post_process(&self, index: usize) -> miette::Result<PostProcess> {
+        let key = format!("post-process[{index}]");
+        if self.files.is_empty() {
+            miette::bail!("`{key}.files` must contain at least one glob");
+        }
+        let regex = SerializableRegex::deserialize(self.regex.as_str().into_deserializer())
+            .map_err(|err: serde::de::value::Error| {
+                miette::miette!("invalid regex '{}' in `{key}.regex`: {err}", self.regex)
+            })?;
+        Ok(PostProcess {
+            files: glob_vec(&format!("{key}.files"), &self.files)?,
+            regex,
+            replacement: self.replacement.clone(),
+        })
+    }
+}
+
+/// The environment of the build script.
+///
+/// The values in `env` are stored in the rendered recipe that ends up in the
+/// package, so they must not contain secrets. Variables that are listed in
+/// `secrets` are instead passed through from the environment of the backend.
+/// Only their names are stored in the recipe, and rattler-build masks their
+/// values in the build log.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ScriptEnvConfig {
+    /// Environment variables with a fixed value.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+
+    /// The names of environment variables that are passed through to the
+    /// build script without being recorded, e.g. tokens for private indexes.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+}
+
+impl ScriptEnvConfig {
+    /// Checks that no variable is configured both with a value and as a
+    /// secret.
+    pub fn validate(&self) -> miette::Result<()> {
+        if let Some(name) = self
+            .secrets
+            .iter()
+            .find(|name| self.env.contains_key(*name))
+        {
+            miette::bail!(
+                "`{name}` is specified in both `script-env.env` and `script-env.secrets`"
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the script that should be used for `build.script` in the
+    /// recipe, which runs `content` in the configured environment.
+    pub fn script(&self, content: ScriptContent) -> Script {
+        for name in &self.secrets {
+            if std::env::var_os(name).is_none() {
+                tracing::warn!("the secret `{name}` is not set in the environment");
+            }
+        }
+        Script {
+            env: self
+                .env
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+            secrets: self.secrets.clone(),
+            content,
+            ..Script::default()
+        }
+    }
+}
+
+/// The run exports of the package. Each entry is a match spec that is added to
+/// the corresponding section of packages that depend on this package.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RunExportsConfig {
+    /// Run dependencies that are added to noarch packages.
+    #[serde(default)]
+    pub noarch: Vec<String>,
+
+    /// Run dependencies that are added when the package is used as a host
+    /// dependency.
+    #[serde(default)]
+    pub weak: Vec<String>,
+
+    /// Run dependencies that are added when the package is used as a build or
+    /// host dependency.
+    #[serde(default)]
+    pub strong: Vec<String>,
+
+    /// Run constraints that are added when the package is used as a host
+    /// dependency.
+    #[serde(default)]
+    pub weak_constraints: Vec<String>,
+
+    /// Run constraints that are added when the package is used as a build or
+    /// host dependency.
+    #[serde(default)]
+    pub strong_constraints: Vec<String>,
+}
+
+impl RunExportsConfig {
+    /// Returns the run exports that should be used for
+    /// `requirements.run_exports` in the recipe.
+    pub fn run_exports(&self) -> miette::Result<RunExports> {
+        Ok(RunExports {
+            noarch: dependencies("run-exports.noarch", &self.noarch)?,
+            weak: dependencies("run-exports.weak", &self.weak)?,
+            strong: dependencies("run-exports.strong", &self.strong)?,
+            weak_constraints: dependencies("run-exports.weak-constraints", &self.weak_constraints)?,
+            strong_constraints: dependencies(
+                "run-exports.strong-constraints",
+                &self.strong_constraints,
+            )?,
+        })
+    }
+}
+
+/// The bounds of a `pin_compatible` run constraint. The bounds are either a
+/// pin expression such as `x.x`, which keeps that many segments of the
+/// version in the host environment, or a concrete version.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PinCompatibleConfig {
+    /// The lower bound of the constraint. Defaults to the exact version in
+    /// the host environment.
+    pub lower_bound: Option<String>,
+
+    /// The upper bound of the constraint. Defaults to `x`, the next major
+    /// version.
+    pub upper_bound: Option<String>,
+
+    /// Pin the exact version and build string of the host package instead.
+    #[serde(default)]
+    pub exact: bool,
+}
+
+impl PinCompatibleConfig {
+    /// Returns the arguments of the pin of `name`.
+    fn pin_args(&self, name: &str) -> miette::Result<PinArgs> {
+        let bound = |key: &str, bound: &Option<String>| {
+            bound
+                .as_deref()
+                .map(|bound| {
+                    PinBound::from_str(bound).map_err(|err| {
+                        miette::miette!(
+                            "invalid bound '{bound}' in `pin-compatible.{name}.{key}`: {err}"
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let mut args = PinArgs {
+            exact: self.exact,
+            ..PinArgs::default()
+        };
+        if let Some(lower_bound) = bound("lower-bound", &self.lower_bound)? {
+            args.lower_bound = Some(lower_bound);
+        }
+        if let Some(upper_bound) = bound("upper-bound", &self.upper_bound)? {
+            args.upper_bound = Some(upper_bound);
+        }
+        Ok(args)
+    }
+}
+
+/// A test that runs commands in an environment that contains the package.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TestConfig {
+    /// The commands to execute.
+    #[serde(default)]
+    pub commands: Vec<String>,
+
+    /// Match specs of additional packages that are installed in the test
+    /// environment. These do not become run dependencies of the package.
+    #[serde(default)]
+    pub requires: Vec<String>,
+}
+
+impl TestConfig {
+    /// Returns the tests that should be used for `tests` in the recipe.
+    pub fn tests(&self) -> miette::Result<Vec<TestType>> {
+        if self.commands.is_empty() {
+            if !self.requires.is_empty() {
+                miette::bail!("`test.requires` is specified but there are no `test.commands`");
+            }
+            return Ok(vec![]);
+        }
+
+        // Validate the specs, but pass them on as strings like the recipe expects.
+        dependencies("test.requires", &self.requires)?;
+
+        Ok(vec![TestType::Command(CommandsTest {
+            script: ScriptContent::Commands(self.commands.clone()).into(),
+            requirements: CommandsTestRequirements {
+                run: self.requires.clone(),
+                build: vec![],
+            },
+            ..CommandsTest::default()
+        })])
+    }
+}
+
+/// Parses a list of match specs into recipe dependencies.
+fn dependencies(key: &str, specs: &[String]) -> miette::Result<Vec<Dependency>> {
+    specs
+        .iter()
+        .map(|spec| {
+            MatchSpec::from_str(spec, ParseStrictness::Strict)
+                .map(Dependency::Spec)
+                .into_diagnostic()
+                .with_context(|| format!("invalid match spec '{spec}' in `{key}`"))
+        })
+        .collect()
+}
+
+/// Converts a list of globs into a [`GlobVec`], validating each of the
+/// patterns.
+fn glob_vec(key: &str, globs: &[String]) -> miette::Result<GlobVec> {
+    validate_globs(key, globs)?;
+    Ok(GlobVec::from_vec(
+        globs.iter().map(String::as_str).collect(),
+        None,
+    ))
+}
+
+/// Validates that each of the globs is a valid pattern.
+fn validate_globs(key: &str, globs: &[String]) -> miette::Result<()> {
+    for glob in globs {
+        globset::Glob::new(glob)
+            .into_diagnostic()
+            .with_context(|| format!("invalid glob '{glob}' in `{key}`"))?;
+    }
+    Ok(())
+}
+
+/// Options that are passed on to the tool configuration of rattler-build.
+/// Options that are not specified keep the value chosen by the backend.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ToolOptions {
+    /// Whether to keep the build directory after the build has finished.
+    pub keep_build: Option<bool>,
+
+    /// The number of threads to use for compressing the package.
+    pub compression_threads: Option<u32>,
+
+    /// The maximum number of concurrent io operations, e.g. downloads.
+    pub io_concurrency_limit: Option<usize>,
+
+    /// Whether to use zstd compressed repodata when available.
+    pub use_zstd: Option<bool>,
+
+    /// Whether to use bz2 compressed repodata when available.
+    pub use_bz2: Option<bool>,
+}
+
+impl ToolOptions {
+    /// Applies the options that have been specified to the given builder.
+    pub fn apply(&self, mut builder: ConfigurationBuilder) -> ConfigurationBuilder {
+        if let Some(keep_build) = self.keep_build {
+            builder = builder.with_keep_build(keep_build);
+        }
+        if let Some(compression_threads) = self.compression_threads {
+            builder = builder.with_compression_threads(Some(compression_threads));
+        }
+        if let Some(io_concurrency_limit) = self.io_concurrency_limit {
+            builder = builder.with_io_concurrency_limit(Some(io_concurrency_limit));
+        }
+        if let Some(use_zstd) = self.use_zstd {
+            builder = builder.with_zstd_repodata_enabled(use_zstd);
+        }
+        if let Some(use_bz2) = self.use_bz2 {
+            builder = builder.with_bz2_repodata_enabled(use_bz2);
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// Parses the contents of a `[tool.pixi-build]` table.
+    fn config(contents: &str) -> BackendConfig {
+        toml::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn files_include_only_the_matching_files() {
+        let config = config(
+            r#"
+            [files]
+            include = ["bin/*", "share/data/**"]
+            "#,
+        );
+        let files = config.files.files().unwrap();
+        assert!(files.is_match(Path::new("bin/tool")));
+        assert!(files.is_match(Path::new("share/data/a/b.txt")));
+        assert!(!files.is_match(Path::new("lib/libtool.so")));
+    }
+
+    #[test]
+    fn files_include_everything_by_default() {
+        let files = BackendConfig::default().files.files().unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn invalid_include_globs_are_rejected() {
+        let config = config(
+            r#"
+            [files]
+            include = ["bin/[a"]
+            "#,
+        );
+        let err = config.files.files().unwrap_err();
+        assert!(err.to_string().contains("`files.include`"), "{err}");
+    }
+
+    #[test]
+    fn test_requires_needs_commands() {
+        let config = config(
+            r#"
+            [test]
+            requires = ["pytest"]
+            "#,
+        );
+        let err = config.test.tests().unwrap_err();
+        assert!(err.to_string().contains("no `test.commands`"), "{err}");
+    }
+
+    #[test]
+    fn test_requires_are_installed_in_the_test_environment() {
+        let config = config(
+            r#"
+            [test]
+            commands = ["pytest"]
+            requires = ["pytest >=8"]
+            "#,
+        );
+        let tests = config.test.tests().unwrap();
+        let [TestType::Command(test)] = tests.as_slice() else {
+            panic!("expected a single command test, got {tests:?}");
+        };
+        assert_eq!(test.requirements.run, vec!["pytest >=8".to_string()]);
+        assert!(test.requirements.build.is_empty());
+    }
+
+    #[test]
+    fn invalid_test_requires_are_rejected() {
+        let config = config(
+            r#"
+            [test]
+            commands = ["pytest"]
+            requires = ["pytest >=>8"]
+            "#,
+        );
+        let err = config.test.tests().unwrap_err();
+        assert!(err.to_string().contains("`test.requires`"), "{err}");
+    }
+
+    #[test]
+    fn files_exclude_takes_precedence_over_include() {
+        let config = config(
+            r#"
+            [files]
+            include = ["lib/**"]
+            exclude = ["lib/**/*.a"]
+            "#,
+        );
+        let files = config.files.files().unwrap();
+        assert!(files.is_match(Path::new("lib/libfoo.so")));
+        assert!(!files.is_match(Path::new("lib/libfoo.a")));
+        assert!(!files.is_match(Path::new("lib/static/libbar.a")));
+    }
+
+    #[test]
+    fn invalid_exclude_globs_are_rejected() {
+        let config = config(
+            r#"
+            [files]
+            exclude = ["lib/[a"]
+            "#,
+        );
+        let err = config.files.files().unwrap_err();
+        assert!(err.to_string().contains("`files.exclude`"), "{err}");
+    }
+
+    #[test]
+    fn installer_args_are_validated() {
+        let config = config(r#"installer-args = ["--no-build-isolation", "-v"]"#);
+        assert_eq!(
+            config.installer_args().unwrap(),
+            ["--no-build-isolation".to_string(), "-v".to_string()]
+        );
+
+        for invalid in [r#"[""]"#, r#"["--config=\"a\""]"#, r#"["-v\n--force"]"#] {
+            let config = self::config(&format!("installer-args = {invalid}"));
+            let err = config.installer_args().unwrap_err();
+            assert!(err.to_string().contains("`installer-args`"), "{err}");
+        }
+    }
+
+    #[test]
+    fn prefix_detection_ignores_the_configured_files() {
+        let config = config(
+            r#"
+            [prefix-detection]
+            ignore-binary-files = true
+            ignore = ["share/doc/**"]
+            "#,
+        );
+        let prefix_detection = config.prefix_detection.prefix_detection().unwrap();
+        assert!(prefix_detection.ignore_binary_files);
+        let PrefixIgnore::Patterns(ignore) = prefix_detection.ignore else {
+            panic!("expected the ignored files to be patterns");
+        };
+        assert!(ignore.is_match(Path::new("share/doc/readme.txt")));
+        assert!(!ignore.is_match(Path::new("bin/tool")));
+    }
+
+    #[test]
+    fn prefix_detection_defaults_to_all_files() {
+        let prefix_detection = BackendConfig::default()
+            .prefix_detection
+            .prefix_detection()
+            .unwrap();
+        assert!(!prefix_detection.ignore_binary_files);
+        assert!(!matches!(
+            prefix_detection.ignore,
+            PrefixIgnore::Patterns(_)
+        ));
+    }
+
+    #[test]
+    fn unknown_prefix_detection_keys_are_rejected() {
+        let result = toml::from_str::<BackendConfig>(
+            r#"
+            [prefix-detection]
+            ignore-binaries = true
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn dynamic_linking_is_passed_to_the_recipe() {
+        let config = config(
+            r#"
+            [dynamic-linking]
+            rpaths = ["lib/", "lib/plugins/"]
+            rpath-allowlist = ["/opt/vendor/*"]
+            missing-dso-allowlist = ["**/libcuda.so*"]
+            "#,
+        );
+        let dynamic_linking = config.dynamic_linking.dynamic_linking().unwrap();
+        assert_eq!(dynamic_linking.rpaths, ["lib/", "lib/plugins/"]);
+        assert!(dynamic_linking
+            .rpath_allowlist
+            .is_match(Path::new("/opt/vendor/lib")));
+        assert!(dynamic_linking
+            .missing_dso_allowlist
+            .is_match(Path::new("usr/lib/libcuda.so.1")));
+    }
+
+    #[test]
+    fn invalid_dynamic_linking_globs_name_the_key() {
+        let config = config(
+            r#"
+            [dynamic-linking]
+            missing-dso-allowlist = ["lib[a"]
+            "#,
+        );
+        let err = config.dynamic_linking.dynamic_linking().unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`dynamic-linking.missing-dso-allowlist`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn script_env_sets_the_variables_and_secrets() {
+        let config = config(
+            r#"
+            [script-env]
+            env = { CFLAGS = "-O2" }
+            secrets = ["INDEX_TOKEN"]
+            "#,
+        );
+        config.script_env.validate().unwrap();
+        let script = config
+            .script_env
+            .script(ScriptContent::Commands(vec!["make".to_string()]));
+        assert_eq!(script.env.get("CFLAGS").map(String::as_str), Some("-O2"));
+        assert_eq!(script.secrets, ["INDEX_TOKEN"]);
+        assert!(!script.env.contains_key("INDEX_TOKEN"));
+        assert!(
+            matches!(script.content, ScriptContent::Commands(commands) if commands == ["make"])
+        );
+    }
+
+    #[test]
+    fn script_env_rejects_secrets_with_a_value() {
+        let config = config(
+            r#"
+            [script-env]
+            env = { INDEX_TOKEN = "hunter2" }
+            secrets = ["INDEX_TOKEN"]
+            "#,
+        );
+        let err = config.script_env.validate().unwrap_err();
+        assert!(err.to_string().contains("`INDEX_TOKEN`"), "{err}");
+    }
+
+    #[test]
+    fn parallel_scripts_export_the_job_count() {
+        let config = config(
+            r#"
+            jobs = 3
+            [script-env]
+            env = { CFLAGS = "-O2" }
+            "#,
+        );
+        let script = config
+            .parallel_script(ScriptContent::Commands(vec!["make".to_string()]))
+            .unwrap();
+        assert_eq!(script.env.get(JOBS_ENV_VAR).map(String::as_str), Some("3"));
+        assert_eq!(script.env.get("CFLAGS").map(String::as_str), Some("-O2"));
+
+        let err = self::config("jobs = 0")
+            .parallel_script(ScriptContent::Commands(vec![]))
+            .unwrap_err();
+        assert!(err.to_string().contains("`jobs`"), "{err}");
+    }
+
+    /// Returns the tool configuration of `config` with the default of a
+    /// backend for keeping the build directory.
+    fn tool_configuration(config: &BackendConfig, keep_build: bool) -> Configuration {
+        config
+            .tool_configuration(
+                None,
+                LoggingOutputHandler::default(),
+                ChannelConfig::default_with_root_dir(std::env::temp_dir()),
+                keep_build,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn backends_only_differ_in_keeping_the_build() {
+        let config = BackendConfig::default();
+        let keeps_build = tool_configuration(&config, true);
+        let removes_build = tool_configuration(&config, false);
+
+        assert!(keeps_build.keep_build);
+        assert!(!removes_build.keep_build);
+        assert_eq!(keeps_build.channel_config, removes_build.channel_config);
+        assert_eq!(
+            keeps_build.compression_threads,
+            removes_build.compression_threads
+        );
+    }
+
+    #[test]
+    fn tool_options_override_the_default_of_the_backend() {
+        let config = config(
+            r#"
+            [tool-options]
+            keep-build = false
+            "#,
+        );
+        assert!(!tool_configuration(&config, true).keep_build);
+
+        let config = self::config(
+            r#"
+            [tool-options]
+            keep-build = true
+            "#,
+        );
+        assert!(tool_configuration(&config, false).keep_build);
+    }
+
+    /// Reads the configuration of a manifest with the given
+    /// `[tool.pixi-build]` table on top of the `global` configuration, for a
+    /// backend that supports `supported_keys`.
+    fn try_from_manifest(
+        manifest_table: &str,
+        global: &str,
+        supported_keys: &[&str],
+    ) -> miette::Result<BackendConfig> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pixi.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+                [project]
+                name = "package"
+                version = "1.0.0"
+                channels = []
+                platforms = ["linux-64"]
+
+                [tool.pixi-build]
+                {manifest_table}
+                "#
+            ),
+        )
+        .unwrap();
+        let manifest = crate::manifest_ext::load_manifest(&path).unwrap();
+        BackendConfig::from_manifest_and_global_config(
+            &manifest,
+            toml::from_str(global).unwrap(),
+            supported_keys,
+        )
+    }
+
+    /// Reads the configuration with [`try_from_manifest`] and asserts that it
+    /// is valid.
+    fn from_manifest(manifest_table: &str, global: &str, supported_keys: &[&str]) -> BackendConfig {
+        try_from_manifest(manifest_table, global, supported_keys).unwrap()
+    }
+
+    #[test]
+    fn several_noarch_types_are_rejected() {
+        for noarch in [
+            r#"noarch = ["generic", "none"]"#,
+            r#"noarch = { python = "generic", helper = "none" }"#,
+        ] {
+            let err = try_from_manifest(noarch, "", &["noarch"]).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("`noarch` must be a single noarch type"),
+                "{noarch}: {err}"
+            );
+            let help = err.help().unwrap().to_string();
+            assert!(help.contains("platform-output"), "{noarch}: {help}");
+        }
+        assert_eq!(
+            from_manifest(r#"noarch = "generic""#, "", &["noarch"]).noarch,
+            Some(NoArch::Generic)
+        );
+    }
+
+    /// Reads the manifest with the given `[tool.pixi-build]` table and
+    /// reports the warnings about it, for a backend that supports `jobs` and
+    /// assumes `noarch = "python"`.
+    fn report_warnings(manifest_table: &str) -> miette::Result<()> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pixi.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+                [project]
+                name = "package"
+                version = "1.0.0"
+                channels = []
+                platforms = ["linux-64"]
+
+                [tool.pixi-build]
+                {manifest_table}
+                "#
+            ),
+        )
+        .unwrap();
+        let manifest = crate::manifest_ext::load_manifest(&path).unwrap();
+        BackendConfig::from_manifest_and_global_config(&manifest, toml::Table::new(), &["jobs"])?
+            .report_warnings(&manifest, NoArch::Python)
+    }
+
+    #[test]
+    fn the_default_noarch_type_is_not_an_error_in_strict_mode() {
+        report_warnings("strict = true").unwrap();
+        report_warnings("strict = true\njobs = 2").unwrap();
+    }
+
+    #[test]
+    fn warnings_are_errors_in_strict_mode() {
+        report_warnings("build-number = 1").unwrap();
+        let err = report_warnings("strict = true\nbuild-number = 1").unwrap_err();
+        assert!(
+            err.to_string().contains("which are errors in strict mode"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn platform_outputs_are_validated() {
+        let keys = &["metapackage", "platform-output"];
+        let config = from_manifest(
+            r#"platform-output = { name = "package-helper", files = ["bin/helper*"] }"#,
+            "",
+            keys,
+        );
+        let platform_output = config.platform_output.unwrap();
+        assert_eq!(platform_output.name.as_normalized(), "package-helper");
+        assert_eq!(platform_output.files, ["bin/helper*"]);
+
+        for (table, message) in [
+            (
+                r#"platform-output = { name = "package", files = ["bin/*"] }"#,
+                "must not have the name of the package",
+            ),
+            (
+                r#"platform-output = { name = "package-helper", files = [] }"#,
+                "must contain at least one glob",
+            ),
+            (
+                r#"platform-output = { name = "package-helper", files = ["bin/[helper"] }"#,
+                "invalid glob 'bin/[helper' in `platform-output.files`",
+            ),
+            (
+                "metapackage = true\nplatform-output = { name = \"package-helper\", files = [\"bin/*\"] }",
+                "cannot have a `platform-output`",
+            ),
+        ] {
+            let err = try_from_manifest(table, "", keys).unwrap_err();
+            assert!(err.to_string().contains(message), "{table}: {err}");
+        }
+    }
+
+    #[test]
+    fn unsupported_keys_of_the_manifest_are_reported() {
+        let config = from_manifest("jobs = 2", "", &["source-dir"]);
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(
+            config.warnings,
+            ["the key `jobs` in the pixi-build configuration is not supported by this backend"]
+        );
+    }
+
+    #[test]
+    fn unsupported_keys_of_the_global_config_are_not_reported() {
+        let config = from_manifest("build-number = 1", "jobs = 2", &["build-number"]);
+        assert_eq!(config.build_number, Some(1));
+        assert_eq!(config.jobs, Some(2));
+        assert!(config.warnings.is_empty(), "{:?}", config.warnings);
+    }
+}
@@ -1,13 +1,32 @@
-use itertools::Either;
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use itertools::{Either, Itertools};
 use miette::IntoDiagnostic;
 use pixi_manifest::CondaDependencies;
-use pixi_spec::SourceSpec;
-use rattler_conda_types::{ChannelConfig, MatchSpec};
+use pixi_spec::{PixiSpec, SourceSpec};
+use rattler_build::recipe::parser::{Dependency, Requirements};
+use rattler_conda_types::{
+    version_spec::EqualityOperator, ChannelConfig, MatchSpec, PackageName, StringMatcher, Version,
+    VersionSpec,
+};
+use reqwest::Url;
+use serde::Deserialize;
+
+/// A source dependency that the frontend has already built. Dependencies on
+/// it are pinned to exactly this version and build string.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuiltSourcePackage {
+    pub name: PackageName,
+    pub version: Version,
+    pub build: String,
+}
 
 /// A helper struct to extract match specs from a manifest.
 pub struct MatchspecExtractor {
     channel_config: ChannelConfig,
     ignore_self: bool,
+    built_sources: HashMap<PackageName, BuiltSourcePackage>,
 }
 
 impl MatchspecExtractor {
@@ -15,11 +34,13 @@ impl MatchspecExtractor {
         Self {
             channel_config,
             ignore_self: false,
+            built_sources: HashMap::new(),
         }
     }
 
     /// If `ignore_self` is `true`, the conversion will skip dependencies that
-    /// point to root directory itself.
+    /// point to root directory itself. Both paths are canonicalized before
+    /// they are compared.
     pub fn with_ignore_self(self, ignore_self: bool) -> Self {
         Self {
             ignore_self,
@@ -27,7 +48,18 @@ impl MatchspecExtractor {
         }
     }
 
-    /// Extracts match specs from the given set of dependencies.
+    /// Registers a source dependency that has already been built. Instead of
+    /// rejecting the source dependency, the conversion will pin it to the exact
+    /// version and build string of the built package.
+    pub fn with_built_source(mut self, package: BuiltSourcePackage) -> Self {
+        self.built_sources.insert(package.name.clone(), package);
+        self
+    }
+
+    /// Extracts match specs from the given set of dependencies. A channel that
+    /// is declared on a dependency (e.g. `conda-forge::numpy`) is kept in
+    /// its match spec, so the solver only takes the package from that
+    /// channel. See [`add_spec_channels`].
     pub fn extract(&self, dependencies: CondaDependencies) -> miette::Result<Vec<MatchSpec>> {
         let root_dir = &self.channel_config.root_dir;
         let mut specs = Vec::new();
@@ -40,18 +72,32 @@ impl MatchspecExtractor {
                     if self.ignore_self
                         && path
                             .resolve(root_dir)
-                            .map_or(false, |path| path.as_path() == root_dir) =>
+                            .map_or(false, |path| is_same_directory(&path, root_dir)) =>
                 {
                     // Skip source dependencies that point to the root directory. That would
                     // be a self reference.
                     continue;
                 }
-                Either::Left(_) => {
+                Either::Left(_) => match self.built_sources.get(&name) {
+                    // Pin source dependencies that have already been built to the exact
+                    // package that was produced.
+                    Some(built) => MatchSpec {
+                        name: Some(name),
+                        version: Some(VersionSpec::Exact(
+                            EqualityOperator::Equals,
+                            built.version.clone(),
+                        )),
+                        build: Some(StringMatcher::Exact(built.build.clone())),
+                        ..MatchSpec::default()
+                    },
                     // All other source dependencies are not yet supported.
-                    return Err(miette::miette!(
-                        "recursive source dependencies are not yet supported"
-                    ));
-                }
+                    None => {
+                        return Err(miette::miette!(
+                            "the source dependency `{}` has not been built, recursive source dependencies are not yet supported",
+                            name.as_source()
+                        ))
+                    }
+                },
                 Either::Right(binary) => MatchSpec::from_nameless(binary, Some(name)),
             };
 
@@ -61,3 +107,492 @@ impl MatchspecExtractor {
         Ok(specs)
     }
 }
+
+/// Returns `true` if `a` and `b` refer to the same directory. Both paths are
+/// canonicalized first, so symlinks or different spellings of the same path
+/// (e.g. `./pkg/../pkg`) compare equal. Paths that cannot be canonicalized,
+/// e.g. because they do not exist, are compared as is.
+fn is_same_directory(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Appends the channels that dependencies in `requirements` are qualified
+/// with to `channels`, unless they are already part of it. The solver only
+/// considers the given channels, so a dependency on a channel that is not
+/// a channel of the project could never be resolved otherwise. The appended
+/// channels have the lowest priority.
+pub fn add_spec_channels(channels: &mut Vec<Url>, requirements: &Requirements) {
+    let spec_channels = requirements
+        .build
+        .iter()
+        .chain(requirements.host.iter())
+        .chain(requirements.run.iter())
+        .filter_map(|dependency| match dependency {
+            Dependency::Spec(spec) => spec.channel.as_ref(),
+            _ => None,
+        })
+        .map(|channel| Url::from(channel.base_url.clone()))
+        .unique()
+        .collect::<Vec<_>>();
+    append_channels(channels, spec_channels);
+}
+
+/// Appends each of `new_channels` to `channels`, unless it is already part of
+/// it.
+pub fn append_channels(channels: &mut Vec<Url>, new_channels: Vec<Url>) {
+    for channel in new_channels {
+        let is_known = channels.iter().any(|url| {
+            url.as_str().trim_end_matches('/') == channel.as_str().trim_end_matches('/')
+        });
+        if !is_known {
+            tracing::debug!("adding the channel {channel} to the channels");
+            channels.push(channel);
+        }
+    }
+}
+
+/// Adds the build `tools` that a backend needs in the host environment to
+/// `host`. A tool that is declared as a run dependency keeps the spec of the
+/// run dependency, otherwise it is added without constraints.
+///
+/// This is the only way in which run dependencies end up in the host
+/// environment. Any other run dependency (e.g. a plugin that is only imported
+/// at runtime) is never installed in the host environment.
+pub fn add_host_tools(host: &mut CondaDependencies, run: &CondaDependencies, tools: &[&str]) {
+    for tool in tools {
+        if host.contains_key(*tool) {
+            // The tool is already declared explicitly.
+            continue;
+        }
+
+        let name = PackageName::from_str(tool).expect("build tools are valid package names");
+        match run.get(*tool) {
+            Some(run_specs) => {
+                for spec in run_specs {
+                    host.insert(name.clone(), spec.clone());
+                }
+            }
+            None => host.insert(name, PixiSpec::default()),
+        }
+    }
+}
+
+/// Adds `tool` to the `run` dependencies with the spec it has in `host`,
+/// unless it is already declared as a run dependency. This is used for
+/// packages that must depend on the same tool at runtime, e.g. noarch python
+/// packages on python, so a bound such as `python >=3.8` that is declared as
+/// a host dependency ends up in the package.
+pub fn add_run_tool(run: &mut CondaDependencies, host: &CondaDependencies, tool: &str) {
+    if run.contains_key(tool) {
+        return;
+    }
+    let name = PackageName::from_str(tool).expect("build tools are valid package names");
+    match host.get(tool) {
+        Some(host_specs) => {
+            for spec in host_specs {
+                run.insert(name.clone(), spec.clone());
+            }
+        }
+        None => run.insert(name, PixiSpec::default()),
+    }
+}
+
+/// Ensures that no package is declared with conflicting specs, either multiple
+/// times within a single dependency section or in both the build and host
+/// sections.
+///
+/// This should be called on the dependencies as declared by the user, before
+/// any build tools are injected by the backend.
+pub fn check_conflicting_dependencies(
+    build: &CondaDependencies,
+    host: &CondaDependencies,
+    run: &CondaDependencies,
+) -> miette::Result<()> {
+    for (section, dependencies) in [("build", build), ("host", host), ("run", run)] {
+        for (name, specs) in dependencies.iter() {
+            if specs.len() > 1 {
+                miette::bail!(
+                    "the package '{}' is declared multiple times in the {section} dependencies with conflicting specs: {}",
+                    name.as_source(),
+                    specs.iter().map(|spec| spec.to_toml_value()).join(", ")
+                );
+            }
+        }
+    }
+
+    for (name, build_specs) in build.iter() {
+        let Some(host_specs) = host.get(name) else {
+            continue;
+        };
+        if build_specs != host_specs {
+            miette::bail!(
+                "the package '{}' is declared in both the build and host dependencies with conflicting specs: {} (build) and {} (host)",
+                name.as_source(),
+                build_specs.iter().map(|spec| spec.to_toml_value()).join(", "),
+                host_specs.iter().map(|spec| spec.to_toml_value()).join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Sorts the dependencies in each section of `requirements` by package name,
+/// so that the recipe, and therefore the hash and build string of the
+/// package, do not depend on the order in which the dependencies were
+/// collected.
+pub fn sort_requirements(requirements: &mut Requirements) {
+    fn sort_key(dependency: &Dependency) -> (String, String) {
+        match dependency {
+            Dependency::Spec(spec) => (
+                spec.name
+                    .as_ref()
+                    .map(|name| name.as_normalized().to_string())
+                    .unwrap_or_default(),
+                spec.to_string(),
+            ),
+            Dependency::PinCompatible(pin) => (
+                pin.pin_compatible.name.as_normalized().to_string(),
+                String::new(),
+            ),
+            // Other kinds of dependencies keep their relative order.
+            _ => Default::default(),
+        }
+    }
+
+    for dependencies in [
+        &mut requirements.build,
+        &mut requirements.host,
+        &mut requirements.run,
+        &mut requirements.run_constraints,
+    ] {
+        dependencies.sort_by_cached_key(sort_key);
+    }
+}
+
+/// Checks that every `pin_compatible` run constraint of `requirements` refers
+/// to a host dependency. The constraint is derived from the version of the
+/// package in the host environment, so it cannot be resolved otherwise.
+///
+/// This has to be called after all host dependencies, including the build
+/// tools of the backend, have been added.
+pub fn check_pin_compatible(requirements: &Requirements) -> miette::Result<()> {
+    for dependency in &requirements.run_constraints {
+        let Dependency::PinCompatible(pin) = dependency else {
+            continue;
+        };
+        let name = &pin.pin_compatible.name;
+        let is_host_dependency = requirements.host.iter().any(|host| match host {
+            Dependency::Spec(spec) => spec.name.as_ref() == Some(name),
+            _ => false,
+        });
+        if !is_host_dependency {
+            miette::bail!(
+                "`pin-compatible` pins '{}', which is not a host dependency of the package",
+                name.as_source()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pixi_spec::PathSpec;
+    use rattler_build::{
+        recipe::parser::PinCompatible,
+        render::pin::{Pin, PinArgs},
+    };
+    use rattler_conda_types::ParseStrictness;
+
+    use super::*;
+
+    /// Parses match specs into recipe dependencies.
+    fn specs(specs: &[&str]) -> Vec<Dependency> {
+        specs
+            .iter()
+            .map(|spec| {
+                Dependency::Spec(MatchSpec::from_str(spec, ParseStrictness::Strict).unwrap())
+            })
+            .collect()
+    }
+
+    /// Returns the match specs of recipe dependencies as strings.
+    fn spec_strings(dependencies: &[Dependency]) -> Vec<String> {
+        dependencies
+            .iter()
+            .map(|dependency| match dependency {
+                Dependency::Spec(spec) => spec.to_string(),
+                _ => panic!("expected a match spec"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn requirements_are_sorted_by_name() {
+        let mut requirements = Requirements {
+            build: specs(&["ninja", "cmake >=3.20"]),
+            host: specs(&["zlib", "openssl 3.*", "bzip2"]),
+            run: specs(&["zlib", "libcurl"]),
+            ..Requirements::default()
+        };
+        sort_requirements(&mut requirements);
+        assert_eq!(spec_strings(&requirements.build), ["cmake >=3.20", "ninja"]);
+        assert_eq!(
+            spec_strings(&requirements.host),
+            ["bzip2", "openssl 3.*", "zlib"]
+        );
+        assert_eq!(spec_strings(&requirements.run), ["libcurl", "zlib"]);
+    }
+
+    #[test]
+    fn sorting_does_not_depend_on_the_collection_order() {
+        let mut a = Requirements {
+            host: specs(&["python 3.12.*", "numpy", "pip"]),
+            ..Requirements::default()
+        };
+        let mut b = Requirements {
+            host: specs(&["pip", "numpy", "python 3.12.*"]),
+            ..Requirements::default()
+        };
+        sort_requirements(&mut a);
+        sort_requirements(&mut b);
+        assert_eq!(spec_strings(&a.host), spec_strings(&b.host));
+    }
+
+    #[test]
+    fn channels_of_qualified_specs_are_appended() {
+        let requirements = Requirements {
+            build: specs(&["conda-forge::cmake"]),
+            host: specs(&["https://prefix.dev/pytorch::pytorch", "numpy"]),
+            run: specs(&[
+                "bioconda::samtools",
+                "https://prefix.dev/pytorch::torchvision",
+            ]),
+            ..Requirements::default()
+        };
+        let mut channels = vec![Url::parse("https://conda.anaconda.org/conda-forge").unwrap()];
+
+        add_spec_channels(&mut channels, &requirements);
+
+        assert_eq!(
+            channels
+                .iter()
+                .map(|url| url.as_str().trim_end_matches('/'))
+                .collect::<Vec<_>>(),
+            [
+                "https://conda.anaconda.org/conda-forge",
+                "https://prefix.dev/pytorch",
+                "https://conda.anaconda.org/bioconda",
+            ]
+        );
+    }
+
+    /// Returns a spec that requires the given version.
+    fn version_spec(version: &str) -> PixiSpec {
+        PixiSpec::Version(VersionSpec::from_str(version, ParseStrictness::Strict).unwrap())
+    }
+
+    /// Returns conda dependencies with the given names and specs.
+    fn conda_dependencies(dependencies: &[(&str, PixiSpec)]) -> CondaDependencies {
+        let mut result = CondaDependencies::default();
+        for (name, spec) in dependencies {
+            result.insert(PackageName::new_unchecked(*name), spec.clone());
+        }
+        result
+    }
+
+    /// Returns the specs of `name` in `dependencies`.
+    fn specs_of(dependencies: &CondaDependencies, name: &str) -> Vec<PixiSpec> {
+        dependencies
+            .get(name)
+            .map(|specs| specs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn host_tools_keep_the_spec_of_the_run_dependency() {
+        let mut host = conda_dependencies(&[("ninja", version_spec(">=1.11"))]);
+        let run = conda_dependencies(&[
+            ("cmake", version_spec(">=3.20")),
+            ("libcurl", PixiSpec::default()),
+        ]);
+
+        add_host_tools(&mut host, &run, &["cmake", "ninja", "make"]);
+
+        assert_eq!(specs_of(&host, "cmake"), [version_spec(">=3.20")]);
+        // A tool that is declared as a host dependency is not changed.
+        assert_eq!(specs_of(&host, "ninja"), [version_spec(">=1.11")]);
+        // Tools that are not declared are added without constraints.
+        assert_eq!(specs_of(&host, "make"), [PixiSpec::default()]);
+        // Other run dependencies never end up in the host environment.
+        assert!(!host.contains_key("libcurl"));
+    }
+
+    /// Returns a source dependency on the directory at `path`.
+    fn path_spec(path: &str) -> PixiSpec {
+        PixiSpec::Path(PathSpec { path: path.into() })
+    }
+
+    #[test]
+    fn self_references_are_skipped() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("sub")).unwrap();
+        let extractor = MatchspecExtractor::new(ChannelConfig::default_with_root_dir(
+            root.path().to_path_buf(),
+        ))
+        .with_ignore_self(true);
+
+        for path in [".", "sub/..", "./sub/../"] {
+            let dependencies = conda_dependencies(&[
+                ("package", path_spec(path)),
+                ("zlib", version_spec(">=1.3")),
+            ]);
+            let specs = extractor.extract(dependencies).unwrap();
+            assert_eq!(
+                specs.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                ["zlib >=1.3"],
+                "{path}"
+            );
+        }
+
+        // A source dependency on another directory is not a self reference.
+        let dependencies = conda_dependencies(&[("sub", path_spec("sub"))]);
+        assert_not_built(&extractor, dependencies, "sub");
+    }
+
+    /// Asserts that extracting `dependencies` fails because the source
+    /// dependency `name` has not been built, i.e. that it was not dropped as
+    /// a self reference.
+    fn assert_not_built(
+        extractor: &MatchspecExtractor,
+        dependencies: CondaDependencies,
+        name: &str,
+    ) {
+        let err = extractor.extract(dependencies).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("the source dependency `{name}` has not been built, recursive source dependencies are not yet supported")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn self_references_through_a_symlinked_root_are_skipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let real_root = directory.path().join("package");
+        std::fs::create_dir(&real_root).unwrap();
+        std::fs::create_dir(directory.path().join("other")).unwrap();
+        let root = directory.path().join("link");
+        std::os::unix::fs::symlink(&real_root, &root).unwrap();
+
+        let extractor = MatchspecExtractor::new(ChannelConfig::default_with_root_dir(root))
+            .with_ignore_self(true);
+        let real_root = real_root.to_str().unwrap();
+        for path in [".", real_root, "../package"] {
+            let dependencies = conda_dependencies(&[
+                ("package", path_spec(path)),
+                ("zlib", version_spec(">=1.3")),
+            ]);
+            let specs = extractor.extract(dependencies).unwrap();
+            assert_eq!(
+                specs.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                ["zlib >=1.3"],
+                "{path}"
+            );
+        }
+
+        // A sibling of the symlinked root is a genuine source dependency.
+        let dependencies = conda_dependencies(&[("other", path_spec("../other"))]);
+        assert_not_built(&extractor, dependencies, "other");
+    }
+
+    #[test]
+    fn built_source_dependencies_are_pinned() {
+        let root = tempfile::tempdir().unwrap();
+        let extractor = MatchspecExtractor::new(ChannelConfig::default_with_root_dir(
+            root.path().to_path_buf(),
+        ))
+        .with_built_source(BuiltSourcePackage {
+            name: PackageName::new_unchecked("child"),
+            version: Version::from_str("1.2.3").unwrap(),
+            build: "h123_0".to_string(),
+        });
+
+        let dependencies = conda_dependencies(&[
+            ("child", path_spec("../child")),
+            ("zlib", version_spec(">=1.3")),
+        ]);
+        let specs = extractor.extract(dependencies).unwrap();
+        assert_eq!(
+            specs.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["child ==1.2.3 h123_0", "zlib >=1.3"]
+        );
+
+        // Source dependencies that have not been built are still rejected.
+        let dependencies = conda_dependencies(&[("other", path_spec("../other"))]);
+        assert_not_built(&extractor, dependencies, "other");
+    }
+
+    #[test]
+    fn self_references_are_rejected_unless_ignored() {
+        let root = tempfile::tempdir().unwrap();
+        let extractor = MatchspecExtractor::new(ChannelConfig::default_with_root_dir(
+            root.path().to_path_buf(),
+        ));
+        let dependencies = conda_dependencies(&[("package", path_spec("."))]);
+        assert!(extractor.extract(dependencies).is_err());
+    }
+
+    #[test]
+    fn run_tools_keep_the_spec_of_the_host_dependency() {
+        let host = conda_dependencies(&[("python", version_spec(">=3.8"))]);
+
+        let mut run = CondaDependencies::default();
+        add_run_tool(&mut run, &host, "python");
+        assert_eq!(specs_of(&run, "python"), [version_spec(">=3.8")]);
+
+        // A tool that is declared as a run dependency is not changed.
+        let mut run = conda_dependencies(&[("python", version_spec(">=3.10"))]);
+        add_run_tool(&mut run, &host, "python");
+        assert_eq!(specs_of(&run, "python"), [version_spec(">=3.10")]);
+
+        // A tool that is not a host dependency is added without constraints.
+        let mut run = CondaDependencies::default();
+        add_run_tool(&mut run, &CondaDependencies::default(), "python");
+        assert_eq!(specs_of(&run, "python"), [PixiSpec::default()]);
+    }
+
+    /// Returns a `pin_compatible` run constraint on `name`.
+    fn pin_compatible(name: &str) -> Dependency {
+        Dependency::PinCompatible(PinCompatible {
+            pin_compatible: Pin {
+                name: PackageName::new_unchecked(name),
+                args: PinArgs::default(),
+            },
+        })
+    }
+
+    #[test]
+    fn pin_compatible_requires_a_host_dependency() {
+        let requirements = Requirements {
+            host: specs(&["numpy", "python"]),
+            run_constraints: vec![pin_compatible("numpy")],
+            ..Requirements::default()
+        };
+        check_pin_compatible(&requirements).unwrap();
+
+        let requirements = Requirements {
+            build: specs(&["numpy"]),
+            host: specs(&["python"]),
+            run: specs(&["numpy"]),
+            run_constraints: vec![pin_compatible("numpy")],
+            ..Requirements::default()
+        };
+        let err = check_pin_compatible(&requirements).unwrap_err();
+        assert!(err.to_string().contains("'numpy'"), "{err}");
+    }
+}
@@ -0,0 +1,32 @@
+//! A procedure that returns the recipe that a backend synthesizes from the
+//! manifest, without building anything. This allows tools to inspect e.g. the
+//! requirements of a package.
+
+use pixi_build_types::ChannelConfiguration;
+use rattler_build::recipe::Recipe;
+use rattler_conda_types::Platform;
+use serde::{Deserialize, Serialize};
+
+/// The name of the method.
+pub const METHOD_NAME: &str = "describe";
+
+/// Parameters for the `describe` method.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DescribeParams {
+    /// The platform for which the recipe is synthesized. Defaults to the
+    /// current platform.
+    pub host_platform: Option<Platform>,
+
+    /// The channel configuration that is used to resolve the channels of the
+    /// dependencies.
+    pub channel_configuration: ChannelConfiguration,
+}
+
+/// The result of the `describe` method.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DescribeResult {
+    /// The recipe that is used to build the package.
+    pub recipe: Recipe,
+}
@@ -0,0 +1,77 @@
+//! The version of a package that is computed from the version control system
+//! instead of being specified in the manifest, like setuptools-scm and
+//! versioneer do.
+//!
+//! The version is derived from the output of `git describe`. If the current
+//! commit is tagged, the tag is the version, e.g. `v1.2.3` becomes `1.2.3`. A
+//! commit after the tag gets a post release version that includes the number
+//! of commits since the tag and the abbreviated commit hash, e.g.
+//! `1.2.3.post5+gabc1234`, which sorts after `1.2.3`.
+
+use std::{path::Path, process::Command, str::FromStr};
+
+use miette::{Context, IntoDiagnostic};
+use pixi_manifest::Manifest;
+use rattler_conda_types::Version;
+
+use crate::{config::VersionSource, manifest_ext::ManifestExt};
+
+/// Returns the version of the package in `manifest` according to `source`.
+///
+/// The version is computed once when the backend is created, so the metadata
+/// and the build of the package always report the same version.
+pub fn package_version(manifest: &Manifest, source: VersionSource) -> miette::Result<Version> {
+    match source {
+        VersionSource::Manifest => Ok(manifest.version_or_default().clone()),
+        VersionSource::GitTag => git_tag_version(manifest.manifest_root()),
+    }
+}
+
+/// Returns the version of the git repository that contains `directory`,
+/// derived from its most recent tag.
+pub fn git_tag_version(directory: &Path) -> miette::Result<Version> {
+    let output = Command::new("git")
+        .args(["describe", "--tags", "--long"])
+        .current_dir(directory)
+        .output()
+        .into_diagnostic()
+        .context("failed to run `git describe`, is git installed?")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("No names found") || stderr.contains("No tags can describe") {
+            return Err(miette::miette!(
+                help = "add a tag such as `v0.1.0`, or set `version` in the manifest and remove `version-source`",
+                "the version is read from git tags, but the repository of {} has no tags",
+                directory.display()
+            ));
+        }
+        miette::bail!(
+            "failed to determine the version of {} with `git describe`: {}",
+            directory.display(),
+            stderr.trim()
+        );
+    }
+    parse_git_describe(String::from_utf8_lossy(&output.stdout).trim())
+}
+
+/// Converts the output of `git describe --tags --long`, which has the form
+/// `<tag>-<commits since tag>-g<hash>`, into a version.
+fn parse_git_describe(describe: &str) -> miette::Result<Version> {
+    let mut parts = describe.rsplitn(3, '-');
+    let (Some(hash), Some(distance), Some(tag)) = (parts.next(), parts.next(), parts.next()) else {
+        miette::bail!("unexpected output of `git describe`: '{describe}'");
+    };
+    let distance = u64::from_str(distance)
+        .into_diagnostic()
+        .with_context(|| format!("unexpected output of `git describe`: '{describe}'"))?;
+
+    let tag_version = tag.strip_prefix('v').unwrap_or(tag);
+    let version = if distance == 0 {
+        tag_version.to_string()
+    } else {
+        format!("{tag_version}.post{distance}+{hash}")
+    };
+    Version::from_str(&version)
+        .into_diagnostic()
+        .with_context(|| format!("the git tag '{tag}' is not a valid version"))
+}
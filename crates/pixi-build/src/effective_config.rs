@@ -0,0 +1,101 @@
+//! The configuration that a backend uses to build a package after the global
+//! configuration file, the manifest and the command line have been applied.
+//! This is a debugging aid, computing it does not build anything.
+
+use std::path::PathBuf;
+
+use rattler_conda_types::{NoArchType, Platform};
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::config::BackendConfig;
+
+/// The value that replaces credentials in the effective configuration.
+const REDACTED: &str = "********";
+
+/// The effective configuration of a backend for a single host platform.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EffectiveConfig {
+    /// The manifest the configuration is read from.
+    pub manifest_path: PathBuf,
+
+    /// The channels that are used to resolve the dependencies, in order of
+    /// priority.
+    pub channels: Vec<Url>,
+
+    /// The platform the build runs on.
+    pub build_platform: Platform,
+
+    /// The platform of the host environment.
+    pub host_platform: Platform,
+
+    /// The platform the package is built for.
+    pub target_platform: Platform,
+
+    /// The noarch type of the package.
+    pub noarch: NoArchType,
+
+    /// The build number of the package.
+    pub build_number: u64,
+
+    /// The installer that installs the package, if the backend uses one.
+    pub installer: Option<String>,
+
+    /// The file extension of the package archive.
+    pub archive_type: String,
+
+    /// The configuration of the backend after all layers have been applied.
+    pub config: BackendConfig,
+}
+
+impl EffectiveConfig {
+    /// Replaces the credentials in the urls of the configuration, so that the
+    /// configuration can be printed safely. The values of secrets are never
+    /// part of the configuration, only their names.
+    pub fn redacted(mut self) -> Self {
+        for channel in &mut self.channels {
+            redact_url(channel);
+        }
+        for arg in &mut self.config.installer_args {
+            // Urls are passed either as separate argument or as `--option=url`.
+            let (option, value) = match arg.split_once('=') {
+                Some((option, value)) if option.starts_with('-') => {
+                    (Some(option.to_string()), value)
+                }
+                _ => (None, arg.as_str()),
+            };
+            let Ok(mut url) = Url::parse(value) else {
+                continue;
+            };
+            if redact_url(&mut url) {
+                *arg = match option {
+                    Some(option) => format!("{option}={url}"),
+                    None => url.to_string(),
+                };
+            }
+        }
+        self
+    }
+}
+
+/// Replaces the password of `url` and a conda token (`/t/<token>/`) in its
+/// path. Returns `true` if anything was replaced.
+fn redact_url(url: &mut Url) -> bool {
+    let mut redacted = false;
+    if url.password().is_some() {
+        let _ = url.set_password(Some(REDACTED));
+        redacted = true;
+    }
+    let segments = url
+        .path_segments()
+        .map(|segments| segments.map(str::to_string).collect::<Vec<_>>());
+    if let Some(mut segments) = segments {
+        if segments.len() > 1 && segments[0] == "t" {
+            segments[1] = REDACTED.to_string();
+            url.set_path(&segments.join("/"));
+            redacted = true;
+        }
+    }
+    redacted
+}
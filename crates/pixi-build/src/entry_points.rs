@@ -0,0 +1,112 @@
+//! Entry points that are declared in the `entry-points` table of the backend
+//! configuration.
+//!
+//! Each entry maps the name of an executable to its target. For the python
+//! backend the target is a `module:function` object reference, from which
+//! rattler-build generates the entry point scripts. For compiled backends the
+//! target is the path of an installed executable, relative to the install
+//! prefix, for which the build script writes a wrapper to `bin`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+use rattler_conda_types::package::EntryPoint;
+use serde::Serialize;
+
+/// An entry point of a compiled package that runs an installed executable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandEntryPoint {
+    /// The name of the wrapper in `bin`.
+    pub name: String,
+
+    /// The path of the executable relative to the install prefix, with `/` as
+    /// separator.
+    pub command: String,
+}
+
+/// Converts the entry points of the configuration to python entry points.
+/// Returns an error if a name is invalid or a target is not a
+/// `module:function` object reference.
+pub fn python_entry_points(
+    entry_points: &BTreeMap<String, String>,
+) -> miette::Result<Vec<EntryPoint>> {
+    entry_points
+        .iter()
+        .map(|(name, target)| {
+            validate_name(name)?;
+            let Some((module, function)) = target.split_once(':') else {
+                miette::bail!(
+                    "the entry point `{name}` must be of the form `module:function`, found '{target}'"
+                );
+            };
+            let (module, function) = (module.trim(), function.trim());
+            if !is_dotted_identifier(module) || !is_dotted_identifier(function) {
+                miette::bail!(
+                    "the entry point `{name}` must be of the form `module:function`, found '{target}'"
+                );
+            }
+            Ok(EntryPoint {
+                command: name.clone(),
+                module: module.to_string(),
+                function: function.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Converts the entry points of the configuration to entry points of a
+/// compiled package. Returns an error if a name is invalid or a target is not
+/// a relative path inside the install prefix.
+pub fn command_entry_points(
+    entry_points: &BTreeMap<String, String>,
+) -> miette::Result<Vec<CommandEntryPoint>> {
+    entry_points
+        .iter()
+        .map(|(name, command)| {
+            validate_name(name)?;
+            let is_valid_path = !command.is_empty()
+                && command.chars().all(|c| {
+                    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '+' | '/')
+                })
+                && Path::new(command)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+            if !is_valid_path {
+                miette::bail!(
+                    "the entry point `{name}` must be the path of an executable relative to the install prefix (e.g. `libexec/{name}`), found '{command}'"
+                );
+            }
+            Ok(CommandEntryPoint {
+                name: name.clone(),
+                command: command.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Checks that `name` can be used as the file name of an executable.
+fn validate_name(name: &str) -> miette::Result<()> {
+    let is_valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !is_valid {
+        miette::bail!(
+            "'{name}' is not a valid entry point name, only letters, digits, `.`, `_` and `-` are allowed"
+        );
+    }
+    Ok(())
+}
+
+/// Returns `true` if `value` is a sequence of python identifiers separated by
+/// dots, e.g. `package.module`.
+fn is_dotted_identifier(value: &str) -> bool {
+    value.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
@@ -0,0 +1,187 @@
+//! Dependencies and channels that are read from a conda `environment.yml`,
+//! which makes it easier to migrate projects that already describe their
+//! environment in one.
+//!
+//! The dependencies of the file become run dependencies of the package. The
+//! manifest (`pixi.toml` or `pyproject.toml`) takes precedence: a package
+//! that the manifest declares as a run dependency keeps the spec of the
+//! manifest. Likewise, the channels of the file are appended after the
+//! channels of the manifest, so they have a lower priority.
+
+use std::path::Path;
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::recipe::parser::Dependency;
+use rattler_conda_types::{Channel, ChannelConfig, MatchSpec, ParseStrictness};
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::dependencies::append_channels;
+
+/// The contents of an `environment.yml`.
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentFile {
+    /// The channels, in order of priority.
+    pub channels: Vec<String>,
+
+    /// The dependencies.
+    pub dependencies: Vec<MatchSpec>,
+}
+
+#[derive(Deserialize)]
+struct RawEnvironmentFile {
+    #[serde(default)]
+    channels: Vec<String>,
+    #[serde(default)]
+    dependencies: Vec<RawDependency>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Spec(String),
+    Pip { pip: Vec<String> },
+}
+
+impl EnvironmentFile {
+    /// Reads the `environment.yml` at `path`. Returns an error if it contains
+    /// a `pip` section, because the backends cannot install pip dependencies
+    /// as part of a conda package.
+    pub fn from_path(path: &Path) -> miette::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .into_diagnostic()
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let raw: RawEnvironmentFile = serde_yaml::from_str(&contents)
+            .into_diagnostic()
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        let mut dependencies = Vec::new();
+        for dependency in raw.dependencies {
+            match dependency {
+                RawDependency::Spec(spec) => dependencies.push(
+                    MatchSpec::from_str(&spec, ParseStrictness::Lenient)
+                        .into_diagnostic()
+                        .with_context(|| {
+                            format!("invalid dependency '{spec}' in {}", path.display())
+                        })?,
+                ),
+                RawDependency::Pip { pip } if pip.is_empty() => {}
+                RawDependency::Pip { .. } => {
+                    return Err(miette::miette!(
+                        help = "declare the packages as conda dependencies, or as dependencies in the manifest",
+                        "the pip dependencies in {} are not supported",
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(Self {
+            channels: raw.channels,
+            dependencies,
+        })
+    }
+
+    /// Adds the dependencies of the file to the `run` dependencies of a
+    /// recipe, unless `run` already contains the package.
+    pub fn add_run_dependencies(&self, run: &mut Vec<Dependency>) {
+        for spec in &self.dependencies {
+            let is_declared = run.iter().any(|dependency| match dependency {
+                Dependency::Spec(declared) => declared.name.is_some() && declared.name == spec.name,
+                _ => false,
+            });
+            if !is_declared {
+                run.push(Dependency::Spec(spec.clone()));
+            }
+        }
+    }
+
+    /// Appends the channels of the file to `channels`, unless they are
+    /// already part of it.
+    pub fn add_channels(
+        &self,
+        channels: &mut Vec<Url>,
+        channel_config: &ChannelConfig,
+    ) -> miette::Result<()> {
+        let urls = self
+            .channels
+            .iter()
+            .map(|channel| {
+                Channel::from_str(channel, channel_config)
+                    .map(|channel| Url::from(channel.base_url))
+                    .into_diagnostic()
+                    .with_context(|| format!("invalid channel '{channel}' in the environment file"))
+            })
+            .collect::<miette::Result<Vec<_>>>()?;
+        append_channels(channels, urls);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `contents` to an `environment.yml` and reads it.
+    fn environment_file(contents: &str) -> miette::Result<EnvironmentFile> {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("environment.yml");
+        std::fs::write(&path, contents).unwrap();
+        EnvironmentFile::from_path(&path)
+    }
+
+    fn dependency(spec: &str) -> Dependency {
+        Dependency::Spec(MatchSpec::from_str(spec, ParseStrictness::Strict).unwrap())
+    }
+
+    #[test]
+    fn manifest_run_dependencies_take_precedence() {
+        let file = environment_file(
+            "channels:\n  - conda-forge\ndependencies:\n  - numpy >=1.26\n  - pandas\n  - pip: []\n",
+        )
+        .unwrap();
+
+        let mut run = vec![dependency("numpy >=2")];
+        file.add_run_dependencies(&mut run);
+
+        let run = run
+            .iter()
+            .map(|dependency| match dependency {
+                Dependency::Spec(spec) => spec.to_string(),
+                _ => panic!("expected a match spec"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(run, ["numpy >=2", "pandas"]);
+    }
+
+    #[test]
+    fn channels_are_appended_after_the_manifest_channels() {
+        let file = environment_file("channels:\n  - bioconda\n  - conda-forge\ndependencies: []\n")
+            .unwrap();
+        let channel_config = ChannelConfig::default_with_root_dir(std::env::temp_dir());
+        let mut channels = vec![Url::parse("https://conda.anaconda.org/conda-forge/").unwrap()];
+
+        file.add_channels(&mut channels, &channel_config).unwrap();
+
+        assert_eq!(
+            channels.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://conda.anaconda.org/conda-forge/",
+                "https://conda.anaconda.org/bioconda/",
+            ]
+        );
+    }
+
+    #[test]
+    fn pip_dependencies_are_rejected() {
+        let err = environment_file("dependencies:\n  - python\n  - pip:\n      - requests\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("pip dependencies"), "{err}");
+    }
+
+    #[test]
+    fn invalid_dependencies_are_rejected() {
+        let err = environment_file("dependencies:\n  - numpy >=>1\n").unwrap_err();
+        assert!(err.to_string().contains("numpy >=>1"), "{err}");
+    }
+}
@@ -0,0 +1,101 @@
+//! Snapshots of the build and host environments that a package was built in.
+//!
+//! A snapshot is written as a conda explicit spec file, the format that
+//! `conda list --explicit` and `conda-lock render --kind explicit` produce:
+//!
+//! ```text
+//! # platform: linux-64
+//! @EXPLICIT
+//! https://conda.anaconda.org/conda-forge/linux-64/zlib-1.3.1-hb9d3cd8_2.conda#c9f075ab2f33b3bbee9e62d4ad0a6cd8
+//! ```
+//!
+//! Every line after `@EXPLICIT` is the url of a package of the environment,
+//! followed by its md5 hash if it is known. An environment can be recreated
+//! from the file with `conda create --file <file>` or
+//! `micromamba create --file <file>`.
+//!
+//! A snapshot records the environment at the time of the build. It contains
+//! no match specs, so it cannot be solved again, and it can only be recreated
+//! as long as the channels still serve the exact same packages.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::{metadata::Output, render::resolved_dependencies::ResolvedDependencies};
+use rattler_conda_types::Platform;
+
+/// Writes a snapshot of the build and host environments of `output` next to
+/// the built `package`, named after the package with a `.build.txt` and
+/// `.host.txt` suffix. Environments that do not exist, or were not resolved,
+/// are skipped. Returns the paths of the written files.
+pub fn write_environment_snapshots(
+    output: &Output,
+    package: &Path,
+) -> miette::Result<Vec<PathBuf>> {
+    let Some(dependencies) = &output.finalized_dependencies else {
+        return Ok(Vec::new());
+    };
+    let build_configuration = &output.build_configuration;
+    let environments = [
+        (
+            "build",
+            build_configuration.build_platform.platform,
+            dependencies.build.as_ref(),
+        ),
+        (
+            "host",
+            build_configuration.host_platform.platform,
+            dependencies.host.as_ref(),
+        ),
+    ];
+
+    let file_stem = package_file_stem(package);
+    let directory = package.parent().unwrap_or(Path::new("."));
+    let mut paths = Vec::new();
+    for (environment, platform, resolved) in environments {
+        let Some(resolved) = resolved else {
+            continue;
+        };
+        let path = directory.join(format!("{file_stem}.{environment}.txt"));
+        std::fs::write(&path, explicit_spec(resolved, platform))
+            .into_diagnostic()
+            .with_context(|| {
+                format!(
+                    "failed to write the {environment} environment snapshot {}",
+                    path.display()
+                )
+            })?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Returns the file name of `package` without the archive extension.
+pub(crate) fn package_file_stem(package: &Path) -> String {
+    let file_name = package
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    file_name
+        .strip_suffix(".conda")
+        .or_else(|| file_name.strip_suffix(".tar.bz2"))
+        .unwrap_or(&file_name)
+        .to_string()
+}
+
+/// Renders the explicit spec file of an environment.
+fn explicit_spec(resolved: &ResolvedDependencies, platform: Platform) -> String {
+    let mut contents = String::new();
+    writeln!(contents, "# platform: {platform}").unwrap();
+    writeln!(contents, "@EXPLICIT").unwrap();
+    for record in &resolved.resolved {
+        match &record.package_record.md5 {
+            Some(md5) => writeln!(contents, "{}#{md5:x}", record.url).unwrap(),
+            None => writeln!(contents, "{}", record.url).unwrap(),
+        }
+    }
+    contents
+}
@@ -0,0 +1,170 @@
+//! The errors that backends return for common problems with a package. In
+//! contrast to free-form errors, callers can tell these apart, e.g. to decide
+//! whether retrying a request makes sense.
+
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use miette::Diagnostic;
+use rattler_conda_types::Platform;
+use thiserror::Error;
+
+use crate::build_tools::MissingBuildToolsError;
+
+/// The JSON-RPC error code of errors that are not a [`BuildBackendError`],
+/// and of [`BuildBackendError::MethodNotSupported`].
+pub const UNKNOWN_ERROR_CODE: i64 = -32000;
+
+/// The JSON-RPC error code of [`BuildBackendError::ManifestNotFound`] and
+/// [`BuildBackendError::NoManifestInDirectory`].
+pub const MANIFEST_NOT_FOUND_ERROR_CODE: i64 = -32001;
+
+/// The JSON-RPC error code of [`BuildBackendError::MissingName`] and
+/// [`BuildBackendError::InvalidName`].
+pub const INVALID_NAME_ERROR_CODE: i64 = -32002;
+
+/// The JSON-RPC error code of [`BuildBackendError::UnsupportedPlatform`].
+pub const UNSUPPORTED_PLATFORM_ERROR_CODE: i64 = -32003;
+
+/// The JSON-RPC error code of [`BuildBackendError::DependencyResolution`],
+/// which includes [`crate::build_tools::MissingBuildToolsError`].
+pub const DEPENDENCY_RESOLUTION_ERROR_CODE: i64 = -32004;
+
+/// The JSON-RPC error code of [`BuildBackendError::Cancelled`], as used by
+/// the language server protocol for cancelled requests.
+pub const REQUEST_CANCELLED_ERROR_CODE: i64 = -32800;
+
+/// An error of a backend that callers can handle programmatically.
+#[derive(Debug, Error, Diagnostic)]
+pub enum BuildBackendError {
+    /// No manifest exists at the given path.
+    #[error("manifest not found at {}", .0.display())]
+    ManifestNotFound(PathBuf),
+
+    /// The directory does not contain any of the supported manifests.
+    #[error("no manifest found in {}, expected one of {}", .directory.display(), .file_names.iter().join(", "))]
+    NoManifestInDirectory {
+        directory: PathBuf,
+        file_names: Vec<&'static str>,
+    },
+
+    /// The manifest does not specify the name of the project.
+    #[error("a 'name' field is required in the project manifest")]
+    MissingName,
+
+    /// The name of the project is not a valid package name.
+    #[error("'{name}' is not a valid package name")]
+    #[diagnostic(help("{reason}"))]
+    InvalidName { name: String, reason: String },
+
+    /// The project does not list the platform the package is built for.
+    #[error("the project does not support the target platform ({0})")]
+    #[diagnostic(help("add the platform to the `platforms` of the project"))]
+    UnsupportedPlatform(Platform),
+
+    /// The backend does not implement a method of the protocol, see
+    /// [`crate::protocol::Protocol`].
+    #[error("the backend does not support `{0}`")]
+    MethodNotSupported(&'static str),
+
+    /// The dependencies of the package could not be resolved, e.g. because
+    /// there is no solution or the repodata could not be fetched.
+    #[error("failed to resolve the dependencies of the package")]
+    DependencyResolution(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// The request was cancelled by the frontend before it completed.
+    #[error("the request was cancelled")]
+    Cancelled,
+}
+
+impl BuildBackendError {
+    /// Returns the JSON-RPC error code of the error. Errors with the same
+    /// cause share a code, so frontends can e.g. tell a mistake in the
+    /// manifest apart from a failure to resolve the dependencies, which may
+    /// be transient.
+    pub fn code(&self) -> i64 {
+        match self {
+            BuildBackendError::ManifestNotFound(_)
+            | BuildBackendError::NoManifestInDirectory { .. } => MANIFEST_NOT_FOUND_ERROR_CODE,
+            BuildBackendError::MissingName | BuildBackendError::InvalidName { .. } => {
+                INVALID_NAME_ERROR_CODE
+            }
+            BuildBackendError::UnsupportedPlatform(_) => UNSUPPORTED_PLATFORM_ERROR_CODE,
+            BuildBackendError::DependencyResolution(_) => DEPENDENCY_RESOLUTION_ERROR_CODE,
+            BuildBackendError::MethodNotSupported(_) => UNKNOWN_ERROR_CODE,
+            BuildBackendError::Cancelled => REQUEST_CANCELLED_ERROR_CODE,
+        }
+    }
+}
+
+/// Returns the JSON-RPC error code of `err`, which is the code of the first
+/// [`BuildBackendError`] in its chain of causes, or [`UNKNOWN_ERROR_CODE`].
+pub fn error_code(err: &miette::Report) -> i64 {
+    err.chain()
+        .find_map(|cause| {
+            if let Some(err) = cause.downcast_ref::<BuildBackendError>() {
+                Some(err.code())
+            } else if cause.is::<MissingBuildToolsError>() {
+                Some(DEPENDENCY_RESOLUTION_ERROR_CODE)
+            } else {
+                None
+            }
+        })
+        .unwrap_or(UNKNOWN_ERROR_CODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use miette::Context;
+
+    use super::*;
+
+    #[test]
+    fn errors_have_the_code_of_their_cause() {
+        let cases = [
+            (
+                BuildBackendError::ManifestNotFound(PathBuf::from("pixi.toml")),
+                MANIFEST_NOT_FOUND_ERROR_CODE,
+            ),
+            (BuildBackendError::MissingName, INVALID_NAME_ERROR_CODE),
+            (
+                BuildBackendError::UnsupportedPlatform(Platform::Linux64),
+                UNSUPPORTED_PLATFORM_ERROR_CODE,
+            ),
+            (
+                BuildBackendError::DependencyResolution("no solution".into()),
+                DEPENDENCY_RESOLUTION_ERROR_CODE,
+            ),
+            (BuildBackendError::Cancelled, REQUEST_CANCELLED_ERROR_CODE),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.code(), code, "{err}");
+            assert_eq!(error_code(&err.into()), code);
+        }
+    }
+
+    #[test]
+    fn context_keeps_the_code() {
+        let result: miette::Result<()> = Err(BuildBackendError::MissingName.into());
+        let err = result
+            .context("failed to read the metadata")
+            .context("failed to build the package")
+            .unwrap_err();
+        assert_eq!(error_code(&err), INVALID_NAME_ERROR_CODE);
+    }
+
+    #[test]
+    fn other_errors_have_the_unknown_code() {
+        assert_eq!(
+            error_code(&miette::miette!("something went wrong")),
+            UNKNOWN_ERROR_CODE
+        );
+    }
+
+    #[test]
+    fn unsupported_methods_have_the_unknown_code() {
+        let err = BuildBackendError::MethodNotSupported("describe");
+        assert_eq!(err.code(), UNKNOWN_ERROR_CODE);
+        assert_eq!(err.to_string(), "the backend does not support `describe`");
+    }
+}
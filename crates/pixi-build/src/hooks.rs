@@ -0,0 +1,95 @@
+//! Commands that are run at specific points of a build.
+
+use std::path::{Path, PathBuf};
+
+use miette::{Context, IntoDiagnostic};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::BuildBackendError;
+
+/// Runs the post-build hook `command` for the built `package`.
+///
+/// The first element of `command` is the program, the remaining elements are
+/// arguments to which the path of the package is appended. The hook runs in
+/// the manifest directory with the environment of the backend, and a relative
+/// program path (e.g. `./scripts/sign.sh`) is resolved against that directory
+/// as well. The build fails if the hook exits with a non-zero status.
+///
+/// The hook is killed if `cancellation` is cancelled before it exits.
+///
+/// Does nothing if `command` is empty.
+pub async fn run_post_build_hook(
+    command: &[String],
+    package: &Path,
+    manifest_root: &Path,
+    cancellation: &CancellationToken,
+) -> miette::Result<()> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+
+    let program_path = PathBuf::from(program);
+    let program_path = if program_path.components().count() > 1 {
+        manifest_root.join(program_path)
+    } else {
+        program_path
+    };
+
+    tracing::info!("running the post-build hook `{program}`");
+    let mut child = Command::new(&program_path)
+        .args(args)
+        .arg(package)
+        .current_dir(manifest_root)
+        .kill_on_drop(true)
+        .spawn()
+        .into_diagnostic()
+        .with_context(|| format!("failed to run the post-build hook `{program}`"))?;
+    let status = tokio::select! {
+        status = child.wait() => status
+            .into_diagnostic()
+            .with_context(|| format!("failed to run the post-build hook `{program}`"))?,
+        () = cancellation.cancelled() => return Err(BuildBackendError::Cancelled.into()),
+    };
+    if !status.success() {
+        miette::bail!(
+            "the post-build hook `{program}` failed for {} ({status})",
+            package.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn a_cancelled_hook_is_killed() {
+        let directory = tempfile::tempdir().unwrap();
+        let cancellation = CancellationToken::new();
+        let command = ["sleep".to_string(), "60".to_string()];
+
+        let start = Instant::now();
+        let hook = run_post_build_hook(
+            &command,
+            Path::new("package.conda"),
+            directory.path(),
+            &cancellation,
+        );
+        let cancel = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancellation.cancel();
+        };
+        let (result, ()) = tokio::join!(hook, cancel);
+
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BuildBackendError>(),
+            Some(BuildBackendError::Cancelled)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+}
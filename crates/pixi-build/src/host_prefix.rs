@@ -0,0 +1,69 @@
+//! Support for using the packages of an existing conda environment as the
+//! host environment of a build.
+
+use std::path::Path;
+
+use itertools::Itertools;
+use miette::{Context, IntoDiagnostic};
+use rattler_build::{metadata::Output, recipe::parser::Dependency};
+use rattler_conda_types::{Matches, PrefixRecord};
+
+/// Replaces the packages of the resolved host environment of `output` with
+/// the packages that are installed in the conda environment at `prefix`.
+///
+/// The host environment of the build is then created from exactly these
+/// packages. The environment itself is not modified. The run exports of the
+/// host dependencies are still taken from the resolved environment. Returns an error if the
+/// dependencies of `output` are not resolved yet, or if the environment does
+/// not contain a package for every host requirement of the recipe.
+pub fn use_host_prefix(prefix: &Path, mut output: Output) -> miette::Result<Output> {
+    if !prefix.join("conda-meta").is_dir() {
+        miette::bail!(
+            "the host prefix {} is not a conda environment",
+            prefix.display()
+        );
+    }
+    let records = PrefixRecord::collect_from_prefix(prefix)
+        .into_diagnostic()
+        .with_context(|| {
+            format!(
+                "failed to read the packages of the host prefix {}",
+                prefix.display()
+            )
+        })?
+        .into_iter()
+        .map(|record| record.repodata_record)
+        .collect::<Vec<_>>();
+
+    let missing = output
+        .recipe
+        .requirements
+        .host
+        .iter()
+        .filter_map(|dependency| match dependency {
+            Dependency::Spec(spec) => Some(spec),
+            _ => None,
+        })
+        .filter(|spec| {
+            !records
+                .iter()
+                .any(|record| spec.matches(&record.package_record))
+        })
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        miette::bail!(
+            "the host prefix {} does not contain a package that satisfies {}",
+            prefix.display(),
+            missing.iter().map(|spec| format!("'{spec}'")).join(", ")
+        );
+    }
+
+    let Some(dependencies) = output.finalized_dependencies.as_mut() else {
+        miette::bail!("the dependencies must be resolved before the host prefix is applied");
+    };
+    match dependencies.host.as_mut() {
+        Some(host) => host.resolved = records,
+        None => tracing::warn!("the package has no host environment, the host prefix is ignored"),
+    }
+    Ok(output)
+}
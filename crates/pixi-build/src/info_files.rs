@@ -0,0 +1,167 @@
+//! Additional files in the `info/` directory of a package, e.g. a software
+//! bill of materials or a build provenance statement.
+//!
+//! rattler-build only writes its own metadata to `info/`, so the files are
+//! added to the built package afterwards by repacking it.
+
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use miette::{Context, IntoDiagnostic};
+use rattler_package_streaming::write::{write_conda_package, CompressionLevel};
+
+use crate::reproducible::extract;
+
+/// Checks that every name of `info_files` is a relative path that stays
+/// inside `info/`.
+pub fn validate_info_file_names(info_files: &BTreeMap<String, PathBuf>) -> miette::Result<()> {
+    for name in info_files.keys() {
+        let is_valid = !name.is_empty()
+            && Path::new(name)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)));
+        if !is_valid {
+            miette::bail!(
+                "'{name}' in `info-files` must be a relative path inside the `info/` directory"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Adds `info_files` to the `info/` directory of the built `package`. The
+/// keys are the paths inside `info/`, the values the source files relative to
+/// `manifest_root`. The sources are read after the build, so they can also be
+/// generated by it. `timestamp` is used for the files in the repacked
+/// archive.
+///
+/// Returns an error if a source file does not exist, or if the package
+/// already contains a file with the same name, e.g. `info/index.json`.
+pub fn add_info_files(
+    package: &Path,
+    info_files: &BTreeMap<String, PathBuf>,
+    manifest_root: &Path,
+    timestamp: &DateTime<Utc>,
+) -> miette::Result<()> {
+    if info_files.is_empty() {
+        return Ok(());
+    }
+    let Some(out_name) = package
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_suffix(".conda"))
+    else {
+        miette::bail!(
+            "info files can only be added to `.conda` packages, not to {}",
+            package.display()
+        );
+    };
+
+    let extracted = extract(package)?;
+    for (name, source) in info_files {
+        let source = manifest_root.join(source);
+        if !source.is_file() {
+            miette::bail!(
+                "the source {} of the info file `info/{name}` does not exist",
+                source.display()
+            );
+        }
+        let destination = extracted.path().join("info").join(name);
+        if destination.exists() {
+            miette::bail!("the package already contains `info/{name}`");
+        }
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent).into_diagnostic()?;
+        }
+        std::fs::copy(&source, &destination)
+            .into_diagnostic()
+            .with_context(|| format!("failed to copy {}", source.display()))?;
+    }
+
+    let mut paths = Vec::new();
+    for entry in walkdir::WalkDir::new(extracted.path()) {
+        let entry = entry.into_diagnostic()?;
+        if !entry.file_type().is_dir() {
+            paths.push(entry.into_path());
+        }
+    }
+
+    let directory = package.parent().unwrap_or(Path::new("."));
+    let mut repacked = tempfile::NamedTempFile::new_in(directory)
+        .into_diagnostic()
+        .context("failed to create a temporary file for the package")?;
+    write_conda_package(
+        repacked.as_file_mut(),
+        extracted.path(),
+        &paths,
+        CompressionLevel::default(),
+        None,
+        out_name,
+        Some(timestamp),
+        None,
+    )
+    .into_diagnostic()
+    .with_context(|| format!("failed to repack {}", package.display()))?;
+    repacked
+        .persist(package)
+        .into_diagnostic()
+        .with_context(|| format!("failed to replace {}", package.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns info files with the given names, all read from `sbom.json`.
+    fn info_files(names: &[&str]) -> BTreeMap<String, PathBuf> {
+        names
+            .iter()
+            .map(|name| (name.to_string(), PathBuf::from("sbom.json")))
+            .collect()
+    }
+
+    #[test]
+    fn names_inside_the_info_directory_are_accepted() {
+        validate_info_file_names(&info_files(&["sbom.json", "licenses/sbom.cdx.json"])).unwrap();
+    }
+
+    #[test]
+    fn names_outside_the_info_directory_are_rejected() {
+        for name in [
+            "",
+            "../sbom.json",
+            "/sbom.json",
+            "./sbom.json",
+            "a/../../sbom.json",
+        ] {
+            let err = validate_info_file_names(&info_files(&[name])).unwrap_err();
+            assert!(err.to_string().contains("`info-files`"), "{name:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn info_files_are_only_added_to_conda_packages() {
+        let directory = tempfile::tempdir().unwrap();
+        let package = directory.path().join("package-1.0-h0_0.tar.bz2");
+        let err = add_info_files(
+            &package,
+            &info_files(&["sbom.json"]),
+            directory.path(),
+            &Utc::now(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`.conda` packages"), "{err}");
+    }
+
+    #[test]
+    fn no_info_files_leave_the_package_untouched() {
+        let directory = tempfile::tempdir().unwrap();
+        let package = directory.path().join("package-1.0-h0_0.conda");
+        add_info_files(&package, &BTreeMap::new(), directory.path(), &Utc::now()).unwrap();
+        assert!(!package.exists());
+    }
+}
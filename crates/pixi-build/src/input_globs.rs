@@ -0,0 +1,29 @@
+//! A procedure that returns the globs of the files that a build of the
+//! package depends on, without building anything. Frontends use these to
+//! watch the sources for changes right after initialization, instead of
+//! waiting for the first build to report them.
+
+use rattler_conda_types::Platform;
+use serde::{Deserialize, Serialize};
+
+/// The name of the method.
+pub const METHOD_NAME: &str = "inputGlobs";
+
+/// Parameters for the `inputGlobs` method.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputGlobsParams {
+    /// The platform for which the package would be built. Defaults to the
+    /// current platform.
+    pub host_platform: Option<Platform>,
+}
+
+/// The result of the `inputGlobs` method.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputGlobsResult {
+    /// The globs, relative to the source directory, that match the files the
+    /// build depends on. These are the same globs that a build of the package
+    /// reports.
+    pub input_globs: Vec<String>,
+}
@@ -1,8 +1,43 @@
+pub mod backend;
+pub mod build_log;
+pub mod build_state;
+pub mod build_tools;
+pub mod channel_diagnostics;
 pub mod cli;
+pub mod conda_build;
+pub mod conda_build_config;
+pub mod conda_metadata;
+pub mod config;
 pub mod protocol;
 pub mod server;
 
 mod consts;
 pub mod dependencies;
+pub mod describe;
+pub mod dynamic_version;
+pub mod effective_config;
+pub mod entry_points;
+pub mod environment_file;
+pub mod environment_snapshot;
+pub mod error;
+pub mod hooks;
+pub mod host_prefix;
+pub mod info_files;
+pub mod input_globs;
+pub mod local_channel;
+pub mod lock;
 pub mod manifest_ext;
+pub mod metadata_cache;
+pub mod metapackage;
+pub mod package_contents;
+pub mod platform;
+pub mod platform_output;
+pub mod prefix_padding;
+pub mod recipe_hash;
+pub mod relocatability;
+pub mod repodata_retry;
+pub mod reproducible;
+pub mod sbom;
+pub mod submodules;
 pub mod utils;
+pub mod variants;
@@ -0,0 +1,116 @@
+//! Support for a local output channel. Packages that are built are added to
+//! this channel, and the channel is used to resolve the dependencies of
+//! subsequent builds. This makes it possible to iterate on packages that
+//! depend on each other without managing a channel manually.
+
+use std::path::{Path, PathBuf};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_conda_types::Platform;
+use reqwest::Url;
+
+/// Returns the URL of the local channel at the absolute path `channel_dir`,
+/// without checking that the channel exists.
+pub fn local_channel_url(channel_dir: &Path) -> miette::Result<Url> {
+    Url::from_directory_path(channel_dir).map_err(|_| {
+        miette::miette!(
+            "failed to convert {} to a channel url",
+            channel_dir.display()
+        )
+    })
+}
+
+/// Replaces `channels` with the channel in the `repodata_source` directory, if
+/// one is given. This is a testing feature to resolve the dependencies against
+/// hypothetical repodata, e.g. to reproduce a bug in the solver. All other
+/// channels, including the output channel, are ignored.
+pub fn apply_repodata_source(
+    channels: &mut Vec<Url>,
+    repodata_source: Option<&Path>,
+) -> miette::Result<()> {
+    let Some(repodata_source) = repodata_source else {
+        return Ok(());
+    };
+    if !repodata_source
+        .join(Platform::NoArch.as_str())
+        .join("repodata.json")
+        .is_file()
+    {
+        miette::bail!(
+            "the repodata source {} must contain `noarch/repodata.json`",
+            repodata_source.display()
+        );
+    }
+    let repodata_source = repodata_source
+        .canonicalize()
+        .into_diagnostic()
+        .with_context(|| format!("failed to resolve {}", repodata_source.display()))?;
+    tracing::warn!(
+        "resolving the dependencies against the repodata in {} instead of the channels",
+        repodata_source.display()
+    );
+    *channels = vec![local_channel_url(&repodata_source)?];
+    Ok(())
+}
+
+/// Ensures that `channel_dir` is a valid channel, creating an empty one if
+/// needed, and returns its URL.
+pub fn prepare_local_channel(channel_dir: &Path) -> miette::Result<Url> {
+    let noarch_dir = channel_dir.join(Platform::NoArch.as_str());
+    if !noarch_dir.join("repodata.json").is_file() {
+        std::fs::create_dir_all(&noarch_dir)
+            .into_diagnostic()
+            .with_context(|| {
+                format!(
+                    "failed to create the local channel {}",
+                    channel_dir.display()
+                )
+            })?;
+        index_local_channel(channel_dir)?;
+    }
+
+    let channel_dir = channel_dir.canonicalize().into_diagnostic()?;
+    local_channel_url(&channel_dir)
+}
+
+/// Adds the package to the `subdir` of the channel in `channel_dir` and
+/// updates the repodata of the channel. Returns the path of the package in the
+/// channel.
+pub fn add_to_local_channel(
+    channel_dir: &Path,
+    package: &Path,
+    subdir: Platform,
+) -> miette::Result<PathBuf> {
+    prepare_local_channel(channel_dir)?;
+
+    let subdir_path = channel_dir.join(subdir.as_str());
+    std::fs::create_dir_all(&subdir_path).into_diagnostic()?;
+    let file_name = package
+        .file_name()
+        .ok_or_else(|| miette::miette!("invalid package path {}", package.display()))?;
+    let destination = subdir_path.join(file_name);
+    std::fs::copy(package, &destination)
+        .into_diagnostic()
+        .with_context(|| {
+            format!(
+                "failed to copy {} to the local channel {}",
+                package.display(),
+                channel_dir.display()
+            )
+        })?;
+
+    index_local_channel(channel_dir)?;
+    Ok(destination)
+}
+
+/// Regenerates the repodata of all subdirectories of the channel.
+fn index_local_channel(channel_dir: &Path) -> miette::Result<()> {
+    rattler_index::index(channel_dir, None)
+        .into_diagnostic()
+        .with_context(|| {
+            format!(
+                "failed to index the local channel {}",
+                channel_dir.display()
+            )
+        })
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::{
+    metadata::Output,
+    recipe::parser::Dependency,
+    render::resolved_dependencies::{FinalizedDependencies, ResolvedDependencies},
+};
+use rattler_conda_types::Matches;
+use serde::{Deserialize, Serialize};
+
+/// The name of the lock file that is stored next to the manifest.
+pub const LOCK_FILE_NAME: &str = "pixi-build.lock";
+
+/// The version of the lock file format.
+const LOCK_FILE_VERSION: u32 = 1;
+
+/// Determines how the lock file is used when the dependencies of a package
+/// are resolved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockMode {
+    /// Use the lock file if it is up to date with the manifest, otherwise
+    /// solve the environments and update the lock file.
+    #[default]
+    Update,
+
+    /// The lock file must exist and be up to date with the manifest. No
+    /// environments are solved and the lock file is never updated.
+    Locked,
+
+    /// The lock file must exist and is used as is, without checking it against
+    /// the manifest. No environments are solved and the lock file is never
+    /// updated.
+    Frozen,
+}
+
+/// The resolved environments of a package, stored per target platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockFile {
+    version: u32,
+    platforms: BTreeMap<String, FinalizedDependencies>,
+}
+
+impl Default for LockFile {
+    fn default() -> Self {
+        Self {
+            version: LOCK_FILE_VERSION,
+            platforms: BTreeMap::new(),
+        }
+    }
+}
+
+impl LockFile {
+    /// Returns the path of the lock file for the manifest in `manifest_root`.
+    pub fn path(manifest_root: &Path) -> PathBuf {
+        manifest_root.join(LOCK_FILE_NAME)
+    }
+
+    /// Reads the lock file from disk, returns `None` if it does not exist.
+    pub fn from_path(path: &Path) -> miette::Result<Option<Self>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).into_diagnostic(),
+        };
+        let lock_file: Self = serde_yaml::from_str(&contents)
+            .into_diagnostic()
+            .with_context(|| format!("failed to parse the lock file {}", path.display()))?;
+        if lock_file.version != LOCK_FILE_VERSION {
+            miette::bail!(
+                "the lock file {} has version {}, but only version {LOCK_FILE_VERSION} is supported",
+                path.display(),
+                lock_file.version
+            );
+        }
+        Ok(Some(lock_file))
+    }
+
+    /// Writes the lock file to disk.
+    pub fn write(&self, path: &Path) -> miette::Result<()> {
+        let contents = serde_yaml::to_string(self).into_diagnostic()?;
+        std::fs::write(path, contents).into_diagnostic()
+    }
+}
+
+/// Sets the finalized dependencies of `output` from the lock file in
+/// `manifest_root` if the lock file can be used. With [`LockMode::Locked`]
+/// and [`LockMode::Frozen`] an error is returned if that is not the case,
+/// which guarantees that no environments are solved.
+pub fn apply_lock_file(
+    mode: LockMode,
+    manifest_root: &Path,
+    mut output: Output,
+) -> miette::Result<Output> {
+    let path = LockFile::path(manifest_root);
+    let target_platform = output.build_configuration.target_platform;
+    let locked = LockFile::from_path(&path)?
+        .and_then(|mut lock_file| lock_file.platforms.remove(target_platform.as_str()));
+
+    let Some(locked) = locked else {
+        if mode == LockMode::Update {
+            return Ok(output);
+        }
+        miette::bail!(
+            "the lock file {} does not contain the environments for {target_platform}, but solving is not allowed because of `--locked` or `--frozen`",
+            path.display()
+        );
+    };
+
+    if mode != LockMode::Frozen {
+        if let Err(reason) = satisfies(&output, &locked) {
+            if mode == LockMode::Update {
+                tracing::info!("the lock file is out of date: {reason}");
+                return Ok(output);
+            }
+            return Err(reason).with_context(|| {
+                format!(
+                    "the lock file {} is not up to date with the manifest, but solving is not allowed because of `--locked`",
+                    path.display()
+                )
+            });
+        }
+    }
+
+    output.finalized_dependencies = Some(locked);
+    Ok(output)
+}
+
+/// Stores the finalized dependencies of `output` in the lock file in
+/// `manifest_root`. This only modifies the lock file with
+/// [`LockMode::Update`].
+pub fn update_lock_file(
+    mode: LockMode,
+    manifest_root: &Path,
+    output: &Output,
+) -> miette::Result<()> {
+    if mode != LockMode::Update {
+        return Ok(());
+    }
+    let Some(finalized_dependencies) = &output.finalized_dependencies else {
+        return Ok(());
+    };
+
+    let path = LockFile::path(manifest_root);
+    let mut lock_file = LockFile::from_path(&path)?.unwrap_or_default();
+    lock_file.platforms.insert(
+        output.build_configuration.target_platform.to_string(),
+        finalized_dependencies.clone(),
+    );
+    lock_file
+        .write(&path)
+        .with_context(|| format!("failed to write the lock file {}", path.display()))
+}
+
+/// Checks that the locked environments contain a package for every
+/// requirement of the recipe.
+fn satisfies(output: &Output, locked: &FinalizedDependencies) -> miette::Result<()> {
+    let requirements = &output.recipe.requirements;
+    environment_satisfies("build", &requirements.build, locked.build.as_ref())?;
+    environment_satisfies("host", &requirements.host, locked.host.as_ref())
+}
+
+fn environment_satisfies(
+    environment: &str,
+    requirements: &[Dependency],
+    locked: Option<&ResolvedDependencies>,
+) -> miette::Result<()> {
+    let records = locked
+        .map(|locked| locked.resolved.as_slice())
+        .unwrap_or_default();
+    for requirement in requirements {
+        let Dependency::Spec(spec) = requirement else {
+            continue;
+        };
+        if !records
+            .iter()
+            .any(|record| spec.matches(&record.package_record))
+        {
+            miette::bail!("no locked package in the {environment} environment satisfies '{spec}'");
+        }
+    }
+    Ok(())
+}
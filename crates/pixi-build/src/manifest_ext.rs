@@ -1,9 +1,17 @@
-use std::{path::Path, str::FromStr, sync::OnceLock};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
 
-use pixi_manifest::Manifest;
-use rattler_conda_types::{ChannelConfig, ParseChannelError, Platform, Version};
+use miette::Context;
+use pixi_manifest::{EnvironmentName, Feature, FeatureName, Manifest};
+use rattler_build::recipe::parser::{About, License};
+use rattler_conda_types::{ChannelConfig, PackageName, ParseChannelError, Platform, Version};
 use reqwest::Url;
 
+use crate::{consts, error::BuildBackendError};
+
 pub trait ManifestExt {
     fn manifest(&self) -> &Manifest;
 
@@ -33,14 +41,77 @@ pub trait ManifestExt {
     }
 
     /// Returns `true` if the manifest is configured to use the specified
-    /// platform.
+    /// platform. Packages can always be built for `noarch`, because they do
+    /// not depend on a specific platform.
     fn supports_target_platform(&self, platform: Platform) -> bool {
-        self.manifest()
-            .parsed
-            .project
-            .platforms
-            .value
-            .contains(&platform)
+        platform == Platform::NoArch
+            || self
+                .manifest()
+                .parsed
+                .project
+                .platforms
+                .value
+                .contains(&platform)
+    }
+
+    /// Returns the name of the package, which is the name of the project.
+    /// Returns an error if the manifest does not specify a name, or if the
+    /// name is empty or not a valid package name.
+    fn package_name(&self) -> Result<PackageName, BuildBackendError> {
+        let Some(name) = self.manifest().parsed.project.name.as_deref() else {
+            return Err(BuildBackendError::MissingName);
+        };
+        let invalid = |reason: &str| BuildBackendError::InvalidName {
+            name: name.to_string(),
+            reason: reason.to_string(),
+        };
+        if name.trim().is_empty() {
+            return Err(invalid("the name must not be empty"));
+        }
+        if name.trim() != name {
+            return Err(invalid("the name must not start or end with whitespace"));
+        }
+        PackageName::from_str(name).map_err(|err| invalid(&err.to_string()))
+    }
+
+    /// Returns the features whose dependencies make up the requirements of the
+    /// package. These are the features of `environment`, or of the default
+    /// environment of the project if `None`, which a project can redefine to
+    /// include other features. As in pixi, the default feature is included
+    /// unless the environment sets `no-default-feature`.
+    fn environment_features(&self, environment: Option<&str>) -> miette::Result<Vec<&Feature>> {
+        let manifest = self.manifest();
+        let name = match environment {
+            Some(environment) => EnvironmentName::from_str(environment).map_err(|err| {
+                miette::miette!("invalid environment name '{environment}': {err}")
+            })?,
+            None => EnvironmentName::Default,
+        };
+        let Some(environment) = manifest.environment(&name) else {
+            if name == EnvironmentName::Default {
+                return Ok(vec![manifest.default_feature()]);
+            }
+            miette::bail!(
+                "the environment '{}' does not exist in {}",
+                name.as_str(),
+                manifest.path.display()
+            );
+        };
+
+        let mut features = Vec::new();
+        if !environment.no_default_feature {
+            features.push(manifest.default_feature());
+        }
+        for feature in &environment.features {
+            let Some(feature) = manifest.feature(&FeatureName::Named(feature.clone())) else {
+                miette::bail!(
+                    "the feature '{feature}' of the environment '{}' does not exist",
+                    name.as_str()
+                );
+            };
+            features.push(feature);
+        }
+        Ok(features)
     }
 
     /// Returns the version as specified in the manifest.
@@ -58,6 +129,36 @@ pub trait ManifestExt {
         self.version()
             .unwrap_or_else(|| DEFAULT_VERSION.get_or_init(|| Version::from_str("0.1.0").unwrap()))
     }
+
+    /// Returns the about section of the recipe, derived from the project
+    /// metadata in the manifest. The `repository` and `documentation` URLs end
+    /// up as `dev_url` and `doc_url` in the package metadata. The URLs are
+    /// validated when the manifest is parsed.
+    ///
+    /// Returns an error if the license is not a valid SPDX expression, e.g.
+    /// `MIT License` instead of `MIT`.
+    fn about(&self) -> miette::Result<About> {
+        let project = &self.manifest().parsed.project;
+        let license = project
+            .license
+            .as_deref()
+            .map(|license| {
+                License::from_str(license).map_err(|err| {
+                    miette::miette!(
+                        help = "use an SPDX identifier such as `MIT`, an expression such as `MIT OR Apache-2.0`, or `LicenseRef-<name>` for a custom license",
+                        "the license '{license}' is not a valid SPDX expression: {err}"
+                    )
+                })
+            })
+            .transpose()?;
+        Ok(About {
+            homepage: project.homepage.clone(),
+            repository: project.repository.clone(),
+            documentation: project.documentation.clone(),
+            license,
+            ..About::default()
+        })
+    }
 }
 
 impl ManifestExt for Manifest {
@@ -65,3 +166,184 @@ impl ManifestExt for Manifest {
         self
     }
 }
+
+/// The names of the manifest files that are searched for in a directory, in
+/// order of preference.
+const MANIFEST_FILE_NAMES: [&str; 2] = [consts::PROJECT_MANIFEST, consts::PYPROJECT_MANIFEST];
+
+/// Returns the path of the manifest that `path` refers to. If `path` is a
+/// directory, it is searched for a `pixi.toml` and then a `pyproject.toml`.
+/// Otherwise `path` itself is returned.
+pub fn find_manifest(path: &Path) -> miette::Result<PathBuf> {
+    if !path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+
+    MANIFEST_FILE_NAMES
+        .iter()
+        .map(|name| path.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            BuildBackendError::NoManifestInDirectory {
+                directory: path.to_path_buf(),
+                file_names: MANIFEST_FILE_NAMES.to_vec(),
+            }
+            .into()
+        })
+}
+
+/// Loads the manifest at the given path, which can also be a directory that
+/// contains the manifest (see [`find_manifest`]).
+///
+/// Returns a distinct error if the manifest does not exist, to differentiate
+/// it from a manifest that exists but cannot be parsed.
+pub fn load_manifest(manifest_path: &Path) -> miette::Result<Manifest> {
+    let manifest_path = find_manifest(manifest_path)?;
+    if !manifest_path.is_file() {
+        return Err(BuildBackendError::ManifestNotFound(manifest_path).into());
+    }
+
+    Manifest::from_path(&manifest_path)
+        .with_context(|| format!("failed to parse manifest from {}", manifest_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Writes `contents` to a `pixi.toml` in a temporary directory and loads
+    /// it. The directory must outlive the manifest.
+    fn manifest(contents: &str) -> (TempDir, Manifest) {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(consts::PROJECT_MANIFEST);
+        std::fs::write(&path, contents).unwrap();
+        let manifest = load_manifest(&path).unwrap();
+        (directory, manifest)
+    }
+
+    /// Returns the manifest of a project with the given name.
+    fn named(name: &str) -> (TempDir, Manifest) {
+        manifest(&format!(
+            r#"
+            [project]
+            name = "{name}"
+            channels = []
+            platforms = ["linux-64"]
+            "#
+        ))
+    }
+
+    /// Returns the reason why the name of the manifest is invalid.
+    fn invalid_name_reason(manifest: &Manifest) -> String {
+        match manifest.package_name() {
+            Err(BuildBackendError::InvalidName { reason, .. }) => reason,
+            other => panic!("expected an invalid name, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn empty_names_are_rejected() {
+        for name in ["", "   "] {
+            let (_directory, manifest) = named(name);
+            assert_eq!(invalid_name_reason(&manifest), "the name must not be empty");
+        }
+    }
+
+    #[test]
+    fn names_with_surrounding_whitespace_are_rejected() {
+        let (_directory, manifest) = named(" package ");
+        assert_eq!(
+            invalid_name_reason(&manifest),
+            "the name must not start or end with whitespace"
+        );
+    }
+
+    #[test]
+    fn valid_names_are_accepted() {
+        let (_directory, manifest) = named("my-package");
+        assert_eq!(manifest.package_name().unwrap().as_source(), "my-package");
+    }
+
+    /// Returns the manifest of a project with the given license.
+    fn licensed(license: &str) -> (TempDir, Manifest) {
+        manifest(&format!(
+            r#"
+            [project]
+            name = "package"
+            channels = []
+            platforms = ["linux-64"]
+            license = "{license}"
+            "#
+        ))
+    }
+
+    #[test]
+    fn spdx_licenses_are_accepted() {
+        for license in ["MIT", "MIT OR Apache-2.0", "LicenseRef-Proprietary"] {
+            let (_directory, manifest) = licensed(license);
+            let about = manifest.about().unwrap();
+            assert_eq!(
+                about.license.map(|license| license.to_string()).as_deref(),
+                Some(license)
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_licenses_are_rejected() {
+        let (_directory, manifest) = licensed("MIT License");
+        let err = manifest.about().unwrap_err();
+        assert!(
+            err.to_string().contains("not a valid SPDX expression"),
+            "{err}"
+        );
+    }
+
+    /// Returns the names of the features of `environment`.
+    fn feature_names(manifest: &Manifest, environment: Option<&str>) -> Vec<String> {
+        manifest
+            .environment_features(environment)
+            .unwrap()
+            .iter()
+            .map(|feature| feature.name.as_str().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn environments_select_the_features() {
+        let (_directory, manifest) = manifest(
+            r#"
+            [project]
+            name = "package"
+            channels = []
+            platforms = ["linux-64"]
+
+            [feature.cuda.dependencies]
+            cuda-version = "12.*"
+
+            [feature.docs.dependencies]
+            sphinx = "*"
+
+            [environments]
+            default = ["cuda"]
+            docs = ["docs"]
+            cuda-only = { features = ["cuda"], no-default-feature = true }
+            "#,
+        );
+
+        assert_eq!(feature_names(&manifest, None), ["default", "cuda"]);
+        assert_eq!(feature_names(&manifest, Some("docs")), ["default", "docs"]);
+        assert_eq!(feature_names(&manifest, Some("cuda-only")), ["cuda"]);
+
+        let err = manifest.environment_features(Some("test")).unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn projects_without_environments_use_the_default_feature() {
+        let (_directory, manifest) = named("package");
+        assert_eq!(feature_names(&manifest, None), ["default"]);
+    }
+}
@@ -0,0 +1,170 @@
+//! An in-memory cache of the metadata of a package.
+//!
+//! The dependencies of a package differ per platform, so the cache is keyed
+//! by the resolved build and host platform, their virtual packages, the
+//! channels that are used to solve the environments, and the source
+//! dependencies that were already built.
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use parking_lot::Mutex;
+use pixi_build_types::CondaPackageMetadata;
+use rattler_build::metadata::{BuildConfiguration, PlatformWithVirtualPackages};
+use rattler_conda_types::Platform;
+use reqwest::Url;
+
+use crate::dependencies::BuiltSourcePackage;
+
+/// Identifies the metadata of a package for a specific build configuration.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct MetadataCacheKey {
+    target_platform: Platform,
+    build_platform: Platform,
+    build_virtual_packages: Vec<String>,
+    host_platform: Platform,
+    host_virtual_packages: Vec<String>,
+    channels: Vec<Url>,
+    built_sources: Vec<String>,
+}
+
+impl MetadataCacheKey {
+    /// Returns the key for the metadata that is computed with the given
+    /// build configuration and built source dependencies.
+    pub fn new(
+        build_configuration: &BuildConfiguration,
+        built_sources: &[BuiltSourcePackage],
+    ) -> Self {
+        Self {
+            target_platform: build_configuration.target_platform,
+            build_platform: build_configuration.build_platform.platform,
+            build_virtual_packages: virtual_packages(&build_configuration.build_platform),
+            host_platform: build_configuration.host_platform.platform,
+            host_virtual_packages: virtual_packages(&build_configuration.host_platform),
+            channels: build_configuration.channels.clone(),
+            built_sources: built_sources
+                .iter()
+                .map(|package| {
+                    format!(
+                        "{} {} {}",
+                        package.name.as_normalized(),
+                        package.version,
+                        package.build
+                    )
+                })
+                .sorted()
+                .collect(),
+        }
+    }
+}
+
+/// Returns the virtual packages of the platform in a stable order.
+fn virtual_packages(platform: &PlatformWithVirtualPackages) -> Vec<String> {
+    let mut virtual_packages = platform
+        .virtual_packages
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    virtual_packages.sort();
+    virtual_packages
+}
+
+/// Caches the metadata of the packages of a manifest per
+/// [`MetadataCacheKey`].
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    entries: Mutex<HashMap<MetadataCacheKey, Vec<CondaPackageMetadata>>>,
+}
+
+impl MetadataCache {
+    /// Returns the cached metadata for the given key.
+    pub fn get(&self, key: &MetadataCacheKey) -> Option<Vec<CondaPackageMetadata>> {
+        self.entries.lock().get(key).cloned()
+    }
+
+    /// Stores the metadata for the given key, replacing any previous entry.
+    pub fn insert(&self, key: MetadataCacheKey, packages: Vec<CondaPackageMetadata>) {
+        self.entries.lock().insert(key, packages);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{GenericVirtualPackage, NoArchType, PackageName, Version};
+
+    use super::*;
+
+    fn virtual_package(name: &str, version: &str) -> GenericVirtualPackage {
+        GenericVirtualPackage {
+            name: PackageName::new_unchecked(name),
+            version: Version::from_str(version).unwrap(),
+            build_string: "0".to_string(),
+        }
+    }
+
+    fn key(
+        host_platform: Platform,
+        host_virtual_packages: Vec<GenericVirtualPackage>,
+    ) -> MetadataCacheKey {
+        let host_platform = PlatformWithVirtualPackages {
+            platform: host_platform,
+            virtual_packages: host_virtual_packages,
+        };
+        MetadataCacheKey {
+            target_platform: host_platform.platform,
+            build_platform: Platform::Linux64,
+            build_virtual_packages: Vec::new(),
+            host_platform: host_platform.platform,
+            host_virtual_packages: virtual_packages(&host_platform),
+            channels: vec![Url::parse("https://prefix.dev/conda-forge").unwrap()],
+            built_sources: Vec::new(),
+        }
+    }
+
+    fn metadata(subdir: Platform) -> CondaPackageMetadata {
+        CondaPackageMetadata {
+            name: PackageName::new_unchecked("package"),
+            version: Version::from_str("1.0.0").unwrap().into(),
+            build: "h0_0".to_string(),
+            build_number: 0,
+            subdir,
+            depends: Vec::new(),
+            constraints: Vec::new(),
+            license: None,
+            license_family: None,
+            noarch: NoArchType::none(),
+        }
+    }
+
+    #[test]
+    fn metadata_is_cached_per_platform() {
+        let cache = MetadataCache::default();
+        let linux = key(Platform::Linux64, Vec::new());
+        let osx = key(Platform::OsxArm64, Vec::new());
+
+        cache.insert(linux.clone(), vec![metadata(Platform::Linux64)]);
+        assert_eq!(cache.get(&linux).unwrap()[0].subdir, Platform::Linux64);
+        assert!(cache.get(&osx).is_none());
+
+        cache.insert(osx.clone(), vec![metadata(Platform::OsxArm64)]);
+        assert_eq!(cache.get(&osx).unwrap()[0].subdir, Platform::OsxArm64);
+        assert_eq!(cache.get(&linux).unwrap()[0].subdir, Platform::Linux64);
+    }
+
+    #[test]
+    fn keys_depend_on_the_virtual_packages_but_not_their_order() {
+        let glibc = virtual_package("__glibc", "2.28");
+        let cuda = virtual_package("__cuda", "12.4");
+
+        assert_eq!(
+            key(Platform::Linux64, vec![glibc.clone(), cuda.clone()]),
+            key(Platform::Linux64, vec![cuda.clone(), glibc.clone()])
+        );
+        assert_ne!(
+            key(Platform::Linux64, vec![glibc.clone()]),
+            key(Platform::Linux64, vec![glibc, cuda])
+        );
+    }
+}
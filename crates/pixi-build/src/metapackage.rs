@@ -0,0 +1,103 @@
+//! Metapackages, which consist only of their metadata. They are used to
+//! install a set of other packages together, so they have run dependencies
+//! but no sources, no build step and no files.
+
+use rattler_build::recipe::{
+    parser::{Python, Script},
+    Recipe,
+};
+use rattler_conda_types::NoArchType;
+
+use crate::config::NoArch;
+
+/// Turns the `recipe` of a backend into the recipe of a metapackage. The
+/// sources, the build script and the build and host dependencies are removed,
+/// so rattler-build packages only the metadata and the run dependencies.
+///
+/// A metapackage contains no platform specific files, so it is
+/// `noarch: generic` unless a `noarch` type is configured explicitly.
+pub fn into_metapackage(recipe: &mut Recipe, noarch: Option<NoArch>) {
+    recipe.source.clear();
+    recipe.build.script = Script::default();
+    recipe.build.python = Python::default();
+    recipe.requirements.build.clear();
+    recipe.requirements.host.clear();
+    recipe.build.noarch = match noarch {
+        Some(noarch) => noarch.into(),
+        None => NoArchType::generic(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rattler_build::recipe::parser::{
+        About, Build, Dependency, Package, Requirements, ScriptContent,
+    };
+    use rattler_conda_types::{MatchSpec, PackageName, ParseStrictness, Version};
+
+    use super::*;
+
+    fn dependency(spec: &str) -> Dependency {
+        Dependency::Spec(MatchSpec::from_str(spec, ParseStrictness::Strict).unwrap())
+    }
+
+    /// Returns the recipe of a package that is built with a script.
+    fn recipe() -> Recipe {
+        Recipe {
+            schema_version: 1,
+            context: Default::default(),
+            package: Package {
+                version: Version::from_str("1.0.0").unwrap().into(),
+                name: PackageName::new_unchecked("bundle"),
+            },
+            cache: None,
+            source: vec![],
+            build: Build {
+                script: Script {
+                    content: ScriptContent::Commands(vec!["make install".to_string()]),
+                    ..Script::default()
+                },
+                noarch: NoArchType::none(),
+                ..Build::default()
+            },
+            requirements: Requirements {
+                build: vec![dependency("cmake")],
+                host: vec![dependency("zlib")],
+                run: vec![dependency("numpy"), dependency("pandas >=2")],
+                ..Requirements::default()
+            },
+            tests: vec![],
+            about: About::default(),
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn metapackages_only_keep_the_run_dependencies() {
+        let mut recipe = recipe();
+        into_metapackage(&mut recipe, None);
+
+        assert!(recipe.source.is_empty());
+        assert!(matches!(
+            &recipe.build.script.content,
+            ScriptContent::Default
+        ));
+        assert!(recipe.requirements.build.is_empty());
+        assert!(recipe.requirements.host.is_empty());
+        assert_eq!(recipe.requirements.run.len(), 2);
+        assert_eq!(recipe.build.noarch, NoArchType::generic());
+    }
+
+    #[test]
+    fn metapackages_keep_a_configured_noarch_type() {
+        let mut recipe = recipe();
+        into_metapackage(&mut recipe, Some(NoArch::None));
+        assert!(recipe.build.noarch.is_none());
+
+        let mut recipe = self::recipe();
+        into_metapackage(&mut recipe, Some(NoArch::Python));
+        assert_eq!(recipe.build.noarch, NoArchType::python());
+    }
+}
@@ -0,0 +1,18 @@
+//! Checks of the contents of a built package.
+
+use std::path::Path;
+
+use crate::reproducible::extract;
+
+/// Checks that the rendered recipe is stored in `info/recipe` of the built
+/// `package`, which is the case if `store-recipe` is enabled.
+pub fn ensure_recipe_stored(package: &Path) -> miette::Result<()> {
+    let extracted = extract(package)?;
+    if !extracted.path().join("info").join("recipe").is_dir() {
+        miette::bail!(
+            "`store-recipe` is enabled, but {} does not contain the recipe in `info/recipe`",
+            package.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use miette::IntoDiagnostic;
+use pixi_build_types::PlatformAndVirtualPackages;
+use rattler_build::metadata::PlatformWithVirtualPackages;
+use rattler_conda_types::{NoArchType, Platform};
+use rattler_virtual_packages::VirtualPackageOverrides;
+
+/// Determines the build and host platform, including their virtual packages.
+///
+/// Platforms that are not specified default to the current platform with
+/// the virtual packages that are detected on this machine. The same applies
+/// to the virtual packages of a platform that is specified without them if
+/// it is the current platform, other platforms have no virtual packages then.
+/// The detection respects the overrides from the environment (e.g.
+/// `CONDA_OVERRIDE_CUDA`).
+pub fn resolve_build_and_host_platform(
+    build_platform: Option<PlatformAndVirtualPackages>,
+    host_platform: Option<PlatformAndVirtualPackages>,
+) -> miette::Result<(PlatformWithVirtualPackages, PlatformWithVirtualPackages)> {
+    let needs_detection = |platform: &Option<PlatformAndVirtualPackages>| match platform {
+        Some(platform) => {
+            platform.virtual_packages.is_none() && platform.platform == Platform::current()
+        }
+        None => true,
+    };
+    let current_platform = if needs_detection(&build_platform) || needs_detection(&host_platform) {
+        Some(
+            PlatformWithVirtualPackages::detect(&VirtualPackageOverrides::from_env())
+                .into_diagnostic()?,
+        )
+    } else {
+        None
+    };
+
+    let resolve = |platform: Option<PlatformAndVirtualPackages>| match platform {
+        Some(PlatformAndVirtualPackages {
+            platform,
+            virtual_packages: Some(virtual_packages),
+        }) => PlatformWithVirtualPackages {
+            platform,
+            virtual_packages,
+        },
+        Some(PlatformAndVirtualPackages { platform, .. }) if platform != Platform::current() => {
+            PlatformWithVirtualPackages {
+                platform,
+                virtual_packages: Vec::new(),
+            }
+        }
+        _ => current_platform
+            .clone()
+            .expect("the current platform is detected if needed"),
+    };
+
+    Ok((resolve(build_platform), resolve(host_platform)))
+}
+
+/// Returns the platform the package is built for, which determines the
+/// `subdir` of the package. This is `noarch` for noarch packages. Otherwise
+/// it is the `requested` target platform if specified (e.g. with
+/// `--target-platform` when cross-compiling) and the host platform if not,
+/// including less conventional platforms such as `emscripten-wasm32`.
+pub fn target_platform(
+    host_platform: Platform,
+    noarch: &NoArchType,
+    requested: Option<Platform>,
+) -> miette::Result<Platform> {
+    match requested {
+        Some(Platform::NoArch) | None if !noarch.is_none() => Ok(Platform::NoArch),
+        Some(requested) if !noarch.is_none() => miette::bail!(
+            "the package is noarch and cannot target {requested}, set `noarch = \"none\"` to build it for a specific platform"
+        ),
+        Some(Platform::NoArch) => miette::bail!(
+            "the package is not noarch and cannot target noarch, configure `noarch` to build a noarch package"
+        ),
+        Some(requested) => Ok(requested),
+        None => Ok(host_platform),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rattler_conda_types::{GenericVirtualPackage, PackageName, Version};
+
+    use super::*;
+
+    fn virtual_package(name: &str, version: &str) -> GenericVirtualPackage {
+        GenericVirtualPackage {
+            name: PackageName::new_unchecked(name),
+            version: Version::from_str(version).unwrap(),
+            build_string: "0".to_string(),
+        }
+    }
+
+    #[test]
+    fn uses_the_specified_virtual_packages() {
+        let build_virtual_packages = vec![virtual_package("__glibc", "2.17")];
+        let host_virtual_packages = vec![virtual_package("__cuda", "12.0")];
+        let (build, host) = resolve_build_and_host_platform(
+            Some(PlatformAndVirtualPackages {
+                platform: Platform::current(),
+                virtual_packages: Some(build_virtual_packages.clone()),
+            }),
+            Some(PlatformAndVirtualPackages {
+                platform: Platform::Linux64,
+                virtual_packages: Some(host_virtual_packages.clone()),
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(build.platform, Platform::current());
+        assert_eq!(build.virtual_packages, build_virtual_packages);
+        assert_eq!(host.platform, Platform::Linux64);
+        assert_eq!(host.virtual_packages, host_virtual_packages);
+    }
+
+    #[test]
+    fn other_platforms_without_virtual_packages_have_none() {
+        let other = if Platform::current() == Platform::Win64 {
+            Platform::Linux64
+        } else {
+            Platform::Win64
+        };
+        let (build, host) = resolve_build_and_host_platform(
+            Some(PlatformAndVirtualPackages {
+                platform: Platform::current(),
+                virtual_packages: Some(Vec::new()),
+            }),
+            Some(PlatformAndVirtualPackages {
+                platform: other,
+                virtual_packages: None,
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(build.platform, Platform::current());
+        assert_eq!(host.platform, other);
+        assert!(host.virtual_packages.is_empty());
+    }
+
+    #[test]
+    fn unspecified_platforms_default_to_the_current_platform() {
+        let (build, host) = resolve_build_and_host_platform(None, None).unwrap();
+        assert_eq!(build.platform, Platform::current());
+        assert_eq!(host.platform, Platform::current());
+    }
+}
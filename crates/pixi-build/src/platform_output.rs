@@ -0,0 +1,167 @@
+//! Noarch packages with a platform specific part, e.g. a python package that
+//! ships a small compiled helper.
+//!
+//! If `platform-output` is configured, the recipe of the package is split
+//! into two outputs that are built from the same sources. The platform
+//! specific output contains the files that match the globs of
+//! `platform-output.files`, and the noarch package contains all other files
+//! and depends on the platform specific output of the same version.
+//!
+//! This has some limitations:
+//!
+//! - The build script runs once per output. Backends that reuse their build
+//!   directory (e.g. cmake) only rebuild what changed, the others build the
+//!   sources twice.
+//! - Both outputs have the run dependencies of the package, and the tests of
+//!   the package only run for the noarch output.
+//! - The noarch output must not contain files that the helper needs at build
+//!   time, because only the files of the output itself are packaged.
+
+use rattler_build::recipe::{
+    parser::{Dependency, GlobVec, Python},
+    Recipe,
+};
+use rattler_conda_types::{version_spec::EqualityOperator, MatchSpec, NoArchType, VersionSpec};
+
+use crate::config::{FilesConfig, PlatformOutputConfig};
+
+/// Splits the `recipe` of a noarch package into the recipe of the platform
+/// specific output and the recipe of the noarch output, in the order in which
+/// they are built. The `files` of the package apply to both outputs.
+///
+/// Returns an error if the package is not noarch, because the whole package
+/// is platform specific in that case.
+pub fn split_platform_output(
+    recipe: Recipe,
+    platform_output: &PlatformOutputConfig,
+    files: &FilesConfig,
+) -> miette::Result<[Recipe; 2]> {
+    if recipe.build.noarch.is_none() {
+        miette::bail!(
+            help = "configure `noarch = \"generic\"` or `noarch = \"python\"`, or remove `platform-output` to build a single platform specific package",
+            "the package has a `platform-output`, but the package itself is not noarch"
+        );
+    }
+    let platform_files = platform_output
+        .files
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let excluded_files = files.exclude.iter().map(String::as_str);
+
+    let mut platform = recipe.clone();
+    platform.package.name = platform_output.name.clone();
+    platform.build.noarch = NoArchType::none();
+    platform.build.python = Python::default();
+    platform.build.files = GlobVec::from_vec(
+        platform_files.clone(),
+        Some(excluded_files.clone().collect()),
+    );
+    platform.tests.clear();
+
+    let mut noarch = recipe;
+    noarch.build.files = GlobVec::from_vec(
+        files.include.iter().map(String::as_str).collect(),
+        Some(excluded_files.chain(platform_files).collect()),
+    );
+    noarch.requirements.run.push(Dependency::Spec(MatchSpec {
+        name: Some(platform_output.name.clone()),
+        version: Some(VersionSpec::Exact(
+            EqualityOperator::Equals,
+            noarch.package.version.version().clone(),
+        )),
+        ..MatchSpec::default()
+    }));
+
+    Ok([platform, noarch])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr};
+
+    use rattler_build::recipe::parser::{About, Build, Package, Requirements};
+    use rattler_conda_types::{PackageName, Version};
+
+    use super::*;
+
+    /// Returns the recipe of the noarch package `package`.
+    fn recipe(noarch: NoArchType) -> Recipe {
+        Recipe {
+            schema_version: 1,
+            context: Default::default(),
+            package: Package {
+                version: Version::from_str("1.2.0").unwrap().into(),
+                name: PackageName::new_unchecked("package"),
+            },
+            cache: None,
+            source: vec![],
+            build: Build {
+                noarch,
+                ..Build::default()
+            },
+            requirements: Requirements::default(),
+            tests: vec![],
+            about: About::default(),
+            extra: Default::default(),
+        }
+    }
+
+    fn platform_output() -> PlatformOutputConfig {
+        PlatformOutputConfig {
+            name: PackageName::new_unchecked("package-helper"),
+            files: vec!["bin/helper*".to_string()],
+        }
+    }
+
+    #[test]
+    fn the_platform_files_are_split_into_their_own_output() {
+        let files = FilesConfig {
+            exclude: vec!["**/*.pdb".to_string()],
+            ..FilesConfig::default()
+        };
+        let [platform, noarch] =
+            split_platform_output(recipe(NoArchType::python()), &platform_output(), &files)
+                .unwrap();
+
+        assert_eq!(platform.package.name.as_normalized(), "package-helper");
+        assert!(platform.build.noarch.is_none());
+        assert!(platform.build.files.is_match(Path::new("bin/helper")));
+        assert!(!platform.build.files.is_match(Path::new("bin/helper.pdb")));
+        assert!(!platform
+            .build
+            .files
+            .is_match(Path::new("site-packages/package/__init__.py")));
+
+        assert_eq!(noarch.package.name.as_normalized(), "package");
+        assert_eq!(noarch.build.noarch, NoArchType::python());
+        assert!(!noarch.build.files.is_match(Path::new("bin/helper")));
+        assert!(noarch
+            .build
+            .files
+            .is_match(Path::new("site-packages/package/__init__.py")));
+        assert_eq!(
+            noarch
+                .requirements
+                .run
+                .iter()
+                .map(|dependency| match dependency {
+                    Dependency::Spec(spec) => spec.to_string(),
+                    _ => panic!("expected a match spec"),
+                })
+                .collect::<Vec<_>>(),
+            ["package-helper ==1.2.0"]
+        );
+    }
+
+    #[test]
+    fn platform_specific_packages_cannot_be_split() {
+        let err = split_platform_output(
+            recipe(NoArchType::none()),
+            &platform_output(),
+            &FilesConfig::default(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not noarch"), "{err}");
+    }
+}
@@ -0,0 +1,109 @@
+//! Configuration of the length of the host prefix of a build.
+//!
+//! Files that contain the host prefix are rewritten when the package is
+//! installed. In binary files the prefix is replaced in place and padded with
+//! null bytes, so a binary can only be relocated to a prefix that is at most
+//! as long as the host prefix it was built in. rattler-build therefore pads
+//! the host prefix to 255 characters on Unix. It does not pad it on Windows.
+//!
+//! A longer host prefix allows installing the package into longer paths, but
+//! the prefix also ends up in the build itself. Shebang lines and command
+//! lines that contain it may exceed the limits of the operating system or of
+//! build tools, and on Windows paths longer than 260 characters require long
+//! path support.
+
+use std::path::{Path, PathBuf};
+
+use rattler_build::metadata::Directories;
+
+/// The pattern that the host prefix is padded with.
+const PLACEHOLDER: &str = "_placehold";
+
+/// The maximum length of a single component of a path on most file systems.
+const MAX_COMPONENT_LENGTH: usize = 255;
+
+/// Replaces the host prefix of `directories` with one in the build directory
+/// that is exactly `length` bytes long. Does nothing if `length` is `None`,
+/// which keeps the default of rattler-build.
+///
+/// Returns an error if the build directory is too long to fit a host prefix
+/// of `length`, or if `length` requires a path component that is longer than
+/// file systems allow.
+pub fn pad_host_prefix(directories: &mut Directories, length: Option<usize>) -> miette::Result<()> {
+    let Some(length) = length else {
+        return Ok(());
+    };
+    directories.host_prefix = padded_host_prefix(&directories.build_dir, length)?;
+    tracing::debug!(
+        "using the host prefix {}",
+        directories.host_prefix.display()
+    );
+    Ok(())
+}
+
+/// Returns the host prefix in `build_dir` that is exactly `length` bytes
+/// long, see [`pad_host_prefix`].
+fn padded_host_prefix(build_dir: &Path, length: usize) -> miette::Result<PathBuf> {
+    let unpadded = build_dir.join("host_env");
+    let unpadded_length = unpadded.as_os_str().len();
+    if length < unpadded_length {
+        miette::bail!(
+            help = "increase `host-prefix-length` or use a shorter build directory",
+            "`host-prefix-length` is {length}, but the host prefix {} is already {unpadded_length} characters long",
+            unpadded.display()
+        );
+    }
+
+    let component_length = length - build_dir.as_os_str().len() - 1;
+    if component_length > MAX_COMPONENT_LENGTH {
+        miette::bail!(
+            "`host-prefix-length` is {length}, but the host prefix in {} can be at most {} characters long",
+            build_dir.display(),
+            length - component_length + MAX_COMPONENT_LENGTH
+        );
+    }
+
+    let padding = PLACEHOLDER
+        .chars()
+        .cycle()
+        .take(length - unpadded_length)
+        .collect::<String>();
+    Ok(build_dir.join(format!("host_env{padding}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_prefix_has_exactly_the_requested_length() {
+        let build_dir = Path::new("/tmp/bld/rattler-build_package_1234");
+        for length in [build_dir.as_os_str().len() + 9, 100, 255] {
+            let prefix = padded_host_prefix(build_dir, length).unwrap();
+            assert_eq!(prefix.as_os_str().len(), length);
+            assert_eq!(prefix.parent(), Some(build_dir));
+            let name = prefix.file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with("host_env"), "{name}");
+            assert!(
+                name["host_env".len()..]
+                    .chars()
+                    .all(|c| PLACEHOLDER.contains(c)),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn host_prefix_cannot_be_shorter_than_the_build_dir() {
+        let build_dir = Path::new("/tmp/bld/rattler-build_package_1234");
+        let err = padded_host_prefix(build_dir, 20).unwrap_err();
+        assert!(err.to_string().contains("already"), "{err}");
+    }
+
+    #[test]
+    fn host_prefix_component_cannot_exceed_the_file_system_limit() {
+        let build_dir = Path::new("/tmp/bld");
+        let err = padded_host_prefix(build_dir, 1024).unwrap_err();
+        assert!(err.to_string().contains("at most 264"), "{err}");
+    }
+}
@@ -1,7 +1,20 @@
-use pixi_build_types::procedures::{
-    conda_build::{CondaBuildParams, CondaBuildResult},
-    conda_metadata::{CondaMetadataParams, CondaMetadataResult},
-    initialize::{InitializeParams, InitializeResult},
+use pixi_build_types::{
+    procedures::{
+        self,
+        initialize::{InitializeParams, InitializeResult},
+    },
+    BackendCapabilities,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    conda_build::{CondaBuildRequest, CondaBuildResponse},
+    conda_metadata::{CondaMetadataRequest, CondaMetadataResponse},
+    describe::{self, DescribeParams, DescribeResult},
+    effective_config::EffectiveConfig,
+    error::BuildBackendError,
+    input_globs::{self, InputGlobsParams, InputGlobsResult},
+    recipe_hash::{self, RecipeHashResult},
 };
 
 /// A trait that is used to initialize a new protocol connection.
@@ -9,6 +22,12 @@ use pixi_build_types::procedures::{
 pub trait ProtocolFactory: Send + Sync + 'static {
     type Protocol: Protocol + Send + Sync + 'static;
 
+    /// Returns the capabilities of the backend that are known without a
+    /// manifest. The capabilities that are returned from
+    /// [`Self::initialize`] can still depend on the manifest and the
+    /// capabilities of the frontend.
+    fn capabilities(&self) -> BackendCapabilities;
+
     /// Called when the client requests initialization.
     async fn initialize(
         &self,
@@ -17,18 +36,56 @@ pub trait ProtocolFactory: Send + Sync + 'static {
 }
 
 /// A trait that defines the protocol for a pixi build backend.
+///
+/// Every method has a default implementation that returns
+/// [`BuildBackendError::MethodNotSupported`], so a backend only implements
+/// the methods it supports.
+///
+/// The methods that can take a long time receive a [`CancellationToken`] that
+/// is cancelled when the frontend cancels the request. Backends stop their
+/// work, including the processes they started, and return
+/// [`BuildBackendError::Cancelled`] when it is cancelled.
 #[async_trait::async_trait]
 pub trait Protocol {
     /// Called when the client requests metadata for a Conda package.
     async fn get_conda_metadata(
         &self,
-        _params: CondaMetadataParams,
-    ) -> miette::Result<CondaMetadataResult> {
-        unimplemented!("get_conda_metadata not implemented");
+        _request: CondaMetadataRequest,
+        _cancellation: CancellationToken,
+    ) -> miette::Result<CondaMetadataResponse> {
+        Err(BuildBackendError::MethodNotSupported(procedures::conda_metadata::METHOD_NAME).into())
+    }
+
+    /// Called when the client requests the recipe that is synthesized from the
+    /// manifest.
+    async fn describe(&self, _params: DescribeParams) -> miette::Result<DescribeResult> {
+        Err(BuildBackendError::MethodNotSupported(describe::METHOD_NAME).into())
+    }
+
+    /// Called when the client requests the configuration that the backend
+    /// uses to build the package, without building anything.
+    async fn effective_config(&self, _params: DescribeParams) -> miette::Result<EffectiveConfig> {
+        Err(BuildBackendError::MethodNotSupported("effective-config").into())
+    }
+
+    /// Called when the client requests a hash of the recipe that is
+    /// synthesized from the manifest, without building anything.
+    async fn recipe_hash(&self, _params: DescribeParams) -> miette::Result<RecipeHashResult> {
+        Err(BuildBackendError::MethodNotSupported(recipe_hash::METHOD_NAME).into())
+    }
+
+    /// Called when the client requests the globs of the files that a build of
+    /// the package depends on, without building anything.
+    async fn input_globs(&self, _params: InputGlobsParams) -> miette::Result<InputGlobsResult> {
+        Err(BuildBackendError::MethodNotSupported(input_globs::METHOD_NAME).into())
     }
 
     /// Called when the client requests to build a Conda package.
-    async fn build_conda(&self, _params: CondaBuildParams) -> miette::Result<CondaBuildResult> {
-        unimplemented!("build_conda not implemented");
+    async fn build_conda(
+        &self,
+        _request: CondaBuildRequest,
+        _cancellation: CancellationToken,
+    ) -> miette::Result<CondaBuildResponse> {
+        Err(BuildBackendError::MethodNotSupported(procedures::conda_build::METHOD_NAME).into())
     }
 }
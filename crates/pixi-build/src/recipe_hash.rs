@@ -0,0 +1,76 @@
+//! A procedure that returns a hash of the recipe that a backend synthesizes
+//! from the manifest, without building anything. Frontends can use it as a
+//! cache key: as long as the hash and the sources are unchanged, a build
+//! produces the same package.
+
+use std::{collections::BTreeMap, path::Path};
+
+use miette::IntoDiagnostic;
+use rattler_build::recipe::Recipe;
+use rattler_conda_types::Platform;
+use rattler_digest::{digest::Digest, Sha256};
+use serde::Serialize;
+
+use crate::{config::JOBS_ENV_VAR, variants::used_variant};
+
+/// The name of the method. The parameters are the same as those of the
+/// `describe` method, see [`crate::describe::DescribeParams`].
+pub const METHOD_NAME: &str = "recipeHash";
+
+/// The result of the `recipeHash` method.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeHashResult {
+    /// The hex encoded SHA256 hash of the recipe.
+    pub hash: String,
+}
+
+/// Computes the hash of `recipe` when it is built for `host_platform` on the
+/// current platform with `variant`.
+///
+/// The hash is computed over a serialization of the recipe, so it is stable
+/// as long as the recipe is synthesized deterministically, e.g. with sorted
+/// requirements. It does not cover the contents of the sources.
+///
+/// Only the parts of the recipe that determine the package contribute to the
+/// hash, so the same project has the same hash on every machine:
+/// - paths in `manifest_root` are hashed relative to it, so checkouts in
+///   different directories have the same hash,
+/// - the number of parallel jobs, see [`JOBS_ENV_VAR`], is left out,
+/// - only the keys of `variant` that are used by the recipe are hashed, see
+///   [`used_variant`].
+pub fn recipe_hash(
+    recipe: &Recipe,
+    manifest_root: &Path,
+    host_platform: Platform,
+    variant: &BTreeMap<String, String>,
+) -> miette::Result<String> {
+    #[derive(Serialize)]
+    struct HashInput<'a> {
+        recipe: &'a Recipe,
+        build_platform: Platform,
+        host_platform: Platform,
+        variant: BTreeMap<String, String>,
+    }
+
+    let mut recipe = recipe.clone();
+    recipe
+        .build
+        .script
+        .env
+        .retain(|name, _| name != JOBS_ENV_VAR);
+    let variant = used_variant(variant, &recipe.requirements, &recipe.build.variant);
+
+    let mut serialized = serde_yaml::to_string(&HashInput {
+        recipe: &recipe,
+        build_platform: Platform::current(),
+        host_platform,
+        variant,
+    })
+    .into_diagnostic()?;
+    let manifest_root = manifest_root.display().to_string();
+    if !manifest_root.is_empty() {
+        serialized = serialized.replace(&manifest_root, "$MANIFEST_ROOT");
+    }
+    Ok(format!("{:x}", Sha256::digest(serialized.as_bytes())))
+}
@@ -0,0 +1,132 @@
+//! Verification that a built package does not hardcode paths of the machine
+//! it was built on.
+//!
+//! rattler-build replaces the host prefix in the files in which it detects
+//! it, so those files are relocated when the package is installed. Any other
+//! absolute path of the build, such as the build prefix or the source
+//! directory, still ends up in the package as is and breaks once the package
+//! is installed elsewhere.
+
+use std::path::{Path, PathBuf};
+
+use itertools::Itertools;
+use miette::{Context, IntoDiagnostic};
+use rattler_build::metadata::Output;
+use rattler_conda_types::package::{PackageFile, PathsJson};
+
+use crate::{config::RelocatabilityCheck, reproducible::extract};
+
+/// A file in a package that contains an absolute path of the build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardcodedPath {
+    /// The path of the file relative to the root of the package.
+    pub file: PathBuf,
+
+    /// The absolute path that the file contains.
+    pub path: String,
+}
+
+/// Checks the built `package` of `output` for hardcoded paths according to
+/// `check`. Every occurrence is reported as a warning, or as an error if
+/// `check` is [`RelocatabilityCheck::Error`].
+///
+/// `source_dir` is the directory the sources were read from, which compiled
+/// backends embed when they build directly from the source directory.
+pub fn check_relocatability(
+    check: RelocatabilityCheck,
+    package: &Path,
+    output: &Output,
+    source_dir: &Path,
+) -> miette::Result<()> {
+    if check == RelocatabilityCheck::Off {
+        return Ok(());
+    }
+
+    let directories = &output.build_configuration.directories;
+    let hardcoded = find_hardcoded_paths(
+        package,
+        &directories.host_prefix,
+        &[
+            directories.build_prefix.as_path(),
+            directories.work_dir.as_path(),
+            source_dir,
+        ],
+    )?;
+    if hardcoded.is_empty() {
+        return Ok(());
+    }
+
+    let report = hardcoded
+        .iter()
+        .map(|hardcoded| {
+            format!(
+                "  - {} contains {}",
+                hardcoded.file.display(),
+                hardcoded.path
+            )
+        })
+        .join("\n");
+    if check == RelocatabilityCheck::Error {
+        miette::bail!(
+            "{} is not relocatable, it contains absolute paths of the build:\n{report}",
+            package.display()
+        );
+    }
+    tracing::warn!(
+        "{} may not be relocatable, it contains absolute paths of the build:\n{report}",
+        package.display()
+    );
+    Ok(())
+}
+
+/// Returns the files of `package` that contain one of `paths`, or that
+/// contain `host_prefix` without being registered for prefix replacement.
+pub fn find_hardcoded_paths(
+    package: &Path,
+    host_prefix: &Path,
+    paths: &[&Path],
+) -> miette::Result<Vec<HardcodedPath>> {
+    let extracted = extract(package)?;
+    let paths_json = PathsJson::from_package_directory(extracted.path())
+        .into_diagnostic()
+        .with_context(|| format!("failed to read the paths of {}", package.display()))?;
+
+    let host_prefix = host_prefix.display().to_string();
+    let paths = paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .filter(|path| !path.is_empty())
+        .unique()
+        .collect::<Vec<_>>();
+
+    let mut hardcoded = Vec::new();
+    for entry in paths_json.paths {
+        let path = extracted.path().join(&entry.relative_path);
+        if path.is_symlink() || !path.is_file() {
+            continue;
+        }
+        let contents = std::fs::read(&path)
+            .into_diagnostic()
+            .with_context(|| format!("failed to read {}", entry.relative_path.display()))?;
+        let mut needles = paths.iter().collect::<Vec<_>>();
+        if entry.prefix_placeholder.is_none() {
+            needles.push(&host_prefix);
+        }
+        hardcoded.extend(
+            needles
+                .into_iter()
+                .filter(|needle| contains(&contents, needle.as_bytes()))
+                .map(|needle| HardcodedPath {
+                    file: entry.relative_path.clone(),
+                    path: needle.clone(),
+                }),
+        );
+    }
+    Ok(hardcoded)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
@@ -0,0 +1,164 @@
+//! Retries and timeouts for downloads of repodata.
+//!
+//! Resolving the dependencies of a package downloads the repodata of every
+//! channel, which fails the whole request if the network is unreliable. The
+//! middleware in this module retries these downloads independently of other
+//! requests. Downloads of packages and the steps of the build itself are
+//! never retried.
+//!
+//! By default no additional attempts are made and no additional timeout is
+//! applied. The timeout of the download client itself always applies.
+
+use std::time::Duration;
+
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// The maximum number of retries that can be configured.
+pub const MAX_RETRIES: u32 = 20;
+
+/// The maximum timeout in seconds that can be configured.
+pub const MAX_TIMEOUT: u64 = 60 * 60;
+
+/// How downloads of repodata are retried.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RepodataConfig {
+    /// The number of times a failed download of repodata is retried. A
+    /// download is retried after a network error, a timeout, or a server
+    /// error. Defaults to 0.
+    #[serde(default)]
+    pub retries: u32,
+
+    /// The number of seconds after which a single attempt to download
+    /// repodata is aborted. Defaults to no timeout besides the one of the
+    /// download client.
+    pub timeout: Option<u64>,
+}
+
+impl RepodataConfig {
+    /// Returns an error if the number of retries or the timeout is out of
+    /// range.
+    pub fn validate(&self) -> miette::Result<()> {
+        if self.retries > MAX_RETRIES {
+            miette::bail!(
+                "`repodata.retries` is {}, but at most {MAX_RETRIES} retries are supported",
+                self.retries
+            );
+        }
+        if let Some(timeout) = self.timeout {
+            if timeout == 0 || timeout > MAX_TIMEOUT {
+                miette::bail!(
+                    "`repodata.timeout` is {timeout}, but it must be between 1 and {MAX_TIMEOUT} seconds"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the middleware that applies the configuration, or `None` if
+    /// the defaults are used.
+    pub fn middleware(&self) -> Option<RepodataRetryMiddleware> {
+        (self.retries > 0 || self.timeout.is_some()).then(|| RepodataRetryMiddleware {
+            retries: self.retries,
+            timeout: self.timeout.map(Duration::from_secs),
+        })
+    }
+}
+
+/// An attempt to download repodata took longer than the configured timeout.
+#[derive(Debug, Error)]
+#[error("the download of {url} timed out after {} seconds", .timeout.as_secs())]
+struct RepodataTimeout {
+    url: reqwest::Url,
+    timeout: Duration,
+}
+
+/// A middleware that retries failed downloads of repodata, see
+/// [`RepodataConfig`]. Other requests are passed on unchanged.
+#[derive(Debug, Clone)]
+pub struct RepodataRetryMiddleware {
+    retries: u32,
+    timeout: Option<Duration>,
+}
+
+impl RepodataRetryMiddleware {
+    /// Runs a single attempt of `request`, aborting it after the timeout.
+    async fn attempt(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let Some(timeout) = self.timeout else {
+            return next.run(request, extensions).await;
+        };
+        let url = request.url().clone();
+        match tokio::time::timeout(timeout, next.run(request, extensions)).await {
+            Ok(result) => result,
+            Err(_) => Err(reqwest_middleware::Error::middleware(RepodataTimeout {
+                url,
+                timeout,
+            })),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RepodataRetryMiddleware {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        if !is_repodata(&request) {
+            return next.run(request, extensions).await;
+        }
+
+        let mut attempt = 0;
+        loop {
+            // Requests with a streaming body cannot be cloned and are not retried.
+            let Some(retry) = request.try_clone().filter(|_| attempt < self.retries) else {
+                return self.attempt(request, extensions, next).await;
+            };
+            let result = self
+                .attempt(retry, extensions, next.clone())
+                .await
+                .and_then(|response| {
+                    if response.status().is_server_error() {
+                        response.error_for_status().map_err(Into::into)
+                    } else {
+                        Ok(response)
+                    }
+                });
+            match result {
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "failed to download {}, retrying ({attempt}/{}): {err}",
+                        request.url(),
+                        self.retries
+                    );
+                    tokio::time::sleep(Duration::from_secs(1 << attempt.min(5))).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns `true` if `request` downloads repodata, e.g. `repodata.json` or
+/// one of its compressed or sharded variants.
+fn is_repodata(request: &Request) -> bool {
+    request
+        .url()
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .is_some_and(|file_name| {
+            file_name.starts_with("repodata") || file_name.ends_with(".msgpack.zst")
+        })
+}
@@ -0,0 +1,145 @@
+//! Verification that two builds of the same package are identical.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use miette::{Context, IntoDiagnostic};
+use rattler_digest::{compute_file_digest, Sha256};
+use tempfile::TempDir;
+
+/// A difference between the contents of two packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageDifference {
+    /// The file only exists in the first package.
+    OnlyInFirst(PathBuf),
+
+    /// The file only exists in the second package.
+    OnlyInSecond(PathBuf),
+
+    /// The file exists in both packages but its contents differ.
+    Contents(PathBuf),
+}
+
+impl Display for PackageDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackageDifference::OnlyInFirst(path) => {
+                write!(f, "{} only exists in the first build", path.display())
+            }
+            PackageDifference::OnlyInSecond(path) => {
+                write!(f, "{} only exists in the second build", path.display())
+            }
+            PackageDifference::Contents(path) => {
+                write!(f, "{} differs between the builds", path.display())
+            }
+        }
+    }
+}
+
+/// Builds a [`GlobSet`] of the paths inside a package that are ignored when
+/// packages are compared.
+pub fn ignore_globs(globs: &[String]) -> miette::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(
+            Glob::new(glob)
+                .into_diagnostic()
+                .with_context(|| format!("invalid glob '{glob}'"))?,
+        );
+    }
+    builder.build().into_diagnostic()
+}
+
+/// Compares the packages at `first` and `second` and returns the files that
+/// differ. Files inside the packages that match `ignore` are not compared.
+///
+/// If the archives are byte-for-byte identical no differences are returned.
+/// Otherwise both archives are extracted and compared file by file, which
+/// makes it possible to ignore files with inherently non-deterministic
+/// contents such as the build timestamp in `info/index.json`.
+pub fn compare_packages(
+    first: &Path,
+    second: &Path,
+    ignore: &GlobSet,
+) -> miette::Result<Vec<PackageDifference>> {
+    if file_digest(first)? == file_digest(second)? {
+        return Ok(Vec::new());
+    }
+
+    let first = extract(first)?;
+    let second = extract(second)?;
+    let mut first_files = package_files(first.path(), ignore)?;
+    let second_files = package_files(second.path(), ignore)?;
+
+    let mut differences = Vec::new();
+    for (path, second_digest) in second_files {
+        match first_files.remove(&path) {
+            Some(first_digest) if first_digest == second_digest => {}
+            Some(_) => differences.push(PackageDifference::Contents(path)),
+            None => differences.push(PackageDifference::OnlyInSecond(path)),
+        }
+    }
+    differences.extend(first_files.into_keys().map(PackageDifference::OnlyInFirst));
+    differences.sort_by(|a, b| difference_path(a).cmp(difference_path(b)));
+    Ok(differences)
+}
+
+fn difference_path(difference: &PackageDifference) -> &Path {
+    match difference {
+        PackageDifference::OnlyInFirst(path)
+        | PackageDifference::OnlyInSecond(path)
+        | PackageDifference::Contents(path) => path,
+    }
+}
+
+fn file_digest(path: &Path) -> miette::Result<String> {
+    let digest = compute_file_digest::<Sha256>(path)
+        .into_diagnostic()
+        .with_context(|| format!("failed to hash {}", path.display()))?;
+    Ok(format!("{digest:x}"))
+}
+
+/// Extracts the package at `package` into a temporary directory.
+pub(crate) fn extract(package: &Path) -> miette::Result<TempDir> {
+    let destination = TempDir::new()
+        .into_diagnostic()
+        .context("failed to create a temporary directory")?;
+    rattler_package_streaming::fs::extract(package, destination.path())
+        .into_diagnostic()
+        .with_context(|| format!("failed to extract {}", package.display()))?;
+    Ok(destination)
+}
+
+/// Returns the digests of all files in the extracted package at `root`,
+/// keyed by their path relative to `root`.
+fn package_files(root: &Path, ignore: &GlobSet) -> miette::Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(root) {
+        let entry = entry.into_diagnostic()?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let relative_path = entry
+            .path()
+            .strip_prefix(root)
+            .expect("walked paths are inside the package")
+            .to_path_buf();
+        if ignore.is_match(&relative_path) {
+            continue;
+        }
+        let digest = if entry.file_type().is_symlink() {
+            std::fs::read_link(entry.path())
+                .into_diagnostic()?
+                .display()
+                .to_string()
+        } else {
+            file_digest(entry.path())?
+        };
+        files.insert(relative_path, digest);
+    }
+    Ok(files)
+}
@@ -0,0 +1,152 @@
+//! Software bills of materials of built packages in the CycloneDX format.
+//!
+//! The bill of materials lists the packages of the resolved build and host
+//! environments of a package. Packages of the host environment have the
+//! scope `required`, because the built package is linked against them or
+//! depends on them at runtime. Packages of the build environment have the
+//! scope `excluded`, they were only used to build the package.
+//!
+//! Packages are identified by their package url, e.g.
+//! `pkg:conda/zlib@1.3.1?build=hb9d3cd8_2&channel=https://conda.anaconda.org/conda-forge/&subdir=linux-64&type=conda`.
+
+use std::path::{Path, PathBuf};
+
+use jsonrpc_core::serde_json;
+use miette::{Context, IntoDiagnostic};
+use rattler_build::metadata::Output;
+use rattler_conda_types::RepoDataRecord;
+use serde::Serialize;
+
+use crate::environment_snapshot::package_file_stem;
+
+/// The version of the CycloneDX specification that is written.
+const SPEC_VERSION: &str = "1.5";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    version: u32,
+    metadata: Metadata,
+    components: Vec<Component>,
+    dependencies: Vec<DependencyEntry>,
+}
+
+#[derive(Serialize)]
+struct Metadata {
+    timestamp: String,
+    component: Component,
+}
+
+#[derive(Serialize)]
+struct Component {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref")]
+    bom_ref: String,
+    name: String,
+    version: String,
+    purl: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DependencyEntry {
+    #[serde(rename = "ref")]
+    reference: String,
+    depends_on: Vec<String>,
+}
+
+/// Writes a CycloneDX bill of materials of `output` next to the built
+/// `package`, named after the package with a `.cdx.json` suffix. Returns the
+/// path of the written file.
+///
+/// Returns an error if the dependencies of `output` are not resolved.
+pub fn write_sbom(output: &Output, package: &Path) -> miette::Result<PathBuf> {
+    let Some(dependencies) = &output.finalized_dependencies else {
+        miette::bail!("the dependencies must be resolved before a bill of materials is written");
+    };
+
+    let package_purl = format!(
+        "pkg:conda/{}@{}?build={}&subdir={}",
+        output.name().as_normalized(),
+        output.version(),
+        output.build_string(),
+        output.target_platform()
+    );
+    let mut components: Vec<Component> = Vec::new();
+    let environments = [
+        ("required", dependencies.host.as_ref()),
+        ("excluded", dependencies.build.as_ref()),
+    ];
+    for (scope, resolved) in environments {
+        for record in resolved.into_iter().flat_map(|resolved| &resolved.resolved) {
+            let purl = purl(record);
+            if components.iter().any(|component| component.purl == purl) {
+                continue;
+            }
+            components.push(Component {
+                kind: "library",
+                bom_ref: purl.clone(),
+                name: record.package_record.name.as_normalized().to_string(),
+                version: record.package_record.version.to_string(),
+                purl,
+                scope: Some(scope),
+            });
+        }
+    }
+
+    let bom = Bom {
+        bom_format: "CycloneDX",
+        spec_version: SPEC_VERSION,
+        version: 1,
+        metadata: Metadata {
+            timestamp: output.build_configuration.timestamp.to_rfc3339(),
+            component: Component {
+                kind: "library",
+                bom_ref: package_purl.clone(),
+                name: output.name().as_normalized().to_string(),
+                version: output.version().to_string(),
+                purl: package_purl.clone(),
+                scope: None,
+            },
+        },
+        dependencies: vec![DependencyEntry {
+            reference: package_purl,
+            depends_on: components
+                .iter()
+                .filter(|component| component.scope == Some("required"))
+                .map(|component| component.bom_ref.clone())
+                .collect(),
+        }],
+        components,
+    };
+
+    let directory = package.parent().unwrap_or(Path::new("."));
+    let path = directory.join(format!("{}.cdx.json", package_file_stem(package)));
+    let contents = serde_json::to_string_pretty(&bom).into_diagnostic()?;
+    std::fs::write(&path, contents)
+        .into_diagnostic()
+        .with_context(|| format!("failed to write the bill of materials {}", path.display()))?;
+    Ok(path)
+}
+
+/// Returns the package url of a resolved package.
+fn purl(record: &RepoDataRecord) -> String {
+    let archive_type = if record.file_name.ends_with(".conda") {
+        "conda"
+    } else {
+        "tar.bz2"
+    };
+    format!(
+        "pkg:conda/{}@{}?build={}&channel={}&subdir={}&type={archive_type}",
+        record.package_record.name.as_normalized(),
+        record.package_record.version,
+        record.package_record.build,
+        record.channel,
+        record.package_record.subdir
+    )
+}
@@ -1,61 +1,226 @@
-use std::{net::SocketAddr, sync::Arc};
-
-use jsonrpc_core::{serde_json, to_value, Error, IoHandler, Params};
-use miette::{IntoDiagnostic, JSONReportHandler};
-use pixi_build_types::{
-    procedures,
-    procedures::{
-        conda_build::CondaBuildParams, conda_metadata::CondaMetadataParams,
-        initialize::InitializeParams,
-    },
+use std::{collections::HashMap, future::Future, net::SocketAddr, num::NonZeroUsize, sync::Arc};
+
+use futures::{future::Either, SinkExt, StreamExt};
+use jsonrpc_core::{
+    middleware::Middleware, serde_json, to_value, Call, Error, ErrorCode, FutureOutput,
+    FutureResponse, Id, MetaIoHandler, Metadata, Output, Params, Response, Version,
+};
+use miette::{Context, IntoDiagnostic, JSONReportHandler};
+use parking_lot::Mutex;
+use pixi_build_types::{procedures, procedures::initialize::InitializeParams};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::{RwLock, Semaphore};
+use tokio_util::{
+    codec::{FramedRead, FramedWrite, LinesCodec, LinesCodecError},
+    sync::CancellationToken,
+};
+
+use crate::{
+    conda_build::CondaBuildRequest,
+    conda_metadata::CondaMetadataRequest,
+    describe::{self, DescribeParams},
+    error::{error_code, REQUEST_CANCELLED_ERROR_CODE},
+    input_globs::{self, InputGlobsParams},
+    protocol::{Protocol, ProtocolFactory},
+    recipe_hash,
 };
-use tokio::sync::RwLock;
 
-use crate::protocol::{Protocol, ProtocolFactory};
+/// The default maximum size of a single request in bytes.
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 64 * 1024 * 1024;
+
+/// Returns the default maximum number of metadata requests that resolve their
+/// dependencies at the same time, which is the number of available CPUs.
+pub fn default_max_parallel_solves() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// The name of the method that returns the capabilities of the backend. In
+/// contrast to the other methods this can be called before `initialize`.
+pub const CAPABILITIES_METHOD_NAME: &str = "capabilities";
+
+/// The name of the notification that cancels a request that is in flight.
+pub const CANCEL_REQUEST_METHOD_NAME: &str = "$/cancelRequest";
+
+/// The name of the parameter that selects the protocol a request is for. A
+/// server can be initialized for several manifests, each identified by the
+/// value of this parameter in the `initialize` request. Requests without the
+/// parameter refer to the protocol that was initialized without it.
+pub const PROTOCOL_ID_PARAM: &str = "protocolId";
 
 /// A JSONRPC server that can be used to communicate with a client.
 pub struct Server<T: ProtocolFactory> {
     factory: T,
+    max_request_size: usize,
+    max_parallel_solves: usize,
 }
 
-enum ServerState<T: ProtocolFactory> {
-    Uninitialized(T),
-    Initialized(T::Protocol),
+struct ServerState<T: ProtocolFactory> {
+    factory: T,
+    protocols: RwLock<HashMap<String, Arc<T::Protocol>>>,
+    /// Limits the number of metadata requests that resolve dependencies at the
+    /// same time. Excess requests wait for a permit.
+    solves: Semaphore,
 }
 
 impl<T: ProtocolFactory> ServerState<T> {
-    pub fn as_protocol(&self) -> Result<&T::Protocol, jsonrpc_core::Error> {
-        match self {
-            Self::Initialized(protocol) => Ok(protocol),
-            _ => Err(Error::invalid_request()),
-        }
+    /// Returns the protocol that was initialized with the given id.
+    async fn protocol(&self, id: &str) -> Result<Arc<T::Protocol>, jsonrpc_core::Error> {
+        self.protocols
+            .read()
+            .await
+            .get(id)
+            .cloned()
+            .ok_or_else(|| not_initialized(id))
     }
 }
 
 impl<T: ProtocolFactory> Server<T> {
     pub fn new(factory: T) -> Self {
-        Self { factory }
+        Self {
+            factory,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            max_parallel_solves: default_max_parallel_solves(),
+        }
     }
 
+    /// Sets the maximum size of a single request in bytes. Larger requests
+    /// are rejected with an error response without being processed.
+    pub fn with_max_request_size(self, max_request_size: usize) -> Self {
+        Self {
+            max_request_size,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of metadata requests that resolve their
+    /// dependencies at the same time. Solving an environment can use a lot of
+    /// memory, so additional requests are queued until a running one
+    /// completes. Defaults to [`default_max_parallel_solves`].
+    pub fn with_max_parallel_solves(self, max_parallel_solves: usize) -> Self {
+        Self {
+            max_parallel_solves,
+            ..self
+        }
+    }
+
+    /// Runs the server over stdin/stdout. Every line on stdin is a request,
+    /// the responses are written to stdout, one per line. Requests are
+    /// handled concurrently, so responses are written in the order in which
+    /// the requests complete.
     pub async fn run(self) -> miette::Result<()> {
-        let io = self.setup_io();
-        jsonrpc_stdio_server::ServerBuilder::new(io).build().await;
-        Ok(())
+        let max_request_size = self.max_request_size;
+        let io = Arc::new(self.into_handler());
+
+        // The line length is limited so that an oversized request is discarded
+        // while it is read, instead of being buffered in memory completely.
+        let mut requests = FramedRead::new(
+            tokio::io::stdin(),
+            LinesCodec::new_with_max_length(max_request_size),
+        );
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let writer = tokio::spawn(async move {
+            let mut responses = FramedWrite::new(tokio::io::stdout(), LinesCodec::new());
+            while let Some(response) = receiver.recv().await {
+                responses
+                    .send(response)
+                    .await
+                    .into_diagnostic()
+                    .context("failed to write a response to stdout")?;
+            }
+            Ok::<_, miette::Report>(())
+        });
+
+        while let Some(request) = requests.next().await {
+            match request {
+                Ok(request) => {
+                    let io = io.clone();
+                    let sender = sender.clone();
+                    tokio::spawn(async move {
+                        if let Some(response) = io.handle(&request).await {
+                            let _ = sender.send(response);
+                        }
+                    });
+                }
+                Err(LinesCodecError::MaxLineLengthExceeded) => {
+                    let _ = sender.send(request_too_large(max_request_size));
+                }
+                Err(LinesCodecError::Io(err)) => {
+                    return Err(err)
+                        .into_diagnostic()
+                        .context("failed to read a request from stdin");
+                }
+            }
+        }
+
+        // Wait for the requests that are still in flight to be answered.
+        drop(sender);
+        writer.await.into_diagnostic()?
     }
 
     pub fn run_over_http(self, port: u16) -> miette::Result<()> {
+        let max_request_size = self.max_request_size;
         let io = self.setup_io();
         jsonrpc_http_server::ServerBuilder::new(io)
+            .max_request_body_size(max_request_size)
             .start_http(&SocketAddr::from(([127, 0, 0, 1], port)))
             .into_diagnostic()?
             .wait();
         Ok(())
     }
 
-    fn setup_io(self) -> IoHandler {
+    /// Returns a handler that processes requests in-process, without reading
+    /// them from stdin. This allows driving a backend through the same
+    /// JSON-RPC flow that frontends use, e.g. to embed it or to test it.
+    pub fn into_handler(self) -> RequestHandler {
+        RequestHandler {
+            io: self.setup_io(),
+        }
+    }
+
+    fn setup_io(
+        self,
+    ) -> MetaIoHandler<RequestMeta, (UnknownMethodMiddleware, CancellationMiddleware)> {
         // Construct a server
-        let mut io = IoHandler::new();
-        let state = Arc::new(RwLock::new(ServerState::Uninitialized(self.factory)));
+        let cancellation = CancellationMiddleware::default();
+        let in_flight = cancellation.in_flight.clone();
+        let mut io = MetaIoHandler::with_middleware((
+            UnknownMethodMiddleware {
+                supported_methods: vec![
+                    CAPABILITIES_METHOD_NAME,
+                    CANCEL_REQUEST_METHOD_NAME,
+                    procedures::initialize::METHOD_NAME,
+                    procedures::conda_metadata::METHOD_NAME,
+                    procedures::conda_build::METHOD_NAME,
+                    describe::METHOD_NAME,
+                    input_globs::METHOD_NAME,
+                    recipe_hash::METHOD_NAME,
+                ],
+            },
+            cancellation,
+        ));
+        let capabilities =
+            to_value(self.factory.capabilities()).expect("failed to convert to json");
+        let state = Arc::new(ServerState {
+            factory: self.factory,
+            protocols: RwLock::default(),
+            solves: Semaphore::new(self.max_parallel_solves.max(1)),
+        });
+
+        io.add_method(CAPABILITIES_METHOD_NAME, move |_params: Params| {
+            let capabilities = capabilities.clone();
+            async move { Ok(capabilities) }
+        });
+
+        io.add_notification(CANCEL_REQUEST_METHOD_NAME, move |params: Params| {
+            let Ok(CancelParams { id }) = params.parse() else {
+                tracing::debug!("received an invalid request to cancel a request");
+                return;
+            };
+            if let Some(cancellation) = in_flight.lock().remove(&request_key(&id)) {
+                tracing::debug!("cancelling the request {id:?}");
+                cancellation.cancel();
+            }
+        });
 
         let initialize_state = state.clone();
         io.add_method(
@@ -64,15 +229,23 @@ impl<T: ProtocolFactory> Server<T> {
                 let state = initialize_state.clone();
 
                 async move {
-                    let params: InitializeParams = params.parse()?;
-                    let mut state = state.write().await;
-                    let ServerState::Uninitialized(factory) = &mut *state else {
-                        return Err(Error::invalid_request());
-                    };
+                    let (id, params): (_, InitializeParams) = parse_params(params)?;
+                    if state.protocols.read().await.contains_key(&id) {
+                        return Err(already_initialized(&id));
+                    }
 
-                    let (protocol, result) =
-                        factory.initialize(params).await.map_err(convert_error)?;
-                    *state = ServerState::Initialized(protocol);
+                    let (protocol, result) = state
+                        .factory
+                        .initialize(params)
+                        .await
+                        .map_err(convert_error)?;
+
+                    // Another request may have initialized the same id in the meantime.
+                    let mut protocols = state.protocols.write().await;
+                    if protocols.contains_key(&id) {
+                        return Err(already_initialized(&id));
+                    }
+                    protocols.insert(id, Arc::new(protocol));
 
                     Ok(to_value(result).expect("failed to convert to json"))
                 }
@@ -80,17 +253,28 @@ impl<T: ProtocolFactory> Server<T> {
         );
 
         let conda_get_metadata = state.clone();
-        io.add_method(
+        io.add_method_with_meta(
             procedures::conda_metadata::METHOD_NAME,
-            move |params: Params| {
+            move |params: Params, meta: RequestMeta| {
                 let state = conda_get_metadata.clone();
 
                 async move {
-                    let params: CondaMetadataParams = params.parse()?;
-                    let state = state.read().await;
-                    state
-                        .as_protocol()?
-                        .get_conda_metadata(params)
+                    let (id, request): (_, CondaMetadataRequest) = parse_params(params)?;
+                    let protocol = state.protocol(&id).await?;
+                    // Requests that skip the solve do not need to wait for a permit.
+                    let _permit = if request.no_deps_metadata {
+                        None
+                    } else {
+                        Some(
+                            state
+                                .solves
+                                .acquire()
+                                .await
+                                .expect("the semaphore is never closed"),
+                        )
+                    };
+                    protocol
+                        .get_conda_metadata(request, meta.cancellation)
                         .await
                         .map(|value| to_value(value).expect("failed to convert to json"))
                         .map_err(convert_error)
@@ -99,17 +283,17 @@ impl<T: ProtocolFactory> Server<T> {
         );
 
         let conda_build = state.clone();
-        io.add_method(
+        io.add_method_with_meta(
             procedures::conda_build::METHOD_NAME,
-            move |params: Params| {
+            move |params: Params, meta: RequestMeta| {
                 let state = conda_build.clone();
 
                 async move {
-                    let params: CondaBuildParams = params.parse()?;
-                    let state = state.read().await;
+                    let (id, request): (_, CondaBuildRequest) = parse_params(params)?;
                     state
-                        .as_protocol()?
-                        .build_conda(params)
+                        .protocol(&id)
+                        .await?
+                        .build_conda(request, meta.cancellation)
                         .await
                         .map(|value| to_value(value).expect("failed to convert to json"))
                         .map_err(convert_error)
@@ -117,10 +301,274 @@ impl<T: ProtocolFactory> Server<T> {
             },
         );
 
+        let describe = state.clone();
+        io.add_method(describe::METHOD_NAME, move |params: Params| {
+            let state = describe.clone();
+
+            async move {
+                let (id, params): (_, DescribeParams) = parse_params(params)?;
+                state
+                    .protocol(&id)
+                    .await?
+                    .describe(params)
+                    .await
+                    .map(|value| to_value(value).expect("failed to convert to json"))
+                    .map_err(convert_error)
+            }
+        });
+
+        let recipe_hash = state.clone();
+        io.add_method(recipe_hash::METHOD_NAME, move |params: Params| {
+            let state = recipe_hash.clone();
+
+            async move {
+                let (id, params): (_, DescribeParams) = parse_params(params)?;
+                state
+                    .protocol(&id)
+                    .await?
+                    .recipe_hash(params)
+                    .await
+                    .map(|value| to_value(value).expect("failed to convert to json"))
+                    .map_err(convert_error)
+            }
+        });
+
+        let input_globs = state.clone();
+        io.add_method(input_globs::METHOD_NAME, move |params: Params| {
+            let state = input_globs.clone();
+
+            async move {
+                let (id, params): (_, InputGlobsParams) = parse_params(params)?;
+                state
+                    .protocol(&id)
+                    .await?
+                    .input_globs(params)
+                    .await
+                    .map(|value| to_value(value).expect("failed to convert to json"))
+                    .map_err(convert_error)
+            }
+        });
+
         io
     }
 }
 
+/// Processes JSON-RPC requests of a [`Server`] in-process, see
+/// [`Server::into_handler`].
+pub struct RequestHandler {
+    io: MetaIoHandler<RequestMeta, (UnknownMethodMiddleware, CancellationMiddleware)>,
+}
+
+impl RequestHandler {
+    /// Handles a serialized JSON-RPC request or batch of requests and
+    /// returns the serialized response. Returns `None` for notifications,
+    /// which have no response.
+    pub async fn handle(&self, request: &str) -> Option<String> {
+        self.io
+            .handle_request(request, RequestMeta::default())
+            .await
+    }
+}
+
+/// A middleware that reports calls to methods that the server does not
+/// support. This usually indicates that the frontend and backend use a
+/// different version of the protocol.
+struct UnknownMethodMiddleware {
+    supported_methods: Vec<&'static str>,
+}
+
+impl<M: Metadata> Middleware<M> for UnknownMethodMiddleware {
+    type Future = FutureResponse;
+    type CallFuture = FutureOutput;
+
+    fn on_call<F, X>(&self, call: Call, meta: M, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, M) -> X + Send + Sync,
+        X: Future<Output = Option<Output>> + Send + 'static,
+    {
+        let (method, response) = match &call {
+            Call::MethodCall(call) => (&call.method, Some((call.id.clone(), call.jsonrpc))),
+            Call::Notification(notification) => (&notification.method, None),
+            Call::Invalid { .. } => return Either::Right(next(call, meta)),
+        };
+        if self.supported_methods.contains(&method.as_str()) {
+            return Either::Right(next(call, meta));
+        }
+
+        tracing::debug!("received a call to the unknown method '{method}'");
+        let output = response.map(|(id, jsonrpc)| {
+            let error = Error {
+                code: ErrorCode::MethodNotFound,
+                message: format!("the method '{method}' is not supported by this backend"),
+                data: Some(serde_json::json!({
+                    "supported_methods": self.supported_methods,
+                })),
+            };
+            Output::from(Err(error), id, jsonrpc)
+        });
+        Either::Left(Box::pin(futures::future::ready(output)))
+    }
+}
+
+/// Splits the parameters of a request into the id of the protocol the request
+/// is for (see [`PROTOCOL_ID_PARAM`]) and the parameters of the method.
+fn parse_params<P: DeserializeOwned>(params: Params) -> Result<(String, P), Error> {
+    let mut value: serde_json::Value = params.parse()?;
+    let id = match value
+        .as_object_mut()
+        .and_then(|object| object.remove(PROTOCOL_ID_PARAM))
+    {
+        None => String::new(),
+        Some(serde_json::Value::String(id)) => id,
+        Some(_) => {
+            return Err(Error::invalid_params(format!(
+                "`{PROTOCOL_ID_PARAM}` must be a string"
+            )))
+        }
+    };
+    let params = serde_json::from_value(value)
+        .map_err(|err| Error::invalid_params(format!("invalid parameters: {err}")))?;
+    Ok((id, params))
+}
+
+fn not_initialized(id: &str) -> Error {
+    let message = if id.is_empty() {
+        "the backend has not been initialized".to_string()
+    } else {
+        format!("the backend has not been initialized for the protocol '{id}'")
+    };
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message,
+        data: None,
+    }
+}
+
+fn already_initialized(id: &str) -> Error {
+    let message = if id.is_empty() {
+        "the backend has already been initialized".to_string()
+    } else {
+        format!("the backend has already been initialized for the protocol '{id}'")
+    };
+    Error {
+        code: ErrorCode::InvalidRequest,
+        message,
+        data: None,
+    }
+}
+
+/// Parameters of the [`CANCEL_REQUEST_METHOD_NAME`] notification.
+#[derive(Deserialize)]
+struct CancelParams {
+    /// The id of the request to cancel.
+    id: Id,
+}
+
+/// The methods that can be cancelled while they are in flight.
+const CANCELLABLE_METHODS: &[&str] = &[
+    procedures::conda_metadata::METHOD_NAME,
+    procedures::conda_build::METHOD_NAME,
+    describe::METHOD_NAME,
+];
+
+/// The metadata of a request that the [`CancellationMiddleware`] passes to
+/// the methods.
+#[derive(Clone, Default)]
+struct RequestMeta {
+    /// Cancelled when the frontend cancels the request, see
+    /// [`CANCEL_REQUEST_METHOD_NAME`].
+    cancellation: CancellationToken,
+}
+
+impl Metadata for RequestMeta {}
+
+/// A middleware that keeps track of the requests that are in flight, so that
+/// they can be cancelled with the [`CANCEL_REQUEST_METHOD_NAME`] notification.
+///
+/// Every request that can be cancelled gets its own [`CancellationToken`],
+/// which is passed to the [`Protocol`] so it can stop the processes it
+/// started. Cancelling a request also drops the future that handles it, so
+/// everything that the request owns, such as temporary directories, is
+/// cleaned up even if the protocol does not check the token.
+#[derive(Default)]
+struct CancellationMiddleware {
+    in_flight: Arc<Mutex<HashMap<String, CancellationToken>>>,
+}
+
+impl Middleware<RequestMeta> for CancellationMiddleware {
+    type Future = FutureResponse;
+    type CallFuture = FutureOutput;
+
+    fn on_call<F, X>(&self, call: Call, meta: RequestMeta, next: F) -> Either<Self::CallFuture, X>
+    where
+        F: Fn(Call, RequestMeta) -> X + Send + Sync,
+        X: Future<Output = Option<Output>> + Send + 'static,
+    {
+        let Call::MethodCall(method_call) = &call else {
+            return Either::Right(next(call, meta));
+        };
+        if !CANCELLABLE_METHODS.contains(&method_call.method.as_str()) {
+            return Either::Right(next(call, meta));
+        }
+
+        let id = method_call.id.clone();
+        let jsonrpc = method_call.jsonrpc;
+        let key = request_key(&id);
+        let cancellation = CancellationToken::new();
+        self.in_flight
+            .lock()
+            .insert(key.clone(), cancellation.clone());
+        let future = next(
+            call,
+            RequestMeta {
+                cancellation: cancellation.clone(),
+            },
+        );
+
+        let in_flight = self.in_flight.clone();
+        Either::Left(Box::pin(async move {
+            let output = tokio::select! {
+                biased;
+                () = cancellation.cancelled() => None,
+                output = future => Some(output),
+            };
+            in_flight.lock().remove(&key);
+            match output {
+                Some(output) => output,
+                None => {
+                    let error = Error {
+                        code: ErrorCode::ServerError(REQUEST_CANCELLED_ERROR_CODE),
+                        message: "the request was cancelled".to_string(),
+                        data: None,
+                    };
+                    Some(Output::from(Err(error), id, jsonrpc))
+                }
+            }
+        }))
+    }
+}
+
+/// Returns the key of a request id in the map of requests that are in flight.
+fn request_key(id: &Id) -> String {
+    serde_json::to_string(id).expect("failed to convert to json")
+}
+
+/// Returns the serialized error response for a request that exceeds the
+/// maximum request size.
+fn request_too_large(max_request_size: usize) -> String {
+    let error = Error {
+        code: ErrorCode::InvalidRequest,
+        message: format!(
+            "the request exceeds the maximum request size of {max_request_size} bytes"
+        ),
+        data: None,
+    };
+    serde_json::to_string(&Response::from(error, Some(Version::V2)))
+        .expect("failed to convert to json")
+}
+
+/// Converts an error of a backend to a JSON-RPC error. The code identifies the
+/// kind of the error, see [`error_code`] for the possible values.
 fn convert_error(err: miette::Report) -> jsonrpc_core::Error {
     let rendered = JSONReportHandler::new();
     let mut json_str = String::new();
@@ -129,7 +577,7 @@ fn convert_error(err: miette::Report) -> jsonrpc_core::Error {
         .expect("failed to convert error to json");
     let data = serde_json::from_str(&json_str).expect("failed to parse json error");
     jsonrpc_core::Error {
-        code: jsonrpc_core::ErrorCode::ServerError(-32000),
+        code: jsonrpc_core::ErrorCode::ServerError(error_code(&err)),
         message: err.to_string(),
         data: Some(data),
     }
@@ -0,0 +1,304 @@
+//! The git submodules of the sources of a package.
+//!
+//! The backends build the sources in the manifest directory, which is usually
+//! a git checkout. A checkout does not contain the submodules unless it was
+//! cloned with `--recurse-submodules`, so projects that vendor code as
+//! submodules enable `recurse-submodules`. The sources are then not built
+//! from the checkout itself, but from a git source of the recipe:
+//! rattler-build clones the repository of the checkout at its current commit
+//! into the work directory and checks out the submodules recursively at the
+//! revisions that are recorded in that commit. The checkout itself is never
+//! modified, which also means that uncommitted changes are not built.
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::recipe::parser::{GitRev, GitSource, GitUrl, Source};
+use rattler_conda_types::Platform;
+
+/// The sources of a package as a git source with submodules, see
+/// [`git_source`].
+#[derive(Debug, Clone)]
+pub struct SubmoduleSource {
+    /// The git source that clones the repository at its current commit.
+    pub source: Source,
+
+    /// The directory of the sources relative to the root of the repository.
+    pub subdirectory: PathBuf,
+}
+
+impl SubmoduleSource {
+    /// Returns the directory of the sources in the work directory of a build
+    /// on `build_platform`, as it is referenced from the build script.
+    pub fn source_dir(&self, build_platform: Platform) -> String {
+        let (src_dir, separator) = if build_platform.is_windows() {
+            ("%SRC_DIR%", "\\")
+        } else {
+            ("$SRC_DIR", "/")
+        };
+        std::iter::once(src_dir.to_string())
+            .chain(
+                self.subdirectory
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+}
+
+/// Returns a git source that clones the repository that contains `directory`
+/// at its current commit, including its submodules.
+///
+/// Fails if `directory` is not part of a git repository, if the repository
+/// has no commit, or if a submodule that is checked out in the repository is
+/// recorded at a revision that does not exist in its repository.
+pub fn git_source(directory: &Path) -> miette::Result<SubmoduleSource> {
+    let output = git(directory, &["rev-parse", "--show-toplevel"])?;
+    if !output.status.success() {
+        miette::bail!(
+            help = "`recurse-submodules` builds the sources from a clone of their git repository, disable it to build sources that are not part of a git repository",
+            "the sources in {} are not part of a git repository",
+            directory.display()
+        );
+    }
+    let root = PathBuf::from(stdout(&output));
+
+    let output = git(
+        &root,
+        &["rev-parse", "--verify", "--quiet", "HEAD^{commit}"],
+    )?;
+    if !output.status.success() {
+        miette::bail!(
+            help = "commit the sources, `recurse-submodules` builds the current commit of the repository",
+            "the git repository {} has no commit",
+            root.display()
+        );
+    }
+    let commit = stdout(&output);
+
+    let output = git(&root, &["ls-tree", "-r", "-z", &commit])?;
+    if !output.status.success() {
+        miette::bail!(
+            "failed to list the git submodules of {}: {}",
+            root.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    for (path, revision) in submodules(&String::from_utf8_lossy(&output.stdout)) {
+        check_revision(&root, path, revision)?;
+    }
+
+    let subdirectory = canonicalize(directory)?
+        .strip_prefix(canonicalize(&root)?)
+        .map(Path::to_path_buf)
+        .into_diagnostic()
+        .with_context(|| {
+            format!(
+                "{} is not inside of the git repository {}",
+                directory.display(),
+                root.display()
+            )
+        })?;
+
+    Ok(SubmoduleSource {
+        source: Source::Git(GitSource {
+            url: GitUrl::Path(root),
+            rev: GitRev::Commit(commit),
+            depth: None,
+            patches: vec![],
+            target_directory: None,
+            lfs: false,
+        }),
+        subdirectory,
+    })
+}
+
+/// Logs a warning if the git repository that contains `directory` has
+/// uncommitted changes, which are not part of a build with submodules.
+pub fn warn_uncommitted_changes(directory: &Path) -> miette::Result<()> {
+    let output = git(directory, &["status", "--porcelain"])?;
+    if output.status.success() && !output.stdout.is_empty() {
+        tracing::warn!(
+            "the git repository of {} has uncommitted changes, which are not built because `recurse-submodules` builds the current commit",
+            directory.display()
+        );
+    }
+    Ok(())
+}
+
+/// Fails if the submodule at `path` in the repository `root` is checked out,
+/// but does not contain its recorded `revision`. Submodules that are not
+/// checked out are fetched during the build.
+fn check_revision(root: &Path, path: &str, revision: &str) -> miette::Result<()> {
+    let submodule = root.join(path);
+    if !submodule.join(".git").exists() {
+        return Ok(());
+    }
+    let output = git(
+        &submodule,
+        &["cat-file", "-e", &format!("{revision}^{{commit}}")],
+    )?;
+    if !output.status.success() {
+        miette::bail!(
+            help = "fetch or push the revision to the repository of the submodule, or update the submodule to a revision that exists",
+            "the submodule '{path}' of {} is recorded at revision {revision}, which does not exist in its repository",
+            root.display()
+        );
+    }
+    Ok(())
+}
+
+/// Runs `git` with `args` in `directory` and returns its output.
+fn git(directory: &Path, args: &[&str]) -> miette::Result<Output> {
+    Command::new("git")
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .into_diagnostic()
+        .with_context(|| format!("failed to run `git {}`, is git installed?", args.join(" ")))
+}
+
+/// Returns the trimmed standard output of `output`.
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// Canonicalizes `path`, with a readable error.
+fn canonicalize(path: &Path) -> miette::Result<PathBuf> {
+    path.canonicalize()
+        .into_diagnostic()
+        .with_context(|| format!("failed to resolve {}", path.display()))
+}
+
+/// Returns the path and the recorded revision of every submodule in the
+/// output of `git ls-tree -r -z`.
+fn submodules(tree: &str) -> impl Iterator<Item = (&str, &str)> {
+    tree.split('\0').filter_map(|entry| {
+        let (info, path) = entry.split_once('\t')?;
+        let mut fields = info.split_whitespace();
+        let _mode = fields.next()?;
+        let kind = fields.next()?;
+        let revision = fields.next()?;
+        (kind == "commit").then_some((path, revision))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `git` with `args` in `directory` and asserts that it succeeds.
+    fn run_git(directory: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=pixi", "-c", "user.email=pixi@example.com"])
+            .args(["-c", "init.defaultBranch=main"])
+            // Submodules of the tests are cloned from local paths, which git
+            // only allows explicitly.
+            .args(["-c", "protocol.file.allow=always"])
+            .args(args)
+            .current_dir(directory)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?}: {output:?}");
+        stdout(&output)
+    }
+
+    /// Creates a repository `project` in `directory` with the repository
+    /// `lib` as submodule, and returns a clone of `project` without the
+    /// submodules.
+    fn clone_without_submodules(directory: &Path) -> PathBuf {
+        let lib = directory.join("lib");
+        let project = directory.join("project");
+        for repository in [&lib, &project] {
+            std::fs::create_dir(repository).unwrap();
+            run_git(repository, &["init", "-q"]);
+        }
+        std::fs::write(lib.join("lib.h"), "").unwrap();
+        run_git(&lib, &["add", "lib.h"]);
+        run_git(&lib, &["commit", "-qm", "lib"]);
+        run_git(
+            &project,
+            &["submodule", "add", "-q", lib.to_str().unwrap(), "lib"],
+        );
+        std::fs::create_dir(project.join("python")).unwrap();
+        std::fs::write(project.join("python/pyproject.toml"), "").unwrap();
+        run_git(&project, &["add", "python"]);
+        run_git(&project, &["commit", "-qm", "project"]);
+
+        run_git(directory, &["clone", "-q", "project", "checkout"]);
+        directory.join("checkout")
+    }
+
+    #[test]
+    fn submodules_are_cloned_at_the_current_commit() {
+        let directory = tempfile::tempdir().unwrap();
+        let checkout = clone_without_submodules(directory.path());
+        let commit = run_git(&checkout, &["rev-parse", "HEAD"]);
+
+        let source = git_source(&checkout.join("python")).unwrap();
+        let Source::Git(git) = &source.source else {
+            panic!("expected a git source, got {:?}", source.source);
+        };
+        assert!(
+            matches!(&git.url, GitUrl::Path(path) if canonicalize(path).unwrap() == canonicalize(&checkout).unwrap())
+        );
+        assert_eq!(git.rev, GitRev::Commit(commit));
+        assert_eq!(source.subdirectory, Path::new("python"));
+        assert_eq!(source.source_dir(Platform::Linux64), "$SRC_DIR/python");
+        assert_eq!(source.source_dir(Platform::Win64), "%SRC_DIR%\\python");
+
+        // The checkout itself is left as it is.
+        assert!(!checkout.join("lib/lib.h").exists());
+    }
+
+    #[test]
+    fn a_missing_revision_is_reported() {
+        let directory = tempfile::tempdir().unwrap();
+        let project = clone_without_submodules(directory.path())
+            .parent()
+            .unwrap()
+            .join("project");
+        run_git(
+            &project,
+            &[
+                "update-index",
+                "--cacheinfo",
+                "160000,8061e53ff0cc5dd1ccd8661e416dfa7567cf1361,lib",
+            ],
+        );
+        run_git(&project, &["commit", "-qm", "missing"]);
+
+        let err = git_source(&project).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("the submodule 'lib'"), "{message}");
+        assert!(
+            message.contains("8061e53ff0cc5dd1ccd8661e416dfa7567cf1361"),
+            "{message}"
+        );
+        assert!(message.contains("does not exist"), "{message}");
+    }
+
+    #[test]
+    fn sources_outside_of_a_repository_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let err = git_source(directory.path()).unwrap_err();
+        assert!(
+            err.to_string().contains("not part of a git repository"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn submodules_are_listed_with_their_revision() {
+        let tree = "100644 blob 8987988000be97d971382fdbe3095b2cc152ad63\tpixi.toml\0\
+160000 commit 8061e53ff0cc5dd1ccd8661e416dfa7567cf1361\tvendor/lib\0";
+        assert_eq!(
+            submodules(tree).collect::<Vec<_>>(),
+            [("vendor/lib", "8061e53ff0cc5dd1ccd8661e416dfa7567cf1361")]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::path::{Path, PathBuf};
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::recipe::parser::{Script, ScriptContent};
+use rattler_conda_types::Platform;
+
+/// Writes the rendered build `script` to `work_directory` so that it can be
+/// inspected, and returns the path of the file.
+///
+/// The environment variables of the script are written at the top of the
+/// file. The values of secrets are never written, only their names.
+pub fn write_build_script(
+    script: &Script,
+    platform: Platform,
+    work_directory: &Path,
+) -> miette::Result<PathBuf> {
+    let (file_name, comment) = if platform.is_windows() {
+        ("build_script.bat", "REM")
+    } else {
+        ("build_script.sh", "#")
+    };
+
+    let mut lines = Vec::new();
+    for (name, value) in &script.env {
+        if platform.is_windows() {
+            lines.push(format!("set \"{name}={value}\""));
+        } else {
+            lines.push(format!("export {name}='{}'", value.replace('\'', r"'\''")));
+        }
+    }
+    for name in &script.secrets {
+        lines.push(format!(
+            "{comment} {name} is passed through from the environment (redacted)"
+        ));
+    }
+    match &script.content {
+        ScriptContent::Commands(commands) => lines.extend(commands.iter().cloned()),
+        _ => miette::bail!("only build scripts that consist of commands can be written"),
+    }
+
+    std::fs::create_dir_all(work_directory).into_diagnostic()?;
+    let path = work_directory.join(file_name);
+    std::fs::write(&path, lines.join("\n") + "\n")
+        .into_diagnostic()
+        .with_context(|| format!("failed to write the build script to {}", path.display()))?;
+    Ok(path)
+}
@@ -1,3 +1,5 @@
+mod build_script;
 mod temporary_recipe;
 
+pub use build_script::write_build_script;
 pub use temporary_recipe::TemporaryRenderedRecipe;
@@ -0,0 +1,676 @@
+use std::collections::BTreeMap;
+
+use miette::{Context, IntoDiagnostic};
+use rattler_build::{
+    hash::HashInfo,
+    metadata::Output,
+    recipe::parser::{BuildString, Dependency, Requirements, VariantKeyUsage},
+};
+use rattler_conda_types::{NoArchType, PackageRecord, ParseStrictness, VersionSpec};
+
+/// Variant keys that describe the platform the package is built on or for.
+const PLATFORM_KEYS: &[&str] = &["build_platform", "host_platform", "target_platform"];
+
+/// Computes the hash that is used in the build string of a package.
+///
+/// The hash only depends on the variant and the noarch type. Every key and
+/// value of the variant contributes to the hash, so different variant
+/// combinations result in different build strings. Because the variant is an
+/// ordered map, the same combination always results in the same build string
+/// regardless of the order in which the keys were inserted.
+///
+/// For noarch packages any platform related variant keys are ignored, so the
+/// same noarch package built on different machines ends up with the same
+/// build string.
+pub fn hash_info(variant: &BTreeMap<String, String>, noarch: &NoArchType) -> HashInfo {
+    if noarch.is_none() {
+        return HashInfo::from_variant(variant, noarch);
+    }
+
+    let variant = variant
+        .iter()
+        .filter(|(key, _)| !PLATFORM_KEYS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    HashInfo::from_variant(&variant, noarch)
+}
+
+/// Returns whether the variant `key` refers to the package `name`. Variant
+/// keys conventionally use underscores where package names use dashes.
+fn is_variant_of(key: &str, name: &str) -> bool {
+    key == name || key.replace('_', "-") == name
+}
+
+/// Pins the build and host dependencies that do not specify a version to the
+/// value of the variant key with the same name, like conda-build does. A
+/// plain version such as `3.11` is pinned as `3.11.*`.
+pub fn apply_variant(
+    requirements: &mut Requirements,
+    variant: &BTreeMap<String, String>,
+) -> miette::Result<()> {
+    for dependency in requirements
+        .build
+        .iter_mut()
+        .chain(requirements.host.iter_mut())
+    {
+        let Dependency::Spec(spec) = dependency else {
+            continue;
+        };
+        if spec.version.is_some() || spec.build.is_some() {
+            continue;
+        }
+        let Some(name) = spec.name.as_ref().map(|name| name.as_normalized()) else {
+            continue;
+        };
+        let Some(value) = variant
+            .iter()
+            .find(|(key, _)| is_variant_of(key, name))
+            .map(|(_, value)| value)
+        else {
+            continue;
+        };
+
+        let version = if value.contains(['<', '>', '=', '!', '*', ',', '|', ' ']) {
+            value.clone()
+        } else {
+            format!("{value}.*")
+        };
+        spec.version = Some(
+            VersionSpec::from_str(&version, ParseStrictness::Lenient)
+                .into_diagnostic()
+                .with_context(|| {
+                    format!("invalid version '{value}' for the variant key of {name}")
+                })?,
+        );
+    }
+    Ok(())
+}
+
+/// Returns the part of `variant` that is used by the build or host
+/// requirements, together with the keys in `usage.use_keys` and without the
+/// keys in `usage.ignore_keys`. Only these keys end up in the variant of the
+/// build and therefore in the build string. Ignored keys are still applied to
+/// the dependencies by [`apply_variant`].
+pub fn used_variant(
+    variant: &BTreeMap<String, String>,
+    requirements: &Requirements,
+    usage: &VariantKeyUsage,
+) -> BTreeMap<String, String> {
+    let names = requirements
+        .build
+        .iter()
+        .chain(requirements.host.iter())
+        .filter_map(|dependency| match dependency {
+            Dependency::Spec(spec) => spec.name.as_ref(),
+            _ => None,
+        })
+        .map(|name| name.as_normalized())
+        .collect::<Vec<_>>();
+    variant
+        .iter()
+        .filter(|(key, _)| {
+            usage.use_keys.contains(key) || names.iter().any(|name| is_variant_of(key, name))
+        })
+        .filter(|(key, _)| !usage.ignore_keys.contains(key))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Adds the `major.minor` version of python in the resolved host environment
+/// of `output` to its variant and updates the hash accordingly, so the build
+/// string of a package that is built for a specific python version carries a
+/// `pyXY` prefix, e.g. `py311h1234567_0`.
+///
+/// Does nothing for noarch packages, if the variant already specifies python,
+/// if `python` is an ignored variant key, or if the host environment does not
+/// contain python.
+pub fn add_python_variant(output: &mut Output) {
+    if !output.recipe.build.noarch.is_none()
+        || output.build_configuration.variant.contains_key("python")
+        || output
+            .recipe
+            .build
+            .variant
+            .ignore_keys
+            .iter()
+            .any(|key| key == "python")
+    {
+        return;
+    }
+    let Some(python) = output
+        .finalized_dependencies
+        .as_ref()
+        .and_then(|dependencies| dependencies.host.as_ref())
+        .and_then(|host| python_variant(host.resolved.iter().map(|record| &record.package_record)))
+    else {
+        return;
+    };
+
+    let build_configuration = &mut output.build_configuration;
+    build_configuration
+        .variant
+        .insert("python".to_string(), python);
+    build_configuration.hash = hash_info(&build_configuration.variant, &output.recipe.build.noarch);
+}
+
+/// Returns the `major.minor` version of python among `records`, which is the
+/// value of the `python` variant key, e.g. `3.11`.
+fn python_variant<'a>(records: impl IntoIterator<Item = &'a PackageRecord>) -> Option<String> {
+    records
+        .into_iter()
+        .find(|record| record.name.as_normalized() == "python")
+        .and_then(|python| python.version.as_major_minor())
+        .map(|(major, minor)| format!("{major}.{minor}"))
+}
+
+/// A part of a build string template, see [`apply_build_string`].
+enum TemplatePart<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Splits a build string template such as `py{python}h{hash}_{build_number}`
+/// into literals and placeholders.
+fn parse_build_string(template: &str) -> miette::Result<Vec<TemplatePart<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if rest[..start].contains('}') {
+            miette::bail!("unmatched `}}` in the build string '{template}'");
+        }
+        if start > 0 {
+            parts.push(TemplatePart::Literal(&rest[..start]));
+        }
+        let Some(length) = rest[start..].find('}') else {
+            miette::bail!("unclosed `{{` in the build string '{template}'");
+        };
+        let key = rest[start + 1..start + length].trim();
+        if key.is_empty() || key.contains('{') {
+            miette::bail!("invalid placeholder in the build string '{template}'");
+        }
+        parts.push(TemplatePart::Placeholder(key));
+        rest = &rest[start + length + 1..];
+    }
+    if rest.contains('}') {
+        miette::bail!("unmatched `}}` in the build string '{template}'");
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest));
+    }
+    Ok(parts)
+}
+
+/// Checks the syntax of a build string template. Whether the placeholders
+/// refer to variant keys can only be checked once the variant of a build is
+/// known, see [`apply_build_string`].
+pub fn validate_build_string(template: &str) -> miette::Result<()> {
+    parse_build_string(template).map(|_| ())
+}
+
+/// Renders the build string `template` for `output` and uses it as the build
+/// string of the package, instead of the default `h<hash>_<build number>`.
+///
+/// A placeholder in braces is replaced by its value: `{hash}` by the hash of
+/// the variant, `{build_number}` by the build number and any other key by the
+/// value of that variant key with the dots removed, so `py{python}` becomes
+/// e.g. `py311`. Returns an error if a placeholder is not a key of the
+/// variant of `output`.
+///
+/// This has to be called after the variant of `output` is final, e.g. after
+/// [`add_python_variant`].
+pub fn apply_build_string(output: &mut Output, template: Option<&str>) -> miette::Result<()> {
+    let Some(template) = template else {
+        return Ok(());
+    };
+    match render_build_string(output, template)? {
+        Ok(build_string) => {
+            output.recipe.build.string = BuildString::Resolved(build_string);
+            Ok(())
+        }
+        Err(key) => {
+            let available = ["hash", "build_number"]
+                .into_iter()
+                .chain(
+                    output
+                        .build_configuration
+                        .variant
+                        .keys()
+                        .map(String::as_str),
+                )
+                .map(|key| format!("`{key}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(miette::miette!(
+                help = format!("the available keys are {available}"),
+                "the build string '{template}' references `{key}`, which is not a key of the variant of the package"
+            ))
+        }
+    }
+}
+
+/// Like [`apply_build_string`], but for an `output` of which the variant may
+/// not be final yet, e.g. because the `python` key is only added once the
+/// host environment is resolved. If `template` references a key that the
+/// variant does not contain yet, the build string is left unchanged. This is
+/// the build string that is reported if the dependencies are never resolved,
+/// e.g. for `noDepsMetadata` requests.
+pub fn apply_partial_build_string(
+    output: &mut Output,
+    template: Option<&str>,
+) -> miette::Result<()> {
+    let Some(template) = template else {
+        return Ok(());
+    };
+    match render_build_string(output, template)? {
+        Ok(build_string) => output.recipe.build.string = BuildString::Resolved(build_string),
+        Err(key) => tracing::debug!(
+            "the build string '{template}' is applied once the variant key `{key}` is known"
+        ),
+    }
+    Ok(())
+}
+
+/// Renders the build string `template` for `output`, see
+/// [`apply_build_string`]. Returns the first placeholder that is not a key
+/// of the variant of `output` instead, if any.
+fn render_build_string<'a>(
+    output: &Output,
+    template: &'a str,
+) -> miette::Result<Result<String, &'a str>> {
+    let build_configuration = &output.build_configuration;
+    let mut build_string = String::new();
+    for part in parse_build_string(template)? {
+        match part {
+            TemplatePart::Literal(literal) => build_string.push_str(literal),
+            TemplatePart::Placeholder("hash") => {
+                build_string.push_str(&build_configuration.hash.hash)
+            }
+            TemplatePart::Placeholder("build_number") => {
+                build_string.push_str(&output.recipe.build.number.to_string())
+            }
+            TemplatePart::Placeholder(key) => match build_configuration.variant.get(key) {
+                Some(value) => build_string.push_str(&value.replace('.', "")),
+                None => return Ok(Err(key)),
+            },
+        }
+    }
+    Ok(Ok(build_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, str::FromStr};
+
+    use rattler_build::{
+        metadata::{
+            BuildConfiguration, Directories, PackagingSettings, PlatformWithVirtualPackages,
+        },
+        recipe::{
+            parser::{Build, Package},
+            Recipe,
+        },
+        render::resolved_dependencies::{
+            FinalizedDependencies, FinalizedRunDependencies, ResolvedDependencies,
+        },
+    };
+    use rattler_conda_types::{
+        package::ArchiveType, MatchSpec, PackageName, Platform, RepoDataRecord, Version,
+    };
+    use rattler_package_streaming::write::CompressionLevel;
+    use reqwest::Url;
+
+    use super::*;
+    use crate::backend::unresolved_output;
+
+    /// Returns a variant with the given keys and values.
+    fn variant(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn noarch_hashes_ignore_the_platform() {
+        let linux = variant(&[("target_platform", "linux-64"), ("python", "3.12")]);
+        let windows = variant(&[("target_platform", "win-64"), ("python", "3.12")]);
+
+        let noarch = NoArchType::python();
+        assert_eq!(
+            hash_info(&linux, &noarch).hash,
+            hash_info(&windows, &noarch).hash
+        );
+
+        let none = NoArchType::none();
+        assert_ne!(
+            hash_info(&linux, &none).hash,
+            hash_info(&windows, &none).hash
+        );
+    }
+
+    /// Returns requirements with the given host dependencies.
+    fn host_requirements(names: &[&str]) -> Requirements {
+        Requirements {
+            host: names
+                .iter()
+                .map(|name| {
+                    Dependency::Spec(MatchSpec::from_str(name, ParseStrictness::Strict).unwrap())
+                })
+                .collect(),
+            ..Requirements::default()
+        }
+    }
+
+    #[test]
+    fn only_variant_keys_of_dependencies_are_used() {
+        let variant = variant(&[
+            ("python", "3.12"),
+            ("numpy", "2"),
+            ("cuda_compiler_version", "12.4"),
+        ]);
+        let requirements = host_requirements(&["python", "numpy"]);
+
+        let used = used_variant(&variant, &requirements, &VariantKeyUsage::default());
+        assert_eq!(used, self::variant(&[("numpy", "2"), ("python", "3.12")]));
+    }
+
+    #[test]
+    fn use_and_ignore_keys_override_the_dependencies() {
+        let variant = variant(&[
+            ("python", "3.12"),
+            ("numpy", "2"),
+            ("cuda_compiler_version", "12.4"),
+        ]);
+        let requirements = host_requirements(&["python", "numpy"]);
+        let usage = VariantKeyUsage {
+            use_keys: vec!["cuda_compiler_version".to_string()],
+            ignore_keys: vec!["numpy".to_string()],
+            ..VariantKeyUsage::default()
+        };
+
+        let used = used_variant(&variant, &requirements, &usage);
+        assert_eq!(
+            used,
+            self::variant(&[("cuda_compiler_version", "12.4"), ("python", "3.12")])
+        );
+    }
+
+    #[test]
+    fn variant_keys_use_underscores_for_dashes() {
+        let variant = variant(&[("libjpeg_turbo", "3")]);
+        let requirements = host_requirements(&["libjpeg-turbo"]);
+
+        let used = used_variant(&variant, &requirements, &VariantKeyUsage::default());
+        assert_eq!(used, variant);
+    }
+
+    /// Returns the record of a package with the given name and version.
+    fn record(name: &str, version: &str) -> PackageRecord {
+        PackageRecord::new(
+            PackageName::new_unchecked(name),
+            Version::from_str(version).unwrap(),
+            "0".to_string(),
+        )
+    }
+
+    #[test]
+    fn python_variant_is_the_major_and_minor_version() {
+        let records = [record("numpy", "2.1.0"), record("python", "3.11.9")];
+        assert_eq!(python_variant(&records), Some("3.11".to_string()));
+    }
+
+    #[test]
+    fn python_variant_requires_python_in_the_host_environment() {
+        let records = [record("pythonnet", "3.0.4"), record("zlib", "1.3.1")];
+        assert_eq!(python_variant(&records), None);
+    }
+
+    #[test]
+    fn different_variants_have_different_hashes() {
+        let noarch = NoArchType::none();
+        let python_311 = hash_info(&variant(&[("python", "3.11")]), &noarch);
+        let python_312 = hash_info(&variant(&[("python", "3.12")]), &noarch);
+        assert_ne!(python_311.hash, python_312.hash);
+
+        let with_numpy = hash_info(&variant(&[("python", "3.12"), ("numpy", "2")]), &noarch);
+        assert_ne!(python_312.hash, with_numpy.hash);
+    }
+
+    #[test]
+    fn the_same_variant_has_a_stable_hash() {
+        let noarch = NoArchType::none();
+        let first = hash_info(&variant(&[("python", "3.12"), ("numpy", "2")]), &noarch);
+        let second = hash_info(&variant(&[("numpy", "2"), ("python", "3.12")]), &noarch);
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(
+            first.hash,
+            hash_info(&variant(&[("python", "3.12"), ("numpy", "2")]), &noarch).hash
+        );
+    }
+
+    /// Returns the unresolved output of a package for linux-64 with the given
+    /// variant and noarch type. The build directories are in `directory`.
+    fn output(directory: &Path, variant: BTreeMap<String, String>, noarch: NoArchType) -> Output {
+        let target_platform = if noarch.is_none() {
+            Platform::Linux64
+        } else {
+            Platform::NoArch
+        };
+        let hash = hash_info(&variant, &noarch);
+        let recipe = Recipe {
+            schema_version: 1,
+            context: Default::default(),
+            package: Package {
+                name: PackageName::new_unchecked("package"),
+                version: Version::from_str("1.0.0").unwrap().into(),
+            },
+            cache: None,
+            source: Vec::new(),
+            build: Build {
+                noarch,
+                ..Build::default()
+            },
+            requirements: Requirements::default(),
+            tests: Vec::new(),
+            about: Default::default(),
+            extra: Default::default(),
+        };
+        let directories = Directories::setup(
+            "package",
+            &directory.join("pixi.toml"),
+            directory,
+            true,
+            &chrono::Utc::now(),
+        )
+        .unwrap();
+        let platform = PlatformWithVirtualPackages {
+            platform: Platform::Linux64,
+            virtual_packages: Vec::new(),
+        };
+        unresolved_output(
+            recipe,
+            BuildConfiguration {
+                target_platform,
+                host_platform: platform.clone(),
+                build_platform: platform,
+                hash,
+                variant,
+                directories,
+                channels: Vec::new(),
+                channel_priority: Default::default(),
+                solve_strategy: Default::default(),
+                timestamp: chrono::Utc::now(),
+                subpackages: Default::default(),
+                packaging_settings: PackagingSettings::from_args(
+                    ArchiveType::Conda,
+                    CompressionLevel::default(),
+                ),
+                store_recipe: false,
+                force_colors: false,
+            },
+        )
+    }
+
+    #[test]
+    fn build_string_templates_are_split_into_parts() {
+        let parts = parse_build_string("py{python}h{ hash }_{build_number}").unwrap();
+        let parts = parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(literal) => format!("literal {literal}"),
+                TemplatePart::Placeholder(key) => format!("placeholder {key}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            [
+                "literal py",
+                "placeholder python",
+                "literal h",
+                "placeholder hash",
+                "literal _",
+                "placeholder build_number",
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_braces_in_build_strings_are_rejected() {
+        let message = |template| validate_build_string(template).unwrap_err().to_string();
+        assert_eq!(
+            message("py{python_0"),
+            "unclosed `{` in the build string 'py{python_0'"
+        );
+        assert_eq!(
+            message("py}{python}"),
+            "unmatched `}` in the build string 'py}{python}'"
+        );
+        assert_eq!(
+            message("{hash}_0}"),
+            "unmatched `}` in the build string '{hash}_0}'"
+        );
+        assert_eq!(
+            message("h{}_0"),
+            "invalid placeholder in the build string 'h{}_0'"
+        );
+    }
+
+    #[test]
+    fn build_string_templates_interpolate_the_variant() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut output = output(
+            directory.path(),
+            variant(&[("python", "3.11")]),
+            NoArchType::none(),
+        );
+        let hash = output.build_configuration.hash.hash.clone();
+        assert_eq!(
+            render_build_string(&output, "py{python}h{hash}_{build_number}").unwrap(),
+            Ok(format!("py311h{hash}_0"))
+        );
+
+        apply_build_string(&mut output, Some("py{python}h{hash}_{build_number}")).unwrap();
+        assert_eq!(output.build_string(), format!("py311h{hash}_0"));
+    }
+
+    #[test]
+    fn build_strings_must_reference_variant_keys() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut output = output(
+            directory.path(),
+            variant(&[("python", "3.11")]),
+            NoArchType::none(),
+        );
+        assert_eq!(
+            render_build_string(&output, "cuda{cuda}_{build_number}").unwrap(),
+            Err("cuda")
+        );
+
+        let err = apply_build_string(&mut output, Some("cuda{cuda}_{build_number}")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the build string 'cuda{cuda}_{build_number}' references `cuda`, which is not a key of the variant of the package"
+        );
+        assert_eq!(
+            err.help().unwrap().to_string(),
+            "the available keys are `hash`, `build_number`, `python`"
+        );
+    }
+
+    #[test]
+    fn partial_build_strings_wait_for_unknown_variant_keys() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut output = output(directory.path(), BTreeMap::new(), NoArchType::none());
+        let default = output.build_string().into_owned();
+
+        apply_partial_build_string(&mut output, Some("py{python}_{build_number}")).unwrap();
+        assert_eq!(output.build_string(), default);
+    }
+
+    /// Returns the unresolved `output` with a resolved host environment that
+    /// contains `records`.
+    fn with_host_environment(mut output: Output, records: Vec<PackageRecord>) -> Output {
+        let resolved = records
+            .into_iter()
+            .map(|package_record| RepoDataRecord {
+                file_name: format!(
+                    "{}-{}-{}.conda",
+                    package_record.name.as_normalized(),
+                    package_record.version,
+                    package_record.build
+                ),
+                url: Url::parse("https://prefix.dev/conda-forge/linux-64/package.conda").unwrap(),
+                channel: "https://prefix.dev/conda-forge/".to_string(),
+                package_record,
+            })
+            .collect();
+        output.finalized_dependencies = Some(FinalizedDependencies {
+            build: None,
+            host: Some(ResolvedDependencies {
+                specs: Vec::new(),
+                resolved,
+            }),
+            run: FinalizedRunDependencies::default(),
+        });
+        output
+    }
+
+    #[test]
+    fn compiled_python_packages_get_a_python_build_string() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut output = with_host_environment(
+            output(directory.path(), BTreeMap::new(), NoArchType::none()),
+            vec![record("python", "3.11.9"), record("numpy", "2.1.0")],
+        );
+        let hash = output.build_configuration.hash.hash.clone();
+        let default = output.build_string().into_owned();
+
+        add_python_variant(&mut output);
+        assert_eq!(
+            output
+                .build_configuration
+                .variant
+                .get("python")
+                .map(String::as_str),
+            Some("3.11")
+        );
+        assert_ne!(output.build_configuration.hash.hash, hash);
+        let build_string = output.build_string().into_owned();
+        assert!(build_string.starts_with("py311"), "{build_string}");
+        assert_ne!(build_string, default);
+    }
+
+    #[test]
+    fn noarch_python_packages_keep_their_build_string() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut output = with_host_environment(
+            output(directory.path(), BTreeMap::new(), NoArchType::python()),
+            vec![record("python", "3.11.9")],
+        );
+        let default = output.build_string().into_owned();
+
+        add_python_variant(&mut output);
+        assert!(output.build_configuration.variant.is_empty());
+        assert_eq!(output.build_string(), default);
+    }
+}
@@ -0,0 +1,416 @@
+//! Drives the JSON-RPC server in-process through [`RequestHandler::handle`],
+//! from `initialize` through `conda/getMetadata` to `conda/build`, for the
+//! fixture manifests in `tests/fixtures`.
+//!
+//! The protocol of these tests reads the fixture manifests but does not
+//! resolve or build anything, so the tests exercise the registration of the
+//! methods, the parsing of the parameters and the conversion of errors
+//! without network access.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use jsonrpc_core::serde_json::{self, json, Value};
+use pixi_build_backend::{
+    conda_build::{CondaBuildRequest, CondaBuildResponse},
+    conda_metadata::{CondaMetadataRequest, CondaMetadataResponse},
+    config::VersionSource,
+    describe,
+    dynamic_version::package_version,
+    error::{
+        BuildBackendError, MANIFEST_NOT_FOUND_ERROR_CODE, REQUEST_CANCELLED_ERROR_CODE,
+        UNKNOWN_ERROR_CODE, UNSUPPORTED_PLATFORM_ERROR_CODE,
+    },
+    manifest_ext::{load_manifest, ManifestExt},
+    protocol::{Protocol, ProtocolFactory},
+    server::{RequestHandler, Server, CANCEL_REQUEST_METHOD_NAME},
+};
+use pixi_build_types::{
+    procedures::{
+        self,
+        conda_build::{CondaBuildResult, CondaBuiltPackage},
+        conda_metadata::{CondaMetadataResult, CondaPackageMetadata},
+        initialize::{InitializeParams, InitializeResult},
+    },
+    BackendCapabilities,
+};
+use pixi_manifest::Manifest;
+use rattler_conda_types::{NoArchType, Platform};
+use tokio_util::sync::CancellationToken;
+
+/// Initializes a [`FixtureProtocol`] for a manifest.
+#[derive(Default)]
+struct FixtureFactory {
+    /// How long a build of the protocols takes.
+    build_delay: Duration,
+}
+
+/// Returns the metadata of the package in the manifest, and "builds" it by
+/// writing an empty package file after `build_delay`.
+struct FixtureProtocol {
+    manifest: Manifest,
+    build_delay: Duration,
+}
+
+impl FixtureProtocol {
+    /// Returns the host platform of a request, which defaults to the current
+    /// platform.
+    fn host_platform(&self, host_platform: Option<Platform>) -> miette::Result<Platform> {
+        let host_platform = host_platform.unwrap_or_else(Platform::current);
+        if !self.manifest.supports_target_platform(host_platform) {
+            return Err(BuildBackendError::UnsupportedPlatform(host_platform).into());
+        }
+        Ok(host_platform)
+    }
+}
+
+#[async_trait::async_trait]
+impl ProtocolFactory for FixtureFactory {
+    type Protocol = FixtureProtocol;
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities {
+            provides_conda_metadata: Some(true),
+            provides_conda_build: Some(true),
+        }
+    }
+
+    async fn initialize(
+        &self,
+        params: InitializeParams,
+    ) -> miette::Result<(Self::Protocol, InitializeResult)> {
+        let manifest = load_manifest(&params.manifest_path)?;
+        Ok((
+            FixtureProtocol {
+                manifest,
+                build_delay: self.build_delay,
+            },
+            InitializeResult {
+                capabilities: self.capabilities(),
+            },
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl Protocol for FixtureProtocol {
+    async fn get_conda_metadata(
+        &self,
+        request: CondaMetadataRequest,
+        _cancellation: CancellationToken,
+    ) -> miette::Result<CondaMetadataResponse> {
+        let params = request.params;
+        let host_platform = self.host_platform(params.host_platform.map(|p| p.platform))?;
+        Ok(CondaMetadataResponse {
+            result: CondaMetadataResult {
+                packages: vec![CondaPackageMetadata {
+                    name: self.manifest.package_name()?,
+                    version: package_version(&self.manifest, VersionSource::Manifest)?.into(),
+                    build: "h0_0".to_string(),
+                    build_number: 0,
+                    subdir: host_platform,
+                    depends: Vec::new(),
+                    constraints: Vec::new(),
+                    license: None,
+                    license_family: None,
+                    noarch: NoArchType::none(),
+                }],
+                input_globs: None,
+            },
+            channels: params.channel_base_urls.unwrap_or_default(),
+            dependencies_resolved: !request.no_deps_metadata,
+        })
+    }
+
+    async fn build_conda(
+        &self,
+        request: CondaBuildRequest,
+        cancellation: CancellationToken,
+    ) -> miette::Result<CondaBuildResponse> {
+        let params = request.params;
+        let host_platform = self.host_platform(params.host_platform.map(|p| p.platform))?;
+        tokio::select! {
+            () = tokio::time::sleep(self.build_delay) => {}
+            () = cancellation.cancelled() => return Err(BuildBackendError::Cancelled.into()),
+        }
+        let name = self.manifest.package_name()?;
+        let version = package_version(&self.manifest, VersionSource::Manifest)?;
+        let output_file = params
+            .work_directory
+            .join(format!("{}-{version}-h0_0.conda", name.as_normalized()));
+        std::fs::create_dir_all(&params.work_directory).unwrap();
+        std::fs::write(&output_file, b"").unwrap();
+        Ok(CondaBuildResponse {
+            result: CondaBuildResult {
+                packages: vec![CondaBuiltPackage {
+                    output_file,
+                    input_globs: vec!["**/*".to_string()],
+                    name: name.as_normalized().to_string(),
+                    version: version.to_string(),
+                    build: "h0_0".to_string(),
+                    subdir: host_platform.to_string(),
+                }],
+            },
+            sboms: Vec::new(),
+            log_file: None,
+        })
+    }
+}
+
+/// Returns the path of the fixture manifest of the given backend.
+fn fixture(backend: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(backend)
+        .join("pixi.toml")
+}
+
+/// Sends a request with the given id, method and parameters to `handler` and
+/// returns the parsed response.
+async fn call(handler: &RequestHandler, id: u64, method: &str, params: Value) -> Value {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    });
+    let response = handler
+        .handle(&request.to_string())
+        .await
+        .expect("a request has a response");
+    serde_json::from_str(&response).unwrap()
+}
+
+/// Initializes a new server for the manifest at `manifest_path`.
+async fn initialize(manifest_path: &Path) -> (RequestHandler, Value) {
+    let handler = Server::new(FixtureFactory::default()).into_handler();
+    let response = call(
+        &handler,
+        1,
+        procedures::initialize::METHOD_NAME,
+        json!({
+            "manifestPath": manifest_path,
+            "capabilities": {},
+        }),
+    )
+    .await;
+    (handler, response)
+}
+
+#[tokio::test]
+async fn initialize_get_metadata_and_build_the_fixtures() {
+    for (backend, name, version) in [
+        ("python", "python-fixture", "0.1.0"),
+        ("cmake", "cmake-fixture", "1.2.3"),
+    ] {
+        let (handler, response) = initialize(&fixture(backend)).await;
+        assert_eq!(
+            response["result"]["capabilities"]["providesCondaBuild"],
+            json!(true),
+            "{backend}: {response}"
+        );
+
+        let response = call(
+            &handler,
+            2,
+            procedures::conda_metadata::METHOD_NAME,
+            json!({
+                "hostPlatform": { "platform": "linux-64" },
+                "channelBaseUrls": ["https://prefix.dev/conda-forge"],
+                "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+                "workDirectory": "work",
+                "noDepsMetadata": true,
+            }),
+        )
+        .await;
+        let result = &response["result"];
+        assert_eq!(result["packages"][0]["name"], json!(name), "{backend}");
+        assert_eq!(
+            result["packages"][0]["version"],
+            json!(version),
+            "{backend}"
+        );
+        assert_eq!(
+            result["packages"][0]["subdir"],
+            json!("linux-64"),
+            "{backend}"
+        );
+        assert_eq!(result["dependenciesResolved"], json!(false), "{backend}");
+        assert_eq!(
+            result["channels"],
+            json!(["https://prefix.dev/conda-forge"]),
+            "{backend}"
+        );
+
+        let work_directory = tempfile::tempdir().unwrap();
+        let response = call(
+            &handler,
+            3,
+            procedures::conda_build::METHOD_NAME,
+            json!({
+                "hostPlatform": { "platform": "linux-64" },
+                "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+                "workDirectory": work_directory.path(),
+            }),
+        )
+        .await;
+        let package = &response["result"]["packages"][0];
+        assert_eq!(package["name"], json!(name), "{backend}: {response}");
+        assert_eq!(package["subdir"], json!("linux-64"), "{backend}");
+        let output_file = package["outputFile"].as_str().unwrap();
+        assert!(Path::new(output_file).is_file(), "{backend}");
+        assert!(
+            response["result"].get("sboms").is_none(),
+            "{backend}: {response}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn invalid_requests_are_rejected() {
+    let handler = Server::new(FixtureFactory::default()).into_handler();
+
+    // Malformed JSON.
+    let response: Value =
+        serde_json::from_str(&handler.handle("{").await.expect("a parse error")).unwrap();
+    assert_eq!(response["error"]["code"], json!(-32700));
+
+    // A request before the backend was initialized.
+    let metadata_params = json!({
+        "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+        "workDirectory": "work",
+    });
+    let response = call(
+        &handler,
+        1,
+        procedures::conda_metadata::METHOD_NAME,
+        metadata_params.clone(),
+    )
+    .await;
+    assert_eq!(response["error"]["code"], json!(-32600), "{response}");
+
+    // An unknown method.
+    let response = call(&handler, 2, "conda/unknown", json!({})).await;
+    assert_eq!(response["error"]["code"], json!(-32601), "{response}");
+
+    let (handler, _) = initialize(&fixture("cmake")).await;
+
+    // Parameters of the wrong type.
+    let response = call(
+        &handler,
+        3,
+        procedures::conda_metadata::METHOD_NAME,
+        json!({ "workDirectory": 3 }),
+    )
+    .await;
+    assert_eq!(response["error"]["code"], json!(-32602), "{response}");
+
+    // A backend error keeps its code.
+    let response = call(
+        &handler,
+        4,
+        procedures::conda_build::METHOD_NAME,
+        json!({
+            "hostPlatform": { "platform": "linux-ppc64le" },
+            "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+            "workDirectory": "work",
+        }),
+    )
+    .await;
+    assert_eq!(
+        response["error"]["code"],
+        json!(UNSUPPORTED_PLATFORM_ERROR_CODE),
+        "{response}"
+    );
+    assert!(response["error"]["data"].is_object(), "{response}");
+}
+
+#[tokio::test]
+async fn initialize_reports_a_missing_manifest() {
+    let directory = tempfile::tempdir().unwrap();
+    let (_, response) = initialize(&directory.path().join("pixi.toml")).await;
+    assert_eq!(
+        response["error"]["code"],
+        json!(MANIFEST_NOT_FOUND_ERROR_CODE),
+        "{response}"
+    );
+}
+
+#[tokio::test]
+async fn methods_that_are_not_implemented_are_not_supported() {
+    let (handler, _) = initialize(&fixture("cmake")).await;
+    let response = call(
+        &handler,
+        2,
+        describe::METHOD_NAME,
+        json!({
+            "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+        }),
+    )
+    .await;
+    assert_eq!(
+        response["error"]["code"],
+        json!(UNKNOWN_ERROR_CODE),
+        "{response}"
+    );
+    assert!(
+        response["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("does not support `describe`"),
+        "{response}"
+    );
+}
+
+#[tokio::test]
+async fn a_cancelled_build_stops() {
+    let handler = Server::new(FixtureFactory {
+        build_delay: Duration::from_secs(60),
+    })
+    .into_handler();
+    let response = call(
+        &handler,
+        1,
+        procedures::initialize::METHOD_NAME,
+        json!({
+            "manifestPath": fixture("cmake"),
+            "capabilities": {},
+        }),
+    )
+    .await;
+    assert!(response.get("result").is_some(), "{response}");
+
+    let work_directory = tempfile::tempdir().unwrap();
+    let build = call(
+        &handler,
+        2,
+        procedures::conda_build::METHOD_NAME,
+        json!({
+            "hostPlatform": { "platform": "linux-64" },
+            "channelConfiguration": { "baseUrl": "https://prefix.dev" },
+            "workDirectory": work_directory.path(),
+        }),
+    );
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": CANCEL_REQUEST_METHOD_NAME,
+            "params": { "id": 2 },
+        });
+        handler.handle(&notification.to_string()).await
+    };
+
+    let start = Instant::now();
+    let (response, cancel_response) = tokio::join!(build, cancel);
+    assert!(cancel_response.is_none());
+    assert_eq!(
+        response["error"]["code"],
+        json!(REQUEST_CANCELLED_ERROR_CODE),
+        "{response}"
+    );
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert_eq!(std::fs::read_dir(work_directory.path()).unwrap().count(), 0);
+}